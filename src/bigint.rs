@@ -0,0 +1,715 @@
+use std::cmp::Ordering;
+use std::convert::Infallible;
+use std::fmt;
+
+// Arbitrary precision integer stored as a sign and a magnitude of base 2^32 digits, least
+// significant first. The magnitude never has leading zero digits and zero is never negative, so
+// equal numbers always have equal representations
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    mag: Vec<u32>,
+}
+
+const BASE: u64 = 1 << 32;
+
+// Long loops report the digit operations they have done to a tick, which stops them by returning
+// an error. That lets a caller bound the time spent in a single operation
+pub type Tick<'a, E> = &'a mut dyn FnMut(u64) -> Result<(), E>;
+
+pub(crate) fn unmetered<T>(f: impl FnOnce(Tick<Infallible>) -> Result<T, Infallible>) -> T {
+    match f(&mut |_| Ok(())) {
+        Ok(x) => x,
+        Err(never) => match never {},
+    }
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        BigInt {
+            negative: false,
+            mag: vec![],
+        }
+    }
+
+    pub fn from_i64(x: i64) -> Self {
+        let abs = x.unsigned_abs();
+        BigInt::from_mag(x < 0, vec![abs as u32, (abs >> 32) as u32])
+    }
+
+    fn from_mag(negative: bool, mut mag: Vec<u32>) -> Self {
+        while mag.last() == Some(&0) {
+            mag.pop();
+        }
+        BigInt {
+            negative: negative && !mag.is_empty(),
+            mag,
+        }
+    }
+
+    // None when the value does not fit in an i64
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.mag.len() > 2 {
+            return None;
+        }
+        let abs = self
+            .mag
+            .iter()
+            .rev()
+            .fold(0u64, |acc, x| (acc << 32) | *x as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(abs)
+        } else {
+            i64::try_from(abs).ok()
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_odd(&self) -> bool {
+        self.mag.first().is_some_and(|x| x & 1 == 1)
+    }
+
+    pub fn neg(&self) -> Self {
+        BigInt::from_mag(!self.negative, self.mag.clone())
+    }
+
+    pub fn abs(&self) -> Self {
+        BigInt::from_mag(false, self.mag.clone())
+    }
+
+    pub fn add(&self, other: &BigInt) -> Self {
+        if self.negative == other.negative {
+            return BigInt::from_mag(self.negative, mag_add(&self.mag, &other.mag));
+        }
+        // the signs differ, so the result takes the sign of the larger magnitude
+        match mag_cmp(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::from_mag(other.negative, mag_sub(&other.mag, &self.mag)),
+            _ => BigInt::from_mag(self.negative, mag_sub(&self.mag, &other.mag)),
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> Self {
+        unmetered(|tick| self.mul_metered(other, tick))
+    }
+
+    pub fn mul_metered<E>(&self, other: &BigInt, tick: Tick<E>) -> Result<Self, E> {
+        Ok(BigInt::from_mag(
+            self.negative != other.negative,
+            mag_mul(&self.mag, &other.mag, tick)?,
+        ))
+    }
+
+    // Quotient rounded towards zero and the remainder with the sign of self. None when dividing
+    // by zero
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        unmetered(|tick| self.div_rem_metered(other, tick))
+    }
+
+    pub fn div_rem_metered<E>(
+        &self,
+        other: &BigInt,
+        tick: Tick<E>,
+    ) -> Result<Option<(BigInt, BigInt)>, E> {
+        if other.is_zero() {
+            return Ok(None);
+        }
+        let (q, r) = mag_div_rem(&self.mag, &other.mag, tick)?;
+        Ok(Some((
+            BigInt::from_mag(self.negative != other.negative, q),
+            BigInt::from_mag(self.negative, r),
+        )))
+    }
+
+    // Number of bits in the magnitude, 0 for zero
+    pub fn bits(&self) -> u64 {
+        match self.mag.last() {
+            Some(top) => self.mag.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    // Multiplies by 2^n
+    pub fn shl(&self, n: u64) -> Self {
+        let mut mag = vec![0u32; (n / 32) as usize];
+        mag.extend(shl_bits(&self.mag, (n % 32) as u32));
+        BigInt::from_mag(self.negative, mag)
+    }
+
+    // Divides by 2^n, rounding towards zero
+    pub fn shr(&self, n: u64) -> Self {
+        let words = (n / 32) as usize;
+        if words >= self.mag.len() {
+            return BigInt::zero();
+        }
+        BigInt::from_mag(self.negative, shr_bits(&self.mag[words..], (n % 32) as u32))
+    }
+
+    // Greatest common divisor, never negative
+    pub fn gcd(&self, other: &BigInt) -> Self {
+        unmetered(|tick| self.gcd_metered(other, tick))
+    }
+
+    pub fn gcd_metered<E>(&self, other: &BigInt, tick: Tick<E>) -> Result<Self, E> {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while let Some((_, r)) = a.div_rem_metered(&b, tick)? {
+            a = b;
+            b = r;
+        }
+        Ok(a)
+    }
+
+    // The bitwise operations act on the infinite two's complement form, so negative numbers have
+    // infinitely many leading ones
+    pub fn and(&self, other: &BigInt) -> Self {
+        self.bitwise(other, |x, y| x & y)
+    }
+
+    pub fn or(&self, other: &BigInt) -> Self {
+        self.bitwise(other, |x, y| x | y)
+    }
+
+    pub fn xor(&self, other: &BigInt) -> Self {
+        self.bitwise(other, |x, y| x ^ y)
+    }
+
+    pub fn not(&self) -> Self {
+        self.neg().sub(&BigInt::from_i64(1))
+    }
+
+    // Number of one bits in the magnitude
+    pub fn count_ones(&self) -> u64 {
+        self.mag.iter().map(|x| x.count_ones() as u64).sum()
+    }
+
+    fn bitwise(&self, other: &BigInt, op: fn(u32, u32) -> u32) -> Self {
+        // one more digit than either magnitude always leaves room for the sign
+        let len = self.mag.len().max(other.mag.len()) + 1;
+        let (a, b) = (self.twos_complement(len), other.twos_complement(len));
+        let digits = a.iter().zip(&b).map(|(x, y)| op(*x, *y)).collect();
+        BigInt::from_twos_complement(digits)
+    }
+
+    fn twos_complement(&self, len: usize) -> Vec<u32> {
+        let mut digits = self.mag.clone();
+        digits.resize(len, 0);
+        if self.negative {
+            negate_digits(&mut digits);
+        }
+        digits
+    }
+
+    fn from_twos_complement(mut digits: Vec<u32>) -> Self {
+        let negative = digits.last().is_some_and(|x| x >> 31 == 1);
+        if negative {
+            negate_digits(&mut digits);
+        }
+        BigInt::from_mag(negative, digits)
+    }
+
+    // Nearest double, infinite when the value is out of range
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    // Reads an optionally signed run of digits in the given radix, from 2 to 36
+    pub fn parse(text: &str, radix: u32) -> Option<Self> {
+        let (negative, digits) = match text.as_bytes().first()? {
+            b'-' => (true, &text[1..]),
+            b'+' => (false, &text[1..]),
+            _ => (false, text),
+        };
+        if digits.is_empty() {
+            return None;
+        }
+        let mut mag = Vec::<u32>::new();
+        for ch in digits.chars() {
+            let digit = ch.to_digit(radix)?;
+            mag_mul_small_add(&mut mag, radix, digit);
+        }
+        Some(BigInt::from_mag(negative, mag))
+    }
+
+    pub fn to_string_radix(&self, radix: u32) -> String {
+        unmetered(|tick| self.to_string_radix_metered(radix, tick))
+    }
+
+    // Divides by the largest power of the radix that fits in a digit, so each division gives
+    // several output digits at once
+    pub fn to_string_radix_metered<E>(&self, radix: u32, tick: Tick<E>) -> Result<String, E> {
+        if self.is_zero() {
+            return Ok("0".to_owned());
+        }
+        let (mut chunk, mut width) = (radix, 1);
+        while let Some(next) = chunk.checked_mul(radix) {
+            chunk = next;
+            width += 1;
+        }
+        let mut digits = Vec::<char>::new();
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            tick(mag.len() as u64)?;
+            let mut rem = mag_div_small(&mut mag, chunk);
+            // every chunk but the most significant one is padded with zeros
+            for _ in 0..width {
+                if mag.is_empty() && rem == 0 {
+                    break;
+                }
+                digits.extend(char::from_digit(rem % radix, radix));
+                rem /= radix;
+            }
+        }
+        if self.negative {
+            digits.push('-');
+        }
+        Ok(digits.iter().rev().collect())
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => mag_cmp(&self.mag, &other.mag),
+            (true, true) => mag_cmp(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_string_radix(10))
+    }
+}
+
+fn mag_cmp(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn mag_add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::<u32>::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, x) in long.iter().enumerate() {
+        let t = *x as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(t as u32);
+        carry = t >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+// a - b where a is at least as large as b
+fn mag_sub(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut diff = Vec::<u32>::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, x) in a.iter().enumerate() {
+        let t = *x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        diff.push(t.rem_euclid(BASE as i64) as u32);
+        borrow = i64::from(t < 0);
+    }
+    diff
+}
+
+// Below this many digits in the shorter operand, long multiplication is faster than splitting
+const KARATSUBA_DIGITS: usize = 32;
+
+// Karatsuba's method: with a = a1 B + a0 and b = b1 B + b0, the middle term a1 b0 + a0 b1 is
+// (a0 + a1)(b0 + b1) - a0 b0 - a1 b1, so three half size products replace four
+fn mag_mul<E>(a: &[u32], b: &[u32], tick: Tick<E>) -> Result<Vec<u32>, E> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    if b.len() < KARATSUBA_DIGITS {
+        return long_mul(a, b, tick);
+    }
+    // two spare digits hold the carries of the partial sums, from_mag drops them again
+    let mut product = vec![0u32; a.len() + b.len() + 2];
+    let half = a.len().div_ceil(2);
+    let (a0, a1) = a.split_at(half);
+    if b.len() <= half {
+        add_at(&mut product, &mag_mul(a0, b, tick)?, 0);
+        add_at(&mut product, &mag_mul(a1, b, tick)?, half);
+        return Ok(product);
+    }
+    let (b0, b1) = b.split_at(half);
+    let low = trimmed(mag_mul(a0, b0, tick)?);
+    let high = trimmed(mag_mul(a1, b1, tick)?);
+    let cross = trimmed(mag_mul(&mag_add(a0, a1), &mag_add(b0, b1), tick)?);
+    let middle = mag_sub(&trimmed(mag_sub(&cross, &low)), &high);
+    add_at(&mut product, &low, 0);
+    add_at(&mut product, &middle, half);
+    add_at(&mut product, &high, 2 * half);
+    Ok(product)
+}
+
+fn long_mul<E>(a: &[u32], b: &[u32], tick: Tick<E>) -> Result<Vec<u32>, E> {
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        tick(b.len() as u64)?;
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let t = *x as u64 * *y as u64 + product[i + j] as u64 + carry;
+            product[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    Ok(product)
+}
+
+// acc += x * BASE^offset, where acc has room for the sum
+fn add_at(acc: &mut [u32], x: &[u32], offset: usize) {
+    let mut carry = 0u64;
+    for (i, digit) in acc[offset..].iter_mut().enumerate() {
+        if i >= x.len() && carry == 0 {
+            break;
+        }
+        let t = *digit as u64 + *x.get(i).unwrap_or(&0) as u64 + carry;
+        *digit = t as u32;
+        carry = t >> 32;
+    }
+}
+
+fn trimmed(mut mag: Vec<u32>) -> Vec<u32> {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+    mag
+}
+
+// mag = mag * factor + digit
+fn mag_mul_small_add(mag: &mut Vec<u32>, factor: u32, digit: u32) {
+    let mut carry = digit as u64;
+    for x in mag.iter_mut() {
+        let t = *x as u64 * factor as u64 + carry;
+        *x = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        mag.push(carry as u32);
+    }
+}
+
+// Divides mag in place and returns the remainder
+fn mag_div_small(mag: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut rem = 0u64;
+    for x in mag.iter_mut().rev() {
+        let t = (rem << 32) | *x as u64;
+        *x = (t / divisor as u64) as u32;
+        rem = t % divisor as u64;
+    }
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+    rem as u32
+}
+
+// Inverts the digits and adds one
+fn negate_digits(digits: &mut [u32]) {
+    let mut carry = 1u64;
+    for x in digits.iter_mut() {
+        let sum = (!*x) as u64 + carry;
+        *x = sum as u32;
+        carry = sum >> 32;
+    }
+}
+
+// Long division of magnitudes (Knuth's algorithm D). b is not zero
+fn mag_div_rem<E>(a: &[u32], b: &[u32], tick: Tick<E>) -> Result<(Vec<u32>, Vec<u32>), E> {
+    if mag_cmp(a, b) == Ordering::Less {
+        return Ok((vec![], a.to_vec()));
+    }
+    if b.len() == 1 {
+        tick(a.len() as u64)?;
+        let mut q = a.to_vec();
+        let r = mag_div_small(&mut q, b[0]);
+        return Ok((q, vec![r]));
+    }
+    // shift both so the top digit of the divisor has its high bit set, which keeps the estimated
+    // quotient digits at most two too large
+    let n = b.len();
+    let m = a.len() - n;
+    let shift = b[n - 1].leading_zeros();
+    let v = shl_bits(b, shift);
+    let mut u = shl_bits(a, shift);
+    u.resize(a.len() + 1, 0);
+    let mut q = vec![0u32; m + 1];
+    for j in (0..=m).rev() {
+        tick(n as u64)?;
+        let num = ((u[j + n] as u64) << 32) | u[j + n - 1] as u64;
+        let mut qhat = num / v[n - 1] as u64;
+        let mut rhat = num % v[n - 1] as u64;
+        while qhat >= BASE || qhat * v[n - 2] as u64 > ((rhat << 32) | u[j + n - 2] as u64) {
+            qhat -= 1;
+            rhat += v[n - 1] as u64;
+            if rhat >= BASE {
+                break;
+            }
+        }
+        // subtract qhat * v from the current window of u
+        let mut borrow = 0i64;
+        for i in 0..n {
+            let p = qhat * v[i] as u64;
+            let t = u[i + j] as i64 - borrow - (p & 0xffff_ffff) as i64;
+            u[i + j] = t as u32;
+            borrow = (p >> 32) as i64 - (t >> 32);
+        }
+        let t = u[j + n] as i64 - borrow;
+        u[j + n] = t as u32;
+        q[j] = qhat as u32;
+        // qhat was one too large, add v back
+        if t < 0 {
+            q[j] = q[j].wrapping_sub(1);
+            let mut carry = 0u64;
+            for i in 0..n {
+                let t = u[i + j] as u64 + v[i] as u64 + carry;
+                u[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            u[j + n] = u[j + n].wrapping_add(carry as u32);
+        }
+    }
+    u.truncate(n);
+    Ok((q, shr_bits(&u, shift)))
+}
+
+fn shl_bits(mag: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return mag.to_vec();
+    }
+    let mut out = Vec::<u32>::with_capacity(mag.len() + 1);
+    let mut carry = 0u32;
+    for x in mag {
+        out.push((x << shift) | carry);
+        carry = x >> (32 - shift);
+    }
+    if carry > 0 {
+        out.push(carry);
+    }
+    out
+}
+
+fn shr_bits(mag: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return mag.to_vec();
+    }
+    let mut out = vec![0u32; mag.len()];
+    for i in 0..mag.len() {
+        let high = mag.get(i + 1).map_or(0, |x| x << (32 - shift));
+        out[i] = (mag[i] >> shift) | high;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big_radix(text: &str, radix: u32) -> BigInt {
+        BigInt::parse(text, radix).expect("valid number")
+    }
+
+    fn big(text: &str) -> BigInt {
+        big_radix(text, 10)
+    }
+
+    #[test]
+    fn bigint_round_trips_text() {
+        for text in [
+            "0",
+            "7",
+            "-7",
+            "4294967296",
+            "-18446744073709551616",
+            "123456789012345678901234567890",
+            // whole chunks of zeros between the digits
+            "1000000000000000000000000000000000001",
+            "-100000000000000000000000000000000000000",
+        ] {
+            assert_eq!(big(text).to_string(), text);
+        }
+        assert_eq!(big("+15").to_string(), "15");
+        assert_eq!(big("-0").to_string(), "0");
+        assert_eq!(BigInt::parse("ff", 16), Some(BigInt::from_i64(255)));
+        assert_eq!(BigInt::from_i64(-255).to_string_radix(2), "-11111111");
+        let text = "zz0000000000000000001";
+        assert_eq!(big_radix(text, 36).to_string_radix(36), text);
+        let ones = "1".repeat(100);
+        assert_eq!(big_radix(&ones, 2).to_string_radix(2), ones);
+        assert_eq!(BigInt::parse("12a", 10), None);
+        assert_eq!(BigInt::parse("-", 10), None);
+    }
+
+    #[test]
+    fn bigint_i64_conversions() {
+        for x in [0, 1, -1, i64::MAX, i64::MIN, 1 << 40] {
+            assert_eq!(BigInt::from_i64(x).to_i64(), Some(x));
+        }
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-9223372036854775809").to_i64(), None);
+    }
+
+    #[test]
+    fn bigint_arithmetic() {
+        let a = big("99999999999");
+        assert_eq!(a.mul(&a).to_string(), "9999999999800000000001");
+        let b = big("-123456789012345678901234567890");
+        assert_eq!(
+            b.add(&big("123456789012345678901234567891")),
+            BigInt::from_i64(1)
+        );
+        assert_eq!(b.sub(&b), BigInt::zero());
+        assert_eq!(
+            BigInt::from_i64(i64::MIN).neg().to_string(),
+            "9223372036854775808"
+        );
+        assert!(b < a && a > BigInt::zero() && big("-5") > big("-6"));
+    }
+
+    #[test]
+    fn bigint_division() {
+        let a = big("1000000000000000000000000000007");
+        let b = big("-33333333333333333");
+        let (q, r) = a.div_rem(&b).expect("nonzero divisor");
+        assert_eq!(q.mul(&b).add(&r), a);
+        assert_eq!(q.to_string(), "-30000000000000");
+        assert_eq!(r.to_string(), "10000000000007");
+        let (q, r) = big("-7").div_rem(&big("2")).expect("nonzero divisor");
+        assert_eq!((q.to_i64(), r.to_i64()), (Some(-3), Some(-1)));
+        assert_eq!(a.div_rem(&BigInt::zero()), None);
+        // divisors whose estimated quotient digit needs correcting
+        let x = big("340282366920938463463374607431768211455");
+        let y = big("18446744073709551617");
+        let (q, r) = x.div_rem(&y).expect("nonzero divisor");
+        assert_eq!(q.to_string(), "18446744073709551615");
+        assert!(r.is_zero());
+    }
+
+    #[test]
+    fn bigint_matches_i128() {
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for _ in 0..2000 {
+            // operands of varied lengths so every digit count is exercised
+            let a = ((next() as i128) << 64 | next() as i128) >> (next() % 120);
+            let b = ((next() as i128) << 64 | next() as i128) >> (next() % 126);
+            let (x, y) = (big(&a.to_string()), big(&b.to_string()));
+            assert_eq!(x.add(&y).to_string(), (a.wrapping_add(b)).to_string());
+            assert_eq!(x.sub(&y).to_string(), (a.wrapping_sub(b)).to_string());
+            if let Some(p) = a.checked_mul(b) {
+                assert_eq!(x.mul(&y).to_string(), p.to_string());
+            }
+            if b != 0 {
+                let (q, r) = x.div_rem(&y).expect("nonzero divisor");
+                assert_eq!(
+                    (q.to_string(), r.to_string()),
+                    ((a / b).to_string(), (a % b).to_string())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bigint_long_products() {
+        // large enough for several levels of Karatsuba splitting, checked against long
+        // multiplication
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut digits = |len: usize| -> Vec<u32> {
+            (0..len)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    seed as u32
+                })
+                .collect()
+        };
+        for (m, n) in [
+            (40, 40),
+            (100, 33),
+            (257, 64),
+            (300, 299),
+            (1000, 31),
+            (513, 500),
+        ] {
+            let (a, b) = (digits(m), digits(n));
+            let fast = unmetered(|tick| mag_mul(&a, &b, tick));
+            let slow = unmetered(|tick| long_mul(&a, &b, tick));
+            assert_eq!(trimmed(fast), trimmed(slow));
+        }
+        let all_ones = vec![u32::MAX; 200];
+        let square = unmetered(|tick| mag_mul(&all_ones, &all_ones, tick));
+        let expected = unmetered(|tick| long_mul(&all_ones, &all_ones, tick));
+        assert_eq!(trimmed(square), trimmed(expected));
+    }
+
+    #[test]
+    fn bigint_shifts_and_gcd() {
+        assert_eq!(big("3").shl(100), big("3802951800684688204490109616128"));
+        assert_eq!(big("-3802951800684688204490109616128").shr(99), big("-6"));
+        assert_eq!(big("5").shr(64), BigInt::zero());
+        assert_eq!(big("3802951800684688204490109616128").bits(), 102);
+        assert_eq!(big("-12").gcd(&big("18")), big("6"));
+        assert_eq!(big("0").gcd(&big("-7")), big("7"));
+        assert_eq!(
+            big("123456789012345678901234567890").to_f64(),
+            1.2345678901234568e29
+        );
+    }
+
+    #[test]
+    fn bigint_bitwise_matches_i64() {
+        let values = [
+            0i64,
+            1,
+            -1,
+            5,
+            -6,
+            255,
+            -256,
+            i64::MAX,
+            i64::MIN,
+            0x5555_5555_5555,
+        ];
+        for x in values {
+            for y in values {
+                let (a, b) = (BigInt::from_i64(x), BigInt::from_i64(y));
+                assert_eq!(a.and(&b), BigInt::from_i64(x & y));
+                assert_eq!(a.or(&b), BigInt::from_i64(x | y));
+                assert_eq!(a.xor(&b), BigInt::from_i64(x ^ y));
+            }
+            assert_eq!(BigInt::from_i64(x).not(), BigInt::from_i64(!x));
+        }
+        assert_eq!(
+            big("-18446744073709551616").and(&big("-1")),
+            big("-18446744073709551616")
+        );
+        assert_eq!(
+            big("-18446744073709551617").and(&big("18446744073709551615")),
+            big("18446744073709551615")
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use crate::bigint::BigInt;
+use crate::error::EvalError;
+use crate::eval::{fixed_args, Environment};
+use crate::number::{integer, to_bigint};
+use crate::token::{Builtin, LispVal};
+
+// Bitwise operations on exact integers from SRFI 151. Integers act as infinite strings of bits in
+// two's complement, so negative numbers have infinitely many leading ones
+
+pub const BITWISE_BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "bitwise-and",
+        func: eval_bitwise_and,
+    },
+    Builtin {
+        name: "bitwise-ior",
+        func: eval_bitwise_ior,
+    },
+    Builtin {
+        name: "bitwise-xor",
+        func: eval_bitwise_xor,
+    },
+    Builtin {
+        name: "bitwise-not",
+        func: eval_bitwise_not,
+    },
+    Builtin {
+        name: "arithmetic-shift",
+        func: eval_arithmetic_shift,
+    },
+    Builtin {
+        name: "bit-count",
+        func: eval_bit_count,
+    },
+    Builtin {
+        name: "integer-length",
+        func: eval_integer_length,
+    },
+    Builtin {
+        name: "bit-set?",
+        func: eval_is_bit_set,
+    },
+    Builtin {
+        name: "copy-bit",
+        func: eval_copy_bit,
+    },
+    Builtin {
+        name: "bit-field",
+        func: eval_bit_field,
+    },
+];
+
+fn exact_integer(val: &LispVal) -> Result<BigInt, EvalError> {
+    to_bigint(val).ok_or_else(|| EvalError::wrong_type("an exact integer", val))
+}
+
+// Bit positions and shift counts have to fit in a fixnum
+fn bit_index(val: &LispVal) -> Result<u64, EvalError> {
+    match val {
+        LispVal::Number(x) if *x >= 0 => Ok(*x as u64),
+        _ => Err(EvalError::wrong_type("a non-negative bit index", val)),
+    }
+}
+
+// Folds any number of integers, starting from the identity of the operation
+fn fold_bits(
+    list: Vec<LispVal>,
+    identity: i64,
+    fixnum: fn(i64, i64) -> i64,
+    op: fn(&BigInt, &BigInt) -> BigInt,
+) -> Result<LispVal, EvalError> {
+    let mut result = LispVal::Number(identity);
+    for x in &list {
+        result = match (&result, x) {
+            (LispVal::Number(a), LispVal::Number(b)) => LispVal::Number(fixnum(*a, *b)),
+            _ => integer(op(&exact_integer(&result)?, &exact_integer(x)?)),
+        };
+    }
+    Ok(result)
+}
+
+fn eval_bitwise_and(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    fold_bits(list, -1, |a, b| a & b, BigInt::and)
+}
+
+fn eval_bitwise_ior(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    fold_bits(list, 0, |a, b| a | b, BigInt::or)
+}
+
+fn eval_bitwise_xor(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    fold_bits(list, 0, |a, b| a ^ b, BigInt::xor)
+}
+
+fn eval_bitwise_not(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    match x {
+        LispVal::Number(x) => Ok(LispVal::Number(!x)),
+        _ => Ok(integer(exact_integer(&x)?.not())),
+    }
+}
+
+// Shifts left for a positive count and right for a negative one. Shifting right rounds down, so
+// the bits shifted out of a negative number are lost towards negative infinity
+fn shift(x: &BigInt, count: i64) -> BigInt {
+    if count >= 0 {
+        return x.shl(count as u64);
+    }
+    let n = count.unsigned_abs();
+    let shifted = x.shr(n);
+    if x.is_negative() && shifted.shl(n) != *x {
+        return shifted.sub(&BigInt::from_i64(1));
+    }
+    shifted
+}
+
+fn eval_arithmetic_shift(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x, count] = fixed_args(list)?;
+    let x = exact_integer(&x)?;
+    let count = match count {
+        LispVal::Number(count) => count,
+        _ => return Err(EvalError::wrong_type("a fixnum shift count", &count)),
+    };
+    if count > 0 {
+        env.allocate((count / 8) as usize)?;
+    }
+    Ok(integer(shift(&x, count)))
+}
+
+// Counts the one bits of a non-negative number and the zero bits of a negative one
+fn eval_bit_count(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    let x = exact_integer(&x)?;
+    let bits = if x.is_negative() { x.not() } else { x };
+    Ok(LispVal::Number(bits.count_ones() as i64))
+}
+
+// Bits needed to hold the number in two's complement, not counting the sign
+fn eval_integer_length(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    let x = exact_integer(&x)?;
+    let bits = if x.is_negative() { x.not() } else { x };
+    Ok(LispVal::Number(bits.bits() as i64))
+}
+
+// (bit-set? index i)
+fn eval_is_bit_set(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [index, x] = fixed_args(list)?;
+    let index = bit_index(&index)?;
+    Ok(LispVal::Bool(
+        shift(&exact_integer(&x)?, -(index as i64)).is_odd(),
+    ))
+}
+
+// (copy-bit index i boolean) gives i with the bit at index set to boolean
+fn eval_copy_bit(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [index, x, bit] = fixed_args(list)?;
+    let index = bit_index(&index)?;
+    let x = exact_integer(&x)?;
+    let bit = match bit {
+        LispVal::Bool(bit) => bit,
+        _ => return Err(EvalError::wrong_type("a boolean", &bit)),
+    };
+    env.allocate((index / 8) as usize)?;
+    let mask = BigInt::from_i64(1).shl(index);
+    Ok(integer(if bit { x.or(&mask) } else { x.and(&mask.not()) }))
+}
+
+// (bit-field i start end) gives the bits of i from start up to but not including end
+fn eval_bit_field(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x, start, end] = fixed_args(list)?;
+    let x = exact_integer(&x)?;
+    let (start, end) = (bit_index(&start)?, bit_index(&end)?);
+    if end < start {
+        return Err(EvalError::wrong_type(
+            "an end index after the start",
+            &LispVal::Number(end as i64),
+        ));
+    }
+    env.allocate(((end - start) / 8) as usize)?;
+    let mask = BigInt::from_i64(1)
+        .shl(end - start)
+        .sub(&BigInt::from_i64(1));
+    Ok(integer(shift(&x, -(start as i64)).and(&mask)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::show;
+
+    #[test]
+    fn bitwise_logic() {
+        assert_eq!(show("(bitwise-and 12 10)"), "8");
+        assert_eq!(show("(bitwise-ior 12 10)"), "14");
+        assert_eq!(show("(bitwise-xor 12 10)"), "6");
+        assert_eq!(show("(bitwise-and)"), "-1");
+        assert_eq!(show("(bitwise-ior 1 2 4 8)"), "15");
+        assert_eq!(
+            show("(bitwise-and -1 18446744073709551616)"),
+            "18446744073709551616"
+        );
+        assert_eq!(
+            show("(bitwise-xor -1 18446744073709551615)"),
+            "-18446744073709551616"
+        );
+        assert_eq!(show("(bitwise-not 0)"), "-1");
+        assert_eq!(
+            show("(bitwise-not -18446744073709551616)"),
+            "18446744073709551615"
+        );
+    }
+
+    #[test]
+    fn bitwise_shifts_and_fields() {
+        assert_eq!(show("(arithmetic-shift 1 70)"), "1180591620717411303424");
+        assert_eq!(show("(arithmetic-shift -5 -1)"), "-3");
+        assert_eq!(show("(arithmetic-shift -1180591620717411303424 -70)"), "-1");
+        assert_eq!(show("(arithmetic-shift 5 -100)"), "0");
+        assert_eq!(show("(bit-count 13)"), "3");
+        assert_eq!(show("(bit-count -13)"), "2");
+        assert_eq!(show("(integer-length 255)"), "8");
+        assert_eq!(show("(integer-length -256)"), "8");
+        assert_eq!(show("(integer-length 0)"), "0");
+        assert_eq!(show("(bit-set? 1 6)"), "#t");
+        assert_eq!(show("(bit-set? 0 6)"), "#f");
+        assert_eq!(show("(bit-set? 100 -1)"), "#t");
+        assert_eq!(show("(bit-set? 2 -4)"), "#t");
+        assert_eq!(show("(bit-set? 1 -4)"), "#f");
+        assert_eq!(show("(copy-bit 0 6 #t)"), "7");
+        assert_eq!(show("(copy-bit 64 0 #t)"), "18446744073709551616");
+        assert_eq!(show("(copy-bit 0 -1 #f)"), "-2");
+        assert_eq!(show("(bit-field 108 2 5)"), "3");
+        assert_eq!(show("(bit-field -1 60 70)"), "1023");
+        assert!(show("(bitwise-and 1.0 1)").starts_with("error: wrong type"));
+        assert!(show("(bit-set? -1 1)").starts_with("error: wrong type"));
+        // results too large to allocate fail even without a heap limit
+        for input in [
+            "(arithmetic-shift 1 100000000000)",
+            "(copy-bit 100000000000 0 #t)",
+            "(bit-field -1 0 100000000000)",
+            "(expt 2 100000000000)",
+        ] {
+            assert!(show(input).starts_with("error: allocation too large"));
+        }
+    }
+}
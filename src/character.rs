@@ -0,0 +1,374 @@
+use std::cmp::Ordering;
+
+use crate::error::EvalError;
+use crate::eval::{compare_chain, fixed_args, Environment};
+use crate::token::{Builtin, LispVal};
+
+// The R7RS character procedures. Case and class follow Unicode rather than ASCII
+
+pub const CHAR_BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "char?",
+        func: eval_is_char,
+    },
+    Builtin {
+        name: "char->integer",
+        func: eval_char_to_integer,
+    },
+    Builtin {
+        name: "integer->char",
+        func: eval_integer_to_char,
+    },
+    Builtin {
+        name: "char-upcase",
+        func: eval_char_upcase,
+    },
+    Builtin {
+        name: "char-downcase",
+        func: eval_char_downcase,
+    },
+    Builtin {
+        name: "char-foldcase",
+        func: eval_char_foldcase,
+    },
+    Builtin {
+        name: "char-alphabetic?",
+        func: eval_is_alphabetic,
+    },
+    Builtin {
+        name: "char-numeric?",
+        func: eval_is_numeric,
+    },
+    Builtin {
+        name: "char-whitespace?",
+        func: eval_is_whitespace,
+    },
+    Builtin {
+        name: "char-upper-case?",
+        func: eval_is_upper_case,
+    },
+    Builtin {
+        name: "char-lower-case?",
+        func: eval_is_lower_case,
+    },
+    Builtin {
+        name: "digit-value",
+        func: eval_digit_value,
+    },
+    Builtin {
+        name: "char=?",
+        func: eval_char_eq,
+    },
+    Builtin {
+        name: "char<?",
+        func: eval_char_less,
+    },
+    Builtin {
+        name: "char>?",
+        func: eval_char_greater,
+    },
+    Builtin {
+        name: "char<=?",
+        func: eval_char_less_eq,
+    },
+    Builtin {
+        name: "char>=?",
+        func: eval_char_greater_eq,
+    },
+    Builtin {
+        name: "char-ci=?",
+        func: eval_char_ci_eq,
+    },
+    Builtin {
+        name: "char-ci<?",
+        func: eval_char_ci_less,
+    },
+    Builtin {
+        name: "char-ci>?",
+        func: eval_char_ci_greater,
+    },
+    Builtin {
+        name: "char-ci<=?",
+        func: eval_char_ci_less_eq,
+    },
+    Builtin {
+        name: "char-ci>=?",
+        func: eval_char_ci_greater_eq,
+    },
+];
+
+// The first code point of every run of ten Unicode decimal digits (general category Nd)
+const DIGIT_ZEROS: &[u32] = &[
+    0x0030, 0x0660, 0x06F0, 0x07C0, 0x0966, 0x09E6, 0x0A66, 0x0AE6, 0x0B66, 0x0BE6, 0x0C66, 0x0CE6,
+    0x0D66, 0x0DE6, 0x0E50, 0x0ED0, 0x0F20, 0x1040, 0x1090, 0x17E0, 0x1810, 0x1946, 0x19D0, 0x1A80,
+    0x1A90, 0x1B50, 0x1BB0, 0x1C40, 0x1C50, 0xA620, 0xA8D0, 0xA900, 0xA9D0, 0xA9F0, 0xAA50, 0xABF0,
+    0xFF10, 0x104A0, 0x10D30, 0x11066, 0x110F0, 0x11136, 0x111D0, 0x112F0, 0x11450, 0x114D0,
+    0x11650, 0x116C0, 0x11730, 0x118E0, 0x11950, 0x11C50, 0x11D50, 0x11DA0, 0x11F50, 0x16A60,
+    0x16AC0, 0x16B50, 0x1D7CE, 0x1D7D8, 0x1D7E2, 0x1D7EC, 0x1D7F6, 0x1E140, 0x1E2F0, 0x1E4F0,
+    0x1E950, 0x1FBF0,
+];
+
+// Lower case letters that fold to another lower case letter, such as final sigma to sigma and
+// micro sign to mu
+const FOLDS: &[(char, char)] = &[
+    ('\u{00B5}', '\u{03BC}'),
+    ('\u{017F}', 's'),
+    ('\u{0345}', '\u{03B9}'),
+    ('\u{03C2}', '\u{03C3}'),
+    ('\u{03D0}', '\u{03B2}'),
+    ('\u{03D1}', '\u{03B8}'),
+    ('\u{03D5}', '\u{03C6}'),
+    ('\u{03D6}', '\u{03C0}'),
+    ('\u{03F0}', '\u{03BA}'),
+    ('\u{03F1}', '\u{03C1}'),
+    ('\u{03F5}', '\u{03B5}'),
+    ('\u{1C80}', '\u{0432}'),
+    ('\u{1C81}', '\u{0434}'),
+    ('\u{1C82}', '\u{043E}'),
+    ('\u{1C83}', '\u{0441}'),
+    ('\u{1C84}', '\u{0442}'),
+    ('\u{1C85}', '\u{0442}'),
+    ('\u{1C86}', '\u{044A}'),
+    ('\u{1C87}', '\u{0463}'),
+    ('\u{1C88}', '\u{A64B}'),
+    ('\u{1E9B}', '\u{1E61}'),
+    ('\u{1FBE}', '\u{03B9}'),
+];
+
+pub(crate) fn char_of(val: &LispVal) -> Result<char, EvalError> {
+    match val {
+        LispVal::Char(x) => Ok(*x),
+        _ => Err(EvalError::wrong_type("a character", val)),
+    }
+}
+
+// The value of a decimal digit in any script, None for every other char
+fn digit_value(ch: char) -> Option<u32> {
+    let code = ch as u32;
+    let run = DIGIT_ZEROS
+        .partition_point(|zero| *zero <= code)
+        .checked_sub(1)?;
+    let value = code - DIGIT_ZEROS[run];
+    (value < 10 && ch.is_numeric()).then_some(value)
+}
+
+// A char whose case mapping is more than one char, like ß to SS, maps to itself
+fn single(mut mapped: impl Iterator<Item = char>, ch: char) -> char {
+    match (mapped.next(), mapped.next()) {
+        (Some(x), None) => x,
+        _ => ch,
+    }
+}
+
+fn upcase(ch: char) -> char {
+    single(ch.to_uppercase(), ch)
+}
+
+fn downcase(ch: char) -> char {
+    single(ch.to_lowercase(), ch)
+}
+
+// Simple case folding from Unicode's CaseFolding.txt. It is the lower case mapping except for
+// Cherokee, which folds to upper case, and the chars in FOLDS
+pub(crate) fn foldcase(ch: char) -> char {
+    match ch {
+        '\u{13A0}'..='\u{13F5}' => ch,
+        '\u{13F8}'..='\u{13FD}' | '\u{AB70}'..='\u{ABBF}' => upcase(ch),
+        _ => match FOLDS.binary_search_by_key(&ch, |x| x.0) {
+            Ok(i) => FOLDS[i].1,
+            Err(_) => downcase(ch),
+        },
+    }
+}
+
+fn eval_is_char(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(matches!(x, LispVal::Char(_))))
+}
+
+fn eval_char_to_integer(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Number(char_of(&x)? as i64))
+}
+
+// Surrogates and numbers past 0x10FFFF are not chars
+fn eval_integer_to_char(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    let code = match x {
+        LispVal::Number(n) => u32::try_from(n).ok().and_then(char::from_u32),
+        _ => None,
+    };
+    code.map(LispVal::Char)
+        .ok_or_else(|| EvalError::wrong_type("a Unicode scalar value", &x))
+}
+
+fn map_char(list: Vec<LispVal>, f: fn(char) -> char) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Char(f(char_of(&x)?)))
+}
+
+fn eval_char_upcase(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    map_char(list, upcase)
+}
+
+fn eval_char_downcase(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    map_char(list, downcase)
+}
+
+fn eval_char_foldcase(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    map_char(list, foldcase)
+}
+
+fn test_char(list: Vec<LispVal>, f: fn(char) -> bool) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(f(char_of(&x)?)))
+}
+
+fn eval_is_alphabetic(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    test_char(list, char::is_alphabetic)
+}
+
+// Only decimal digits are numeric, so digit-value has a value for every numeric char
+fn eval_is_numeric(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    test_char(list, |ch| digit_value(ch).is_some())
+}
+
+fn eval_is_whitespace(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    test_char(list, char::is_whitespace)
+}
+
+fn eval_is_upper_case(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    test_char(list, char::is_uppercase)
+}
+
+fn eval_is_lower_case(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    test_char(list, char::is_lowercase)
+}
+
+fn eval_digit_value(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(match digit_value(char_of(&x)?) {
+        Some(value) => LispVal::Number(value as i64),
+        None => LispVal::Bool(false),
+    })
+}
+
+// Chars compare by code point, the -ci comparisons after folding case
+fn compare_chars(
+    list: Vec<LispVal>,
+    fold: fn(char) -> char,
+    accepts: fn(Ordering) -> bool,
+) -> Result<LispVal, EvalError> {
+    compare_chain(
+        &list,
+        |x| Ok(fold(char_of(x)?)),
+        |a, b| Ok(Some(a.cmp(b))),
+        accepts,
+    )
+}
+
+fn eval_char_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_chars(list, |ch| ch, Ordering::is_eq)
+}
+
+fn eval_char_less(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_chars(list, |ch| ch, Ordering::is_lt)
+}
+
+fn eval_char_greater(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_chars(list, |ch| ch, Ordering::is_gt)
+}
+
+fn eval_char_less_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_chars(list, |ch| ch, Ordering::is_le)
+}
+
+fn eval_char_greater_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_chars(list, |ch| ch, Ordering::is_ge)
+}
+
+fn eval_char_ci_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_chars(list, foldcase, Ordering::is_eq)
+}
+
+fn eval_char_ci_less(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_chars(list, foldcase, Ordering::is_lt)
+}
+
+fn eval_char_ci_greater(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_chars(list, foldcase, Ordering::is_gt)
+}
+
+fn eval_char_ci_less_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_chars(list, foldcase, Ordering::is_le)
+}
+
+fn eval_char_ci_greater_eq(
+    list: Vec<LispVal>,
+    _env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    compare_chars(list, foldcase, Ordering::is_ge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::show;
+
+    #[test]
+    fn digit_values_cover_every_script() {
+        assert_eq!(digit_value('7'), Some(7));
+        assert_eq!(digit_value('\u{0664}'), Some(4)); // Arabic-Indic four
+        assert_eq!(digit_value('\u{FF19}'), Some(9)); // fullwidth nine
+        assert_eq!(digit_value('\u{1D7D9}'), Some(1)); // mathematical double-struck one
+        assert_eq!(digit_value('a'), None);
+        assert_eq!(digit_value('\u{00BD}'), None); // vulgar fraction one half
+        assert_eq!(digit_value('\u{2167}'), None); // Roman numeral eight
+    }
+
+    #[test]
+    fn char_procedures() {
+        assert_eq!(show("(char? #\\a)"), "#t");
+        assert_eq!(show("(char? \"a\")"), "#f");
+        assert_eq!(show("(char->integer #\\λ)"), "955");
+        assert_eq!(show("(char=? (integer->char 955) #\\λ)"), "#t");
+        assert!(show("(integer->char 55296)").starts_with("error: wrong type"));
+        assert!(show("(integer->char 1114112)").starts_with("error: wrong type"));
+        assert_eq!(show("(char-upcase #\\ä)"), "Ä");
+        assert_eq!(show("(char-downcase #\\Σ)"), "σ");
+        assert_eq!(show("(char-upcase #\\ß)"), "ß");
+        assert_eq!(show("(char-foldcase #\\A)"), "a");
+        assert_eq!(show("(char-foldcase #\\µ)"), "μ");
+        assert_eq!(show("(char-foldcase #\\ς)"), "σ");
+        assert_eq!(show("(char-foldcase #\\ſ)"), "s");
+        assert_eq!(show("(char-foldcase #\\ꭰ)"), "Ꭰ");
+        assert_eq!(show("(char-foldcase #\\ß)"), "ß");
+        assert_eq!(show("(string-map char-upcase \"abc\")"), "ABC");
+        assert_eq!(show("(char-alphabetic? #\\λ)"), "#t");
+        assert_eq!(show("(char-alphabetic? #\\3)"), "#f");
+        assert_eq!(show("(char-numeric? #\\٣)"), "#t");
+        assert_eq!(show("(char-numeric? #\\½)"), "#f");
+        assert_eq!(show("(char-whitespace? #\\tab)"), "#t");
+        assert_eq!(show("(char-whitespace? #\\x3000)"), "#t");
+        assert_eq!(show("(char-upper-case? #\\Ä)"), "#t");
+        assert_eq!(show("(char-lower-case? #\\Ä)"), "#f");
+        assert_eq!(show("(digit-value #\\٣)"), "3");
+        assert_eq!(show("(digit-value #\\x)"), "#f");
+    }
+
+    #[test]
+    fn char_comparison() {
+        assert_eq!(show("(char<? #\\a #\\b #\\c)"), "#t");
+        assert_eq!(show("(char<? #\\a #\\c #\\b)"), "#f");
+        assert_eq!(show("(char>=? #\\b #\\b #\\a)"), "#t");
+        assert_eq!(show("(char=? #\\a #\\A)"), "#f");
+        assert_eq!(show("(char-ci=? #\\a #\\A)"), "#t");
+        assert_eq!(show("(char-ci<? #\\a #\\B)"), "#t");
+        assert_eq!(show("(char-ci=? #\\Σ #\\σ)"), "#t");
+        assert_eq!(show("(char-ci=? #\\ς #\\Σ #\\σ)"), "#t");
+        assert_eq!(show("(char-ci=? #\\µ #\\Μ)"), "#t");
+        assert_eq!(show("(char-ci=? #\\Ꭰ #\\ꭰ)"), "#t");
+        assert!(show("(char<? #\\a)").starts_with("error"));
+        assert!(show("(char<? #\\a \"b\")").starts_with("error: wrong type"));
+    }
+}
@@ -0,0 +1,229 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::symbol::Symbol;
+use crate::token::{LispVal, Location};
+
+// What went wrong while evaluating. Values are the ones that caused the error
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    UnboundVariable(Symbol),
+    Unassigned(Symbol), // Name bound by letrec or an internal define read before its init ran
+    WrongType {
+        expected: &'static str,
+        got: LispVal,
+    },
+    Arity {
+        min: usize,
+        max: Option<usize>, // None when a rest parameter takes any number of extra arguments
+        got: usize,
+    },
+    DivisionByZero,
+    NotApplicable(LispVal),
+    // Special form used with the wrong shape
+    Syntax {
+        message: String,
+        form: LispVal,
+    },
+    // Made by the error procedure
+    User {
+        message: String,
+        irritants: Vec<LispVal>,
+    },
+    Raised(LispVal),          // Object given to raise that nothing caught
+    HandlerReturned(LispVal), // Exception handler returned for an error that cannot continue
+    LimitExceeded(Limit),
+    AllocationTooLarge(usize), // Bytes asked for in one go, over the cap that holds without limits
+    Interrupted,               // Stopped through an InterruptHandle
+}
+
+// The resource limits an evaluation can run into
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Limit {
+    Fuel,
+    Depth,
+    Heap,
+    Time,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalError {
+    pub kind: ErrorKind,
+    pub location: Option<Location>, // Innermost form being evaluated when the error happened
+    pub backtrace: Vec<CallFrame>,  // Procedure calls active when it happened, outermost first
+}
+
+// One active procedure call. A call in tail position replaces the frame of its caller
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallFrame {
+    pub name: Option<String>, // None for anonymous procedures
+    pub call_site: Option<Location>,
+    pub tail_calls: usize, // Tail calls folded into this frame
+}
+
+impl EvalError {
+    pub fn wrong_type(expected: &'static str, got: &LispVal) -> Self {
+        ErrorKind::WrongType {
+            expected,
+            got: got.clone(),
+        }
+        .into()
+    }
+
+    pub fn arity(min: usize, max: Option<usize>, got: usize) -> Self {
+        ErrorKind::Arity { min, max, got }.into()
+    }
+
+    pub fn syntax(message: &str, form: &[LispVal]) -> Self {
+        ErrorKind::Syntax {
+            message: message.to_owned(),
+            form: LispVal::List(form.to_vec()),
+        }
+        .into()
+    }
+
+    // The backtrace innermost call first, with runs of the same call shown once
+    pub fn backtrace_report(&self) -> String {
+        let mut report = String::new();
+        let mut frames = self.backtrace.iter().rev().peekable();
+        while let Some(frame) = frames.next() {
+            let mut repeats = 0;
+            while frames.peek() == Some(&frame) {
+                frames.next();
+                repeats += 1;
+            }
+            report.push_str(&format!("  {}", frame));
+            if repeats > 0 {
+                report.push_str(&format!(" (repeated {} more times)", repeats));
+            }
+            report.push('\n');
+        }
+        report
+    }
+
+    // Keeps a location that was already found closer to the cause
+    pub fn or_at(mut self, location: Option<Location>) -> Self {
+        if self.location.is_none() {
+            self.location = location;
+        }
+        self
+    }
+}
+
+impl ErrorKind {
+    // Message of the error object a handler receives, the values involved are its irritants
+    pub fn message(&self) -> String {
+        match self {
+            ErrorKind::UnboundVariable(_) => "unbound variable".to_owned(),
+            ErrorKind::Unassigned(_) => "variable used before its definition".to_owned(),
+            ErrorKind::WrongType { expected, .. } => format!("wrong type: expected {}", expected),
+            ErrorKind::NotApplicable(_) => "not a procedure".to_owned(),
+            ErrorKind::Syntax { message, .. } | ErrorKind::User { message, .. } => message.clone(),
+            ErrorKind::Raised(_) => "uncaught exception".to_owned(),
+            ErrorKind::HandlerReturned(_) => "exception handler returned".to_owned(),
+            _ => self.to_string(),
+        }
+    }
+
+    pub fn irritants(&self) -> Vec<LispVal> {
+        match self {
+            ErrorKind::UnboundVariable(x) | ErrorKind::Unassigned(x) => {
+                vec![LispVal::Symbol(x.clone())]
+            }
+            ErrorKind::WrongType { got, .. } => vec![got.clone()],
+            ErrorKind::NotApplicable(x) | ErrorKind::Raised(x) | ErrorKind::HandlerReturned(x) => {
+                vec![x.clone()]
+            }
+            ErrorKind::Syntax { form, .. } => vec![form.clone()],
+            ErrorKind::User { irritants, .. } => irritants.clone(),
+            _ => vec![],
+        }
+    }
+}
+
+impl From<ErrorKind> for EvalError {
+    fn from(kind: ErrorKind) -> Self {
+        EvalError {
+            kind,
+            location: None,
+            backtrace: vec![],
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::UnboundVariable(x) => write!(f, "unbound variable: {}", x),
+            ErrorKind::Unassigned(x) => write!(f, "variable used before its definition: {}", x),
+            ErrorKind::WrongType { expected, got } => {
+                write!(f, "wrong type: expected {} but got {}", expected, show(got))
+            }
+            ErrorKind::Arity { min, max, got } => {
+                write!(f, "wrong number of arguments: expected ")?;
+                match max {
+                    Some(max) if max == min => write!(f, "{}", min)?,
+                    Some(max) => write!(f, "between {} and {}", min, max)?,
+                    None => write!(f, "at least {}", min)?,
+                }
+                write!(f, " but got {}", got)
+            }
+            ErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ErrorKind::NotApplicable(x) => write!(f, "not a procedure: {}", show(x)),
+            ErrorKind::Syntax { message, form } => {
+                write!(f, "{}. error in: {}", message, show(form))
+            }
+            ErrorKind::User { message, irritants } => {
+                write!(f, "{}", message)?;
+                for x in irritants {
+                    write!(f, " {}", show(x))?;
+                }
+                Ok(())
+            }
+            ErrorKind::Raised(x) => write!(f, "uncaught exception: {}", show(x)),
+            ErrorKind::HandlerReturned(x) => {
+                write!(f, "exception handler returned from {}", show(x))
+            }
+            ErrorKind::LimitExceeded(Limit::Fuel) => write!(f, "evaluation ran out of fuel"),
+            ErrorKind::LimitExceeded(Limit::Depth) => {
+                write!(f, "maximum evaluation depth exceeded")
+            }
+            ErrorKind::LimitExceeded(Limit::Heap) => write!(f, "heap allocation limit exceeded"),
+            ErrorKind::LimitExceeded(Limit::Time) => write!(f, "evaluation timed out"),
+            ErrorKind::AllocationTooLarge(x) => write!(f, "allocation too large: {} bytes", x),
+            ErrorKind::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(loc) => write!(f, "{} at {}", self.kind, loc),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+impl fmt::Display for CallFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "in {}",
+            self.name.as_deref().unwrap_or("anonymous procedure")
+        )?;
+        if let Some(loc) = self.call_site {
+            write!(f, " called at {}", loc)?;
+        }
+        if self.tail_calls > 0 {
+            write!(f, " after {} tail calls", self.tail_calls)?;
+        }
+        Ok(())
+    }
+}
+
+fn show(val: &LispVal) -> String {
+    val.show_val().trim_end().to_owned()
+}
@@ -1,686 +1,2645 @@
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem::{size_of, size_of_val};
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::bitwise::BITWISE_BUILTINS;
+use crate::character::CHAR_BUILTINS;
+use crate::error::{CallFrame, ErrorKind, EvalError, Limit};
+use crate::list::LIST_BUILTINS;
+use crate::number::NUMBER_BUILTINS;
+use crate::random::{RandomSource, RANDOM_BUILTINS};
+use crate::string::STRING_BUILTINS;
+use crate::symbol::{Symbol, SYMBOL_BUILTINS};
 use crate::{parser::Program, token::*};
 
-#[derive(PartialEq, Clone)]
+struct Frame {
+    store: RefCell<HashMap<Symbol, LispVal>>,
+    outer: Option<Rc<Frame>>,
+}
+
+const DEFAULT_MAX_DEPTH: usize = 1_000;
+
+// A nested evaluation takes anywhere from one to several KB of native stack depending on the
+// build and the procedures in between, so the stack is measured rather than estimated from the
+// depth. Half the 2MB a spawned thread gets leaves room for the frames between two checks
+const DEFAULT_MAX_STACK: usize = 1 << 20;
+
+// How often the clock is read when there is a timeout
+const CLOCK_STEPS: u64 = 256;
+
+// A single allocation this large is refused even without a heap limit, since asking for it would
+// more likely abort the process than succeed
+const MAX_ALLOCATION: usize = 1 << 28;
+
+// Digit operations of bignum arithmetic that count as one evaluation step
+const WORK_PER_STEP: u64 = 1 << 12;
+
+// Bounds on a single call to eval_prog, None means unlimited. Even without limits no single
+// allocation may be larger than MAX_ALLOCATION
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub fuel: Option<u64>,         // Evaluation steps
+    pub max_depth: Option<usize>,  // Evaluations nested inside each other
+    pub max_stack: Option<usize>,  // Bytes of native stack used by nested evaluations
+    pub max_heap: Option<usize>,   // Approximate bytes allocated for values, bindings and scopes
+    pub timeout: Option<Duration>, // Wall clock time
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            fuel: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_stack: Some(DEFAULT_MAX_STACK),
+            max_heap: None,
+            timeout: None,
+        }
+    }
+}
+
+// What the running call to eval_prog has used so far
+#[derive(Default)]
+struct Usage {
+    steps: Cell<u64>,
+    work: Cell<u64>, // Digit operations not yet counted as a step
+    depth: Cell<usize>,
+    stack_base: Cell<usize>, // Address of the outermost eval's frame
+    heap: Cell<usize>,
+    deadline: Cell<Option<Instant>>,
+}
+
+// Stops a running evaluation from another thread or a signal handler. The evaluation fails with
+// ErrorKind::Interrupted at its next step
+#[derive(Clone, Debug, Default)]
+pub struct InterruptHandle {
+    flag: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    // Drops an interrupt that no evaluation has seen yet
+    pub fn clear(&self) {
+        self.flag.store(false, Ordering::Relaxed);
+    }
+
+    // Reports an interrupt once
+    fn take(&self) -> bool {
+        self.flag.load(Ordering::Relaxed) && self.flag.swap(false, Ordering::Relaxed)
+    }
+}
+
+// State that follows the calls being made rather than the scopes. Every environment made from the
+// same global environment shares it
+#[derive(Default)]
+struct Dynamic {
+    handlers: RefCell<Vec<Option<LispVal>>>, // Installed exception handlers, None marks a guard
+    handler_failed: Cell<bool>, // Set while an error raised by a handler unwinds to where it was installed
+    calls: RefCell<Vec<CallFrame>>, // Procedure calls being evaluated, outermost first
+    calls_base: Cell<usize>,    // Number of calls made outside the running eval
+    limits: Cell<Limits>,
+    usage: Usage,
+    interrupt: InterruptHandle,
+    random: Rc<RandomSource>,          // Bound to default-random-source
+    frames: RefCell<Vec<Weak<Frame>>>, // Every frame made for this interpreter that may still exist
+}
+
+impl Dynamic {
+    fn add_frame(&self, frame: &Rc<Frame>) {
+        let mut frames = self.frames.borrow_mut();
+        // Dropped frames are only forgotten when the vector is full, so adding stays amortized O(1)
+        if frames.len() == frames.capacity() {
+            frames.retain(|x| x.strong_count() > 0);
+        }
+        frames.push(Rc::downgrade(frame));
+    }
+}
+
+// A closure binds into the frame it was made in and that frame may bind the closure, so frames
+// can keep each other alive. Once no environment of the interpreter is left nothing can evaluate
+// in them any more, and emptying them frees the cycles
+impl Drop for Dynamic {
+    fn drop(&mut self) {
+        for frame in self.frames.take() {
+            if let Some(frame) = frame.upgrade() {
+                let store = frame.store.take();
+                drop(frame);
+                drop(store);
+            }
+        }
+    }
+}
+
+thread_local! {
+    // The builtins by name. Interning the names once keeps every new global environment from
+    // adding them to the symbol table again
+    static GLOBALS: Vec<(Symbol, Builtin)> = [
+        BUILTINS,
+        NUMBER_BUILTINS,
+        BITWISE_BUILTINS,
+        RANDOM_BUILTINS,
+        LIST_BUILTINS,
+        SYMBOL_BUILTINS,
+        STRING_BUILTINS,
+        CHAR_BUILTINS,
+    ]
+    .into_iter()
+    .flatten()
+    .map(|x| (Symbol::intern(x.name), *x))
+    .collect();
+}
+
+// Frames are shared between every environment handle pointing at them, so a closure and the
+// scope that created it see the same bindings. Dropping the last environment of an interpreter
+// empties its frames, closures that outlive it no longer find their bindings
+#[derive(Clone)]
 pub struct Environment {
-    store: HashMap<String, LispVal>,
-    outer: Option<Box<Environment>>,
+    frame: Rc<Frame>,
+    dynamic: Rc<Dynamic>,
+}
+
+// The frames a closure was made in. Unlike an environment it does not keep the interpreter alive
+#[derive(Clone)]
+pub struct Scope(Rc<Frame>);
+
+impl PartialEq for Scope {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Scope")
+    }
+}
+
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.frame, &other.frame)
+    }
+}
+
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Environment")
+    }
 }
 
 impl Environment {
     pub fn init_env() -> Environment {
+        let mut env = Environment {
+            frame: Rc::new(Frame {
+                store: RefCell::new(HashMap::<Symbol, LispVal>::new()),
+                outer: None,
+            }),
+            dynamic: Rc::new(Dynamic::default()),
+        };
+        env.dynamic.add_frame(&env.frame);
+        GLOBALS.with(|globals| {
+            for (name, builtin) in globals {
+                env.define(name.clone(), LispVal::Fun(*builtin));
+            }
+        });
+        let random = LispVal::RandomSource(env.random_source());
+        env.define(Symbol::intern("default-random-source"), random);
+        env
+    }
+
+    pub fn new_enclosed(&mut self, envir: Environment) -> Environment {
+        envir.charge(size_of::<Frame>());
+        let frame = Rc::new(Frame {
+            store: RefCell::new(HashMap::<Symbol, LispVal>::new()),
+            outer: Some(envir.frame),
+        });
+        envir.dynamic.add_frame(&frame);
         Environment {
-            store: HashMap::<String, LispVal>::new(),
-            outer: None,
+            frame,
+            dynamic: envir.dynamic,
         }
     }
 
-    pub fn new_enclosed(&mut self, envir: Environment) -> Environment {
-        let mut env = Environment::init_env();
-        env.outer = Some(Box::new(envir));
-        env
+    pub fn scope(&self) -> Scope {
+        Scope(self.frame.clone())
+    }
+
+    // Evaluates in scope, with the calls, handlers and limits of this environment
+    pub fn in_scope(&self, scope: &Scope) -> Environment {
+        Environment {
+            frame: scope.0.clone(),
+            dynamic: self.dynamic.clone(),
+        }
     }
 
-    pub fn get(&self, name: String) -> Option<LispVal> {
-        let p = self.store.get(&name);
-        match p {
-            Some(c) => return Some(c.clone()),
-            None => {
-                if self.outer != None {
-                    let p = self.outer.as_ref()?;
+    // Frames from the innermost out
+    fn frames(&self) -> impl Iterator<Item = &Frame> {
+        std::iter::successors(Some(&*self.frame), |x| x.outer.as_deref())
+    }
 
-                    let pp = p.get(name.clone());
-                    match pp {
-                        Some(c) => return Some(c),
-                        None => return None,
-                    }
-                } else {
-                    None
+    pub fn get(&self, name: &Symbol) -> Option<LispVal> {
+        self.frames()
+            .find_map(|frame| frame.store.borrow().get(name).cloned())
+    }
+
+    // Binds name in the current frame, replacing any binding it already has there
+    pub fn define(&mut self, name: Symbol, obj: LispVal) -> LispVal {
+        self.charge(size_of::<LispVal>() + size_of::<Symbol>());
+        self.frame.store.borrow_mut().insert(name, obj.clone());
+        obj
+    }
+
+    // Changes the binding of name in the nearest frame that has one
+    pub fn set(&mut self, name: Symbol, obj: LispVal) -> Result<LispVal, EvalError> {
+        for frame in self.frames() {
+            if let Some(val) = frame.store.borrow_mut().get_mut(&name) {
+                *val = obj.clone();
+                return Ok(obj);
+            }
+        }
+        Err(ErrorKind::UnboundVariable(name).into())
+    }
+
+    fn push_handler(&self, handler: Option<LispVal>) {
+        self.dynamic.handlers.borrow_mut().push(handler);
+    }
+
+    fn pop_handler(&self) -> Option<LispVal> {
+        self.dynamic.handlers.borrow_mut().pop().flatten()
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.dynamic.limits.get()
+    }
+
+    // Limits apply from the next call to eval_prog on any environment of this interpreter
+    pub fn set_limits(&self, limits: Limits) {
+        self.dynamic.limits.set(limits);
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.dynamic.interrupt.clone()
+    }
+
+    // The source random-integer and random-real draw from
+    pub(crate) fn random_source(&self) -> Rc<RandomSource> {
+        self.dynamic.random.clone()
+    }
+
+    fn charge(&self, bytes: usize) {
+        let heap = &self.dynamic.usage.heap;
+        heap.set(heap.get().saturating_add(bytes));
+    }
+
+    // Charges an allocation a builtin is about to make, failing before it is made if it is too large
+    // to make at all or goes over the heap limit
+    pub(crate) fn allocate(&self, bytes: usize) -> Result<(), EvalError> {
+        if bytes > MAX_ALLOCATION {
+            return Err(ErrorKind::AllocationTooLarge(bytes).into());
+        }
+        self.charge(bytes);
+        if self
+            .limits()
+            .max_heap
+            .is_some_and(|x| self.dynamic.usage.heap.get() > x)
+        {
+            return Err(ErrorKind::LimitExceeded(Limit::Heap).into());
+        }
+        Ok(())
+    }
+
+    // Resets the usage counted against the limits, unless an evaluation is already running
+    fn start_usage(&self) {
+        let usage = &self.dynamic.usage;
+        if usage.depth.get() > 0 {
+            return;
+        }
+        usage.steps.set(0);
+        usage.work.set(0);
+        usage.heap.set(0);
+        let timeout = self.limits().timeout;
+        usage.deadline.set(timeout.map(|x| Instant::now() + x));
+    }
+
+    // Counts one evaluation step, checks for an interrupt and every limit but the depth
+    fn step(&self) -> Result<(), EvalError> {
+        self.advance(1)
+    }
+
+    // Counts the digit operations of a long bignum loop, so the fuel, the timeout and interrupts
+    // can stop it part way
+    pub(crate) fn work(&self, ops: u64) -> Result<(), EvalError> {
+        let usage = &self.dynamic.usage;
+        let work = usage.work.get().saturating_add(ops);
+        usage.work.set(work % WORK_PER_STEP);
+        match work / WORK_PER_STEP {
+            0 => Ok(()),
+            steps => self.advance(steps),
+        }
+    }
+
+    fn advance(&self, count: u64) -> Result<(), EvalError> {
+        if self.dynamic.interrupt.take() {
+            return Err(ErrorKind::Interrupted.into());
+        }
+        let usage = &self.dynamic.usage;
+        let limits = self.limits();
+        let before = usage.steps.get();
+        let steps = before.saturating_add(count);
+        usage.steps.set(steps);
+        if limits.fuel.is_some_and(|x| steps > x) {
+            return Err(ErrorKind::LimitExceeded(Limit::Fuel).into());
+        }
+        if limits.max_heap.is_some_and(|x| usage.heap.get() > x) {
+            return Err(ErrorKind::LimitExceeded(Limit::Heap).into());
+        }
+        if steps / CLOCK_STEPS != before / CLOCK_STEPS {
+            if let Some(deadline) = usage.deadline.get() {
+                if Instant::now() >= deadline {
+                    return Err(ErrorKind::LimitExceeded(Limit::Time).into());
                 }
             }
         }
+        Ok(())
+    }
+
+    // Starts a nested evaluation. Calls it makes in tail position replace each other
+    fn open_calls(&self) -> usize {
+        let len = self.dynamic.calls.borrow().len();
+        self.dynamic.calls_base.replace(len)
+    }
+
+    // Ends the evaluation started by open_calls, dropping the calls it made. An error leaving it
+    // takes a copy of the calls active where it happened
+    fn close_calls<T>(&self, base: usize, result: Result<T, EvalError>) -> Result<T, EvalError> {
+        let mut calls = self.dynamic.calls.borrow_mut();
+        let result = result.map_err(|mut e| {
+            if e.backtrace.is_empty() {
+                e.backtrace = calls.clone();
+            }
+            e
+        });
+        calls.truncate(self.dynamic.calls_base.replace(base));
+        result
+    }
+
+    fn push_call(&self, mut frame: CallFrame) {
+        let mut calls = self.dynamic.calls.borrow_mut();
+        if calls.len() > self.dynamic.calls_base.get() {
+            if let Some(caller) = calls.pop() {
+                frame.tail_calls = caller.tail_calls + 1;
+            }
+        }
+        calls.push(frame);
     }
 
-    pub fn set(&mut self, name: String, obj: LispVal) -> LispVal {
-        self.store.insert(name, obj.clone());
-        return obj;
+    // The innermost handler, None when there is none or a guard is closer
+    fn current_handler(&self) -> Option<LispVal> {
+        self.dynamic.handlers.borrow().last().cloned().flatten()
     }
 }
 
-pub fn eval_prog(prog: Program, env: &mut Environment) -> Result<Vec<LispVal>, String> {
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "not",
+        func: eval_not,
+    },
+    Builtin {
+        name: "eq?",
+        func: eval_is_eq,
+    },
+    Builtin {
+        name: "eqv?",
+        func: eval_is_eqv,
+    },
+    Builtin {
+        name: "equal?",
+        func: eval_is_equal,
+    },
+    Builtin {
+        name: "values",
+        func: eval_values,
+    },
+    Builtin {
+        name: "call-with-values",
+        func: eval_call_with_values,
+    },
+    Builtin {
+        name: "raise",
+        func: eval_raise,
+    },
+    Builtin {
+        name: "raise-continuable",
+        func: eval_raise_continuable,
+    },
+    Builtin {
+        name: "with-exception-handler",
+        func: eval_with_exception_handler,
+    },
+    Builtin {
+        name: "error",
+        func: eval_error,
+    },
+    Builtin {
+        name: "error-object?",
+        func: eval_is_error_object,
+    },
+    Builtin {
+        name: "error-object-message",
+        func: eval_error_object_message,
+    },
+    Builtin {
+        name: "error-object-irritants",
+        func: eval_error_object_irritants,
+    },
+    Builtin {
+        name: "file-error?",
+        func: eval_is_file_error,
+    },
+    Builtin {
+        name: "read-error?",
+        func: eval_is_read_error,
+    },
+];
+
+// Forms hand back the expression in tail position instead of evaluating it, so eval loops on it
+// rather than recursing. Loops written as tail calls then run in constant stack space
+enum Tail {
+    Value(LispVal),
+    Expr(LispVal, Environment),
+}
+
+pub fn eval_prog(prog: Program, env: &mut Environment) -> Result<Vec<LispVal>, EvalError> {
+    env.start_usage();
     let mut v = Vec::<LispVal>::new();
-    // let x = prog.len();
-    // println!("{}", x);
     for list in prog {
-        let result = eval(list, env);
-        match result {
-            Ok(x) => {
-                v.push(x);
-            }
-            Err(x) => return Err(x),
-        }
+        v.push(eval(list, env)?);
     }
     Ok(v)
 }
 
-fn eval(val: LispVal, env: &mut Environment) -> Result<LispVal, String> {
-    let evl = match val {
-        LispVal::List(_) => eval_list(&val.clone(), env),
-        LispVal::Atom(x) => eval_atom(x.literal, env),
-        //LispVal::DottedList(_, _) => eval_dotted_list(&val),
-        LispVal::Float(_) => Ok(val),
-        LispVal::Number(_) => Ok(val),
-        LispVal::String(_) => Ok(val.clone()),
-        //LispVal::Fun(x) => eval_fun(&val),
-        //LispVal::Lamda(_, _) => eval_lambda(&val, env),
-        LispVal::Nil => Ok(val),
-        LispVal::Bool(_) => Ok(val),
-        _ => return Err("problem with eval".to_owned()),
-    };
-    evl
+// Where the stack is at the caller. Only differences between two positions mean anything
+#[inline(always)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
 }
 
-fn eval_atom(val: String, env: &mut Environment) -> Result<LispVal, String> {
-    let check = env.get(val.clone());
-    match check {
-        Some(x) => Ok(x),
-        None => Err(format!("identifier not in environment: {}", val.clone())),
+fn eval(val: LispVal, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let usage = &env.dynamic.usage;
+    let depth = &usage.depth;
+    let limits = env.limits();
+    if depth.get() == 0 {
+        usage.stack_base.set(stack_position());
     }
+    let stack = usage.stack_base.get().abs_diff(stack_position());
+    if limits.max_depth.is_some_and(|x| depth.get() >= x)
+        || limits.max_stack.is_some_and(|x| stack > x)
+    {
+        return Err(ErrorKind::LimitExceeded(Limit::Depth).into());
+    }
+    depth.set(depth.get() + 1);
+    let base = env.open_calls();
+    let result = eval_tail(val, env);
+    let result = env.close_calls(base, result);
+    let depth = &env.dynamic.usage.depth;
+    depth.set(depth.get() - 1);
+    result
 }
-fn eval_list(val: &LispVal, env: &mut Environment) -> Result<LispVal, String> {
-    let list = match val.clone() {
-        LispVal::List(x) => x,
-        _ => return Err(format!("not a list. LispVal: {:?}", *val)),
-    };
 
-    match &list[0] {
-        LispVal::Atom(x) => match x.kind {
-            TokenType::MINUS | TokenType::PLUS | TokenType::ASTERICK | TokenType::SLASH => {
-                return eval_bin(&list, env)
+fn eval_tail(val: LispVal, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let mut val = val;
+    let mut env = env.clone();
+    loop {
+        env.step()?;
+        let tail = match val {
+            // errors are tagged with the innermost form that has a location
+            LispVal::List(_) => {
+                eval_list(&val, &mut env).map_err(|e| e.or_at(location_of(&val)))?
             }
-            TokenType::LET => return eval_let(&list, env),
-            TokenType::EQ | TokenType::GT | TokenType::GTEQ | TokenType::LT | TokenType::LTEQ => {
-                return eval_cond(&list, env)
+            LispVal::Atom(x) => {
+                return eval_atom(x.name, &mut env).map_err(|e| e.or_at(Some(x.loc)))
             }
-            TokenType::QUOTE => return Ok(LispVal::List(list[1..].to_vec())),
-            // TokenType::IF => return eval_if(&list),
-            TokenType::DEFINE => return eval_define(&list, env),
-            TokenType::LAMBDA => return eval_lambda(&list, env),
-            TokenType::IDENT => {
-                let x = eval_atom(x.literal.clone(), env);
-                let val = match x {
-                    Ok(v) => v,
-                    Err(err) => return Err(err),
-                };
-                match val {
-                    LispVal::List(x) => {
-                        return eval_proc(&x, &list[1..].to_vec(), env);
-                    }
-                    _ => return Err(format!("should of been a list for procedure")),
+            LispVal::DottedList(_, _) => {
+                return Err(ErrorKind::Syntax {
+                    message: "cannot evaluate a dotted list".to_owned(),
+                    form: val,
                 }
+                .into())
             }
-            // TokenType::LAMBDA => return eval_lam(&list),
-            _ => todo!("Not fully finished"),
-        },
-
-        LispVal::List(x) => match &x[0] {
-            LispVal::Atom(z) => {
-                if z.kind == TokenType::LAMBDA {
-                    return eval_lambda(&list.clone(), env);
-                }else if z.kind == TokenType::DEFINE {
-                    let y =  eval_define(&x.clone(), env);
-                    if list.len() == 1 {
-                        return y;
-                    }
-                    return eval_list(&LispVal::List(list[1..].to_vec()), env);
-                }else if z.kind == TokenType::LET {
-                    eval_let(&x.clone(), env)
-                }
-                 else {
-                    return eval_list(&list[0], env);
-                }
+            _ => return Ok(val),
+        };
+        match tail {
+            Tail::Value(x) => return Ok(x),
+            Tail::Expr(x, e) => {
+                val = x;
+                env = e;
             }
+        }
+    }
+}
 
-            _ => return eval_list(&list[0], env),
-        },
-        LispVal::Float(_) => todo!(),
-        LispVal::DottedList(_, _) => todo!(),
-        LispVal::Number(_) => todo!(),
-        LispVal::String(_) => todo!(),
-        LispVal::Fun(_, _) => todo!(),
-        LispVal::Lamda(_, _) => return eval_lambda(&list, env),
-        LispVal::Nil => todo!(),
-        LispVal::Bool(_) => todo!(),
+fn eval_atom(val: Symbol, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let check = env.get(&val);
+    match check {
+        Some(LispVal::Undefined) => Err(ErrorKind::Unassigned(val).into()),
+        Some(x) => Ok(x),
+        None => Err(ErrorKind::UnboundVariable(val).into()),
     }
 }
 
-fn eval_let(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    let vec = &list[1];
-    // println!("{}", list.len());
-    // println!("{:?}", vec);
-    let mut envr = env.new_enclosed(env.clone());
-    let _ = match vec {
-        LispVal::List(x) =>{eval_let_pair(&x, &mut envr)},
-        _ => return Err(format!("let eval went wrong")),
-    };
-    let res = eval(list[2].clone(), &mut envr);
-    match res {
-        Ok(re) => return Ok(re),
-        Err(x) => return Err(x),
+// Where a form was read, taken from the first identifier in it
+fn location_of(val: &LispVal) -> Option<Location> {
+    match val {
+        LispVal::Atom(x) if x.loc.line > 0 => Some(x.loc),
+        LispVal::List(x) | LispVal::DottedList(x, _) => x.iter().find_map(location_of),
+        _ => None,
     }
 }
 
-fn eval_let_pair(list: &Vec<LispVal>, env: &mut Environment) -> Result<(), String> {
-    let mut ident: String;
-    for pair in list {
-        match pair {
-            LispVal::List(x) => {
-                match &x[0] {
-                    LispVal::Atom(tok) => ident = tok.literal.clone(),
-                    _ => return Err(format!("first of let pair of variable binding should be an identifier. Error in: {:?}", x[0]))
-                }
-                let check = eval(x[1].clone(), env);
-                match check {
-                Ok(x) =>  {
-                    _ = env.set(ident, x);
-                },
-                Err(x) => return Err(x)
-                }
-            },
-            _ => return Err(format!("variable bindings in let statement should be in pairs. (let ((x 5)(y 6))...error in {}", pair.show_val()))
+// Only #f is false in Scheme, every other value counts as true
+pub fn is_true(val: &LispVal) -> bool {
+    !matches!(val, LispVal::Bool(false))
+}
+
+fn eval_list(val: &LispVal, env: &mut Environment) -> Result<Tail, EvalError> {
+    let list = match val {
+        LispVal::List(x) => x,
+        _ => return Err(EvalError::wrong_type("a list", val)),
+    };
+    if list.is_empty() {
+        return Err(EvalError::syntax("cannot evaluate an empty list", list));
+    }
+
+    if let LispVal::Atom(x) = &list[0] {
+        match x.kind {
+            TokenType::LET => return eval_let(list, env),
+            TokenType::LETSTAR => return eval_let_star(list, env),
+            TokenType::LETREC | TokenType::LETRECSTAR => return eval_letrec(list, env),
+            TokenType::LETVALUES | TokenType::LETSTARVALUES => return eval_let_values(list, env),
+            TokenType::QUOTE => return eval_quote(list).map(Tail::Value),
+            TokenType::BEGIN => return eval_sequence(&list[1..], env),
+            TokenType::IF => return eval_if(list, env),
+            TokenType::COND => return eval_cond_clauses(list, env),
+            TokenType::GUARD => return eval_guard(list, env),
+            TokenType::CASE => return eval_case(list, env),
+            TokenType::WHEN | TokenType::UNLESS => return eval_when(list, env),
+            TokenType::AND => return eval_and(list, env),
+            TokenType::OR => return eval_or(list, env),
+            TokenType::DEFINE => return eval_define(list, env).map(Tail::Value),
+            TokenType::SET => return eval_set(list, env).map(Tail::Value),
+            TokenType::LAMBDA => return eval_lambda(list, env).map(Tail::Value),
+            TokenType::CASELAMBDA => return eval_case_lambda(list, env).map(Tail::Value),
+            _ => (),
         }
     }
-    Ok(())
+
+    let proc = eval(list[0].clone(), env)?;
+    env.charge(list.len() * size_of::<LispVal>());
+    let mut args = Vec::<LispVal>::with_capacity(list.len() - 1);
+    for arg in &list[1..] {
+        args.push(eval(arg.clone(), env)?);
+    }
+    apply_proc(proc, args, location_of(val), env)
 }
 
-fn eval_cond(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    if list.len() != 3 {
-        return Err(
-            "invalid conditional operation. should have only 2 arguments for operation ex (< 2 3)"
-                .to_owned(),
-        );
-    }
-    let var: LispVal;
-    match &list[0] {
-        LispVal::Atom(x) => match x.kind {
-            TokenType::LT => var = eval_cond_lt(list, env).unwrap(),
-            TokenType::LTEQ => var = eval_cond_lteq(list, env).unwrap(),
-            TokenType::EQ => var = eval_cond_eq(list, env).unwrap(),
-            TokenType::GT => var = eval_cond_gt(list, env).unwrap(),
-            TokenType::GTEQ => var = eval_cond_gteq(list, env).unwrap(),
-            _ => {
-                return Err(format!(
-                    "invalid cond operation. error: {}",
-                    list[0].show_val()
-                ))
-            }
-        },
-        _ => {
-            return Err(format!(
-                "invalid cond operation. error: {}",
-                list[0].show_val()
-            ))
-        }
+fn eval_quote(list: &[LispVal]) -> Result<LispVal, EvalError> {
+    if list.len() != 2 {
+        return Err(EvalError::syntax(
+            "quote takes exactly one datum (quote x)",
+            list,
+        ));
     }
-    Ok(var)
+    Ok(datum(&list[1]))
 }
 
-fn eval_cond_lt(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    if list.len() != 3 {
-        return Err(
-            "invalid conditional operation. should have 2 arguments for operation ex (< 2 3)"
-                .to_owned(),
-        );
-    }
-    let first = list[1].clone();
-    let second = list[2].clone();
-    let res_first = eval(first, env).unwrap();
-    let res_second = eval(second, env).unwrap();
-    match res_first {
-        LispVal::Number(x) => match res_second {
-            LispVal::Number(y) => {
-                return Ok(LispVal::Bool(x < y));
-            }
-            _ => {
-                return Err(format!(
-                    "conditonal not fully implemented first: {} second: {}",
-                    res_first.show_val(),
-                    res_second.show_val()
-                ))
-            }
-        },
-        _ => {
-            return Err(format!(
-                "conditonal not fully implemented first: {} second: {}",
-                res_first.show_val(),
-                res_second.show_val()
-            ))
-        }
+// Quoted identifiers become symbols and quoted lists become pairs, like the values built at run
+// time
+fn datum(val: &LispVal) -> LispVal {
+    match val {
+        LispVal::Atom(x) => LispVal::Symbol(x.name.clone()),
+        LispVal::List(x) => x
+            .iter()
+            .rev()
+            .fold(LispVal::Nil, |acc, x| LispVal::cons(datum(x), acc)),
+        LispVal::DottedList(x, y) => x
+            .iter()
+            .rev()
+            .fold(datum(y), |acc, x| LispVal::cons(datum(x), acc)),
+        x => x.clone(),
     }
 }
 
-fn eval_cond_lteq(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    if list.len() != 3 {
-        return Err(
-            "invalid conditional operation. should have 2 arguments for operation ex (< 2 3)"
-                .to_owned(),
-        );
-    }
-    let first = list[1].clone();
-    let second = list[2].clone();
-    let res_first = eval(first, env).unwrap();
-    let res_second = eval(second, env).unwrap();
-    match res_first {
-        LispVal::Number(x) => match res_second {
-            LispVal::Number(y) => {
-                return Ok(LispVal::Bool(x <= y));
-            }
-            _ => {
-                return Err(format!(
-                    "conditonal not fully implemented first: {} second: {}",
-                    res_first.show_val(),
-                    res_second.show_val()
-                ))
-            }
-        },
-        _ => {
-            return Err(format!(
-                "conditonal not fully implemented first: {} second: {}",
-                res_first.show_val(),
-                res_second.show_val()
-            ))
-        }
+// Evaluates each expression in order, the last one is left in tail position
+fn eval_sequence(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    let (last, init) = match list.split_last() {
+        Some(x) => x,
+        None => return Ok(Tail::Value(LispVal::Void)),
+    };
+    for expr in init {
+        eval(expr.clone(), env)?;
     }
+    Ok(Tail::Expr(last.clone(), env.clone()))
 }
 
-fn eval_cond_eq(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    if list.len() != 3 {
-        return Err(
-            "invalid conditional operation. should have 2 arguments for operation ex (< 2 3)"
-                .to_owned(),
-        );
-    }
-    let first = list[1].clone();
-    let second = list[2].clone();
-    let res_first = eval(first, env).unwrap();
-    let res_second = eval(second, env).unwrap();
-    match res_first {
-        LispVal::Number(x) => match res_second {
-            LispVal::Number(y) => {
-                return Ok(LispVal::Bool(x == y));
+// Bodies of procedures and lets may start with internal definitions. Their names are bound in
+// the body's own scope before any of them is evaluated, which gives them letrec* semantics
+fn eval_body(body: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    let mut defining = true;
+    for expr in body {
+        match defined_names(expr) {
+            Some(names) if defining => {
+                for name in names {
+                    env.define(name, LispVal::Undefined);
+                }
             }
-            _ => {
-                return Err(format!(
-                    "conditonal not fully implemented first: {} second: {}",
-                    res_first.show_val(),
-                    res_second.show_val()
+            Some(_) => {
+                return Err(EvalError::syntax(
+                    "internal definitions must come before the expressions of a body",
+                    std::slice::from_ref(expr),
                 ))
             }
-        },
-        _ => {
-            return Err(format!(
-                "conditonal not fully implemented first: {} second: {}",
-                res_first.show_val(),
-                res_second.show_val()
-            ))
+            None => defining = false,
         }
     }
+    eval_sequence(body, env)
 }
 
-fn eval_cond_gt(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    if list.len() != 3 {
-        return Err(
-            "invalid conditional operation. should have 2 arguments for operation ex (< 2 3)"
-                .to_owned(),
-        );
-    }
-    let first = list[1].clone();
-    let second = list[2].clone();
-    let res_first = eval(first, env).unwrap();
-    let res_second = eval(second, env).unwrap();
-    match res_first {
-        LispVal::Number(x) => match res_second {
-            LispVal::Number(y) => {
-                return Ok(LispVal::Bool(x > y));
-            }
-            _ => {
-                return Err(format!(
-                    "conditonal not fully implemented first: {} second: {}",
-                    res_first.show_val(),
-                    res_second.show_val()
-                ))
-            }
-        },
-        _ => {
-            return Err(format!(
-                "conditonal not fully implemented first: {} second: {}",
-                res_first.show_val(),
-                res_second.show_val()
-            ))
+// Names bound by a definition, including definitions spliced in by begin
+fn defined_names(expr: &LispVal) -> Option<Vec<Symbol>> {
+    let list = match expr {
+        LispVal::List(x) => x,
+        _ => return None,
+    };
+    if is_keyword(&list[0], TokenType::DEFINE) {
+        let name = match list.get(1)? {
+            LispVal::List(x) | LispVal::DottedList(x, _) => &x[0],
+            x => x,
+        };
+        return match name {
+            LispVal::Atom(x) => Some(vec![x.name.clone()]),
+            _ => None,
+        };
+    }
+    if is_keyword(&list[0], TokenType::BEGIN) && list.len() > 1 {
+        let mut names = Vec::<Symbol>::new();
+        for expr in &list[1..] {
+            names.extend(defined_names(expr)?);
         }
+        return Some(names);
     }
+    None
 }
 
-fn eval_cond_gteq(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    if list.len() != 3 {
-        return Err(
-            "invalid conditional operation. should have 2 arguments for operation ex (< 2 3)"
-                .to_owned(),
-        );
-    }
-    let first = list[1].clone();
-    let second = list[2].clone();
-    let res_first = eval(first, env).unwrap();
-    let res_second = eval(second, env).unwrap();
-    match res_first {
-        LispVal::Number(x) => match res_second {
-            LispVal::Number(y) => {
-                return Ok(LispVal::Bool(x >= y));
-            }
-            _ => {
-                return Err(format!(
-                    "conditonal not fully implemented first: {} second: {}",
-                    res_first.show_val(),
-                    res_second.show_val()
-                ))
-            }
-        },
-        _ => {
-            return Err(format!(
-                "conditonal not fully implemented first: {} second: {}",
-                res_first.show_val(),
-                res_second.show_val()
-            ))
-        }
+fn eval_if(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    if list.len() != 3 && list.len() != 4 {
+        return Err(EvalError::syntax(
+            "if should have a test, a consequent and an optional alternative (if (< x 3) x 3)",
+            list,
+        ));
+    }
+    if is_true(&eval(list[1].clone(), env)?) {
+        Ok(Tail::Expr(list[2].clone(), env.clone()))
+    } else if list.len() == 4 {
+        Ok(Tail::Expr(list[3].clone(), env.clone()))
+    } else {
+        Ok(Tail::Value(LispVal::Void))
     }
 }
 
-fn eval_bin(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    if list.len() < 3 {
-        return Err(
-            "invalid binary operation. should have at least 2 arguments for operation ex (+ 2 3)"
-                .to_owned(),
-        );
-    }
-    let var: LispVal;
-    match &list[0] {
-        LispVal::Atom(x) => match x.kind {
-            TokenType::ASTERICK => var = eval_bin_mult(list, env).unwrap(),
-            TokenType::MINUS => var = eval_bin_sub(list, env).unwrap(),
-            TokenType::SLASH => var = eval_bin_div(list, env).unwrap(),
-            TokenType::PLUS => var = eval_bin_add(list, env).unwrap(),
+fn eval_cond_clauses(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    let tail = match_clauses(list, &list[1..], env)?;
+    Ok(tail.unwrap_or(Tail::Value(LispVal::Void)))
+}
+
+// Picks the first cond style clause whose test is true, None when no clause matches
+fn match_clauses(
+    list: &[LispVal],
+    clauses: &[LispVal],
+    env: &mut Environment,
+) -> Result<Option<Tail>, EvalError> {
+    for (index, clause) in clauses.iter().enumerate() {
+        let clause = match clause {
+            LispVal::List(x) => x,
             _ => {
-                return Err(format!(
-                    "invalid binary operation. error: {}",
-                    list[0].show_val()
+                return Err(EvalError::syntax(
+                    "clauses should be lists ((< x 3) x)",
+                    list,
                 ))
             }
-        },
-        _ => {
-            return Err(format!(
-                "invalid binary operation. error: {}",
-                list[0].show_val()
-            ))
+        };
+        let test = if is_keyword(&clause[0], TokenType::ELSE) {
+            if index != clauses.len() - 1 {
+                return Err(EvalError::syntax(
+                    "else clause must be the last clause",
+                    list,
+                ));
+            }
+            LispVal::Bool(true)
+        } else {
+            eval(clause[0].clone(), env)?
+        };
+        if !is_true(&test) {
+            continue;
+        }
+        if clause.len() > 1 && is_keyword(&clause[1], TokenType::ARROW) {
+            return eval_arrow(&clause[1..], test, env).map(Some);
+        }
+        if clause.len() == 1 {
+            return Ok(Some(Tail::Value(test)));
         }
+        return eval_sequence(&clause[1..], env).map(Some);
     }
-    Ok(var)
+    Ok(None)
 }
 
-fn eval_bin_mult(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    let mut result = LispVal::Number(1);
-    for i in 1..list.len() {
-        let res = eval(list[i].clone(), env);
-        match res {
-            Ok(x) => match x {
-                LispVal::Number(x) => match result {
-                    LispVal::Number(y) => result = LispVal::Number(x * y),
-                    _ => return Err("could not multiply lispval".to_owned()),
-                },
-                _ => return Err("could not multipy lispval. not a number".to_owned()),
-            },
-            Err(err) => return Err(err),
-        }
-    }
-    Ok(result)
-}
-
-fn eval_bin_sub(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    let mut result = LispVal::Number(0);
-    // if the first number in the sub (- x y). Add x to result or the answer will be -x - y instead
-    // of x - y.
-    for i in 1..list.len() {
-        let res = eval(list[i].clone(), env);
-        match res {
-            Ok(x) => {
-                if i == 1 {
-                    match x {
-                        LispVal::Number(x) => match result {
-                            LispVal::Number(y) => result = LispVal::Number(y + x),
-                            _ => return Err("could not sub lispval".to_owned()),
-                        },
-                        _ => return Err("could not sub lispval. not a number".to_owned()),
-                    }
-                } else {
-                    match x {
-                        LispVal::Number(x) => match result {
-                            LispVal::Number(y) => result = LispVal::Number(y - x),
-                            _ => return Err("could not sub lispval".to_owned()),
-                        },
-                        _ => return Err("could not sub lispval. not a number".to_owned()),
-                    }
+fn eval_case(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    if list.len() < 2 {
+        return Err(EvalError::syntax(
+            "case should have a key and clauses (case x ((1 2) 'low) (else 'high))",
+            list,
+        ));
+    }
+    let key = eval(list[1].clone(), env)?;
+    for (index, clause) in list[2..].iter().enumerate() {
+        let clause = match clause {
+            LispVal::List(x) if x.len() > 1 => x,
+            _ => {
+                return Err(EvalError::syntax(
+                    "case clauses should be a list of data followed by expressions ((1 2) x)",
+                    list,
+                ))
+            }
+        };
+        let matched = match &clause[0] {
+            x if is_keyword(x, TokenType::ELSE) => {
+                if index != list.len() - 3 {
+                    return Err(EvalError::syntax(
+                        "else clause must be the last clause of case",
+                        list,
+                    ));
                 }
+                true
             }
-            Err(err) => return Err(err),
+            LispVal::List(data) => data.iter().any(|x| is_eqv(&datum(x), &key)),
+            LispVal::Nil => false,
+            _ => return Err(EvalError::syntax("case clause data should be a list", list)),
+        };
+        if !matched {
+            continue;
         }
+        if is_keyword(&clause[1], TokenType::ARROW) {
+            return eval_arrow(&clause[1..], key, env);
+        }
+        return eval_sequence(&clause[1..], env);
     }
-    Ok(result)
+    Ok(Tail::Value(LispVal::Void))
 }
 
-fn eval_bin_add(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    let mut result = LispVal::Number(0);
-    // if the first number in the sub (- x y). Add x to result or the answer will be -x - y instead
-    // of x - y.
-    for i in 1..list.len() {
-        let res = eval(list[i].clone(), env);
-        match res {
-            Ok(x) => match x {
-                LispVal::Number(x) => match result {
-                    LispVal::Number(y) => result = LispVal::Number(y + x),
-                    _ => return Err("could not sub lispval".to_owned()),
-                },
-                _ => return Err("could not sub lispval. not a number".to_owned()),
-            },
-
-            Err(err) => return Err(err),
-        }
-    }
-    Ok(result)
-}
-
-fn eval_bin_div(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    let mut result = LispVal::Number(0);
-    for i in 1..list.len() {
-        let res = eval(list[i].clone(), env);
-        match res {
-            Ok(x) => {
-                if i == 1 {
-                    match x {
-                        LispVal::Number(x) => {
-                            if x == 0 {
-                                return Ok(LispVal::Number(0));
-                            }
-                            match result {
-                                LispVal::Number(y) => result = LispVal::Number(y + x),
-                                _ => return Err("could not divide lispval".to_owned()),
-                            }
-                        }
-                        _ => return Err("could not divide lispval. not a number".to_owned()),
-                    }
-                } else {
-                    match x {
-                        LispVal::Number(x) => {
-                            if x == 0 {
-                                return Err("cannot divide by 0".to_owned());
-                            }
-                            match result {
-                                LispVal::Number(y) => result = LispVal::Number(y / x),
-                                _ => return Err("could not sub lispval".to_owned()),
-                            }
-                        }
-                        _ => return Err("could not sub lispval. not a number".to_owned()),
-                    }
-                }
-            }
-            Err(err) => return Err(err),
-        }
+// Handles the (=> receiver) tail of cond and case clauses by calling receiver with the value
+fn eval_arrow(list: &[LispVal], val: LispVal, env: &mut Environment) -> Result<Tail, EvalError> {
+    if list.len() != 2 {
+        return Err(EvalError::syntax(
+            "=> should be followed by exactly one expression",
+            list,
+        ));
     }
-    Ok(result)
+    let receiver = eval(list[1].clone(), env)?;
+    apply_proc(receiver, vec![val], location_of(&list[1]), env)
 }
 
-fn eval_define(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    // first index should be the define atom
-    // second index should be either a procedure or a variable that is later assigned a value or body of function
-    // the list should be a length of 3
-
-    if list.len() != 3 {
-        return Err(format!("define should have 3 parts (define (x) (3))"));
+// when evaluates its body if the test is true, unless if the test is false
+fn eval_when(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    if list.len() < 3 {
+        return Err(EvalError::syntax(
+            "when and unless should have a test and a body (when (< x 3) x)",
+            list,
+        ));
     }
-    match &list[1] {
-        LispVal::Atom(x) => {
-            env.set(
-                x.literal.clone(),
-                eval(list[2].clone(), &mut env.clone()).unwrap(),
-            );
-            return Ok(LispVal::List(
-                [
-                    LispVal::Atom(x.clone()),
-                    LispVal::Atom(Token {
-                        literal: "<-".to_owned(),
-                        kind: TokenType::ILLEGAL,
-                    }),
-                    eval(list[2].clone(), env).unwrap(),
-                ]
-                .to_vec(),
-            ));
-        }
-        LispVal::List(x) => {
-            if x.len() < 2 {
-                match &x[0] {
-                    LispVal::Atom(x) => {
-                        env.set(
-                            x.literal.clone(),
-                            eval(list[2].clone(), &mut env.clone()).unwrap(),
-                        );
-                        return Ok(LispVal::List(
-                            [
-                                LispVal::Atom(x.clone()),
-                                LispVal::Atom(Token {
-                                    literal: "<-".to_owned(),
-                                    kind: TokenType::ILLEGAL,
-                                }),
-                                eval(list[2].clone(), env).unwrap(),
-                            ]
-                            .to_vec(),
-                        ));
-                    }
-                    _ => {
-                        return Err(format!(
-                            "define should be a list of identifiers or just an identifier"
-                        ))
-                    }
-                }
-            } else {
-                match &x[0] {
-                        LispVal::Atom(y) => {
-                            env.set(
-                                y.literal.clone(),
-                                LispVal::List(vec![LispVal::List(x[1..].to_vec()), list[2].clone()]),
-                            );
-                            return Ok(LispVal::List(
-                                [
-                                    LispVal::String(format!("fn {}", y.literal.clone())),
-                                    //LispVal::Atom(y.clone()),
-                                    // // LispVal::String(format!("number of parameters {} ", x[1..].len())),
-                                    //LispVal::String("parameters:".to_owned()),
-                                    LispVal::List(x[1..].to_vec())
-                                ]
-                                .to_vec(),
-                            ));
-                        },
-                        _ => return Err(format!(
-                            "error: first part to define a procedure should be the name of the procedure"
-                        ))
-                    }
-            }
-        }
-
-        _ => {
-            return Err(format!(
-                "define should be a list of identifier or just an identifier"
-            ))
-        }
+    let expected = is_keyword(&list[0], TokenType::WHEN);
+    if is_true(&eval(list[1].clone(), env)?) == expected {
+        return eval_sequence(&list[2..], env);
     }
+    Ok(Tail::Value(LispVal::Void))
 }
 
-fn eval_proc(
-    proc: &Vec<LispVal>,
-    list: &Vec<LispVal>,
-    env: &mut Environment,
-) -> Result<LispVal, String> {
-    let x = match &proc[0] {
-        LispVal::List(y) => y.len(),
-        _ => return Err("error getting the amount of parameters in procedure".to_owned()),
+fn eval_and(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    let (last, init) = match list[1..].split_last() {
+        Some(x) => x,
+        None => return Ok(Tail::Value(LispVal::Bool(true))),
     };
-    if list.len() != x {
-        return Err("not enough arguments for procedure".to_owned());
-    }
-    let mut o_env = env.new_enclosed(env.clone());
-
-    match &proc[0] {
-        LispVal::List(x) => {
-            for (index, val) in x.iter().enumerate() {
-                match val {
-                    LispVal::Atom(x) => {
-                        o_env.set(x.literal.clone(), list[index].clone());
-                    }
-                    _ => return Err("should of been an atom".to_owned()),
-                }
-            }
+    for expr in init {
+        let result = eval(expr.clone(), env)?;
+        if !is_true(&result) {
+            return Ok(Tail::Value(result));
         }
-        _ => return Err("should of been a list".to_owned()),
     }
-    eval(proc[1].clone(), &mut o_env)
+    Ok(Tail::Expr(last.clone(), env.clone()))
 }
 
-fn eval_lambda(list: &Vec<LispVal>, env: &mut Environment) -> Result<LispVal, String> {
-    let mut params: Vec<LispVal> = Vec::<LispVal>::new();
-    match &list[0] {
-        LispVal::Lamda(x,_) => match *x.clone() {
-            LispVal::List(z) => {
-                for i in z {
-                    params.push(i);
-                }
+fn eval_or(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    let (last, init) = match list[1..].split_last() {
+        Some(x) => x,
+        None => return Ok(Tail::Value(LispVal::Bool(false))),
+    };
+    for expr in init {
+        let result = eval(expr.clone(), env)?;
+        if is_true(&result) {
+            return Ok(Tail::Value(result));
+        }
+    }
+    Ok(Tail::Expr(last.clone(), env.clone()))
+}
+
+fn eval_not(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    if list.len() != 1 {
+        return Err(EvalError::arity(1, Some(1), list.len()));
+    }
+    Ok(LispVal::Bool(!is_true(&list[0])))
+}
+
+fn is_keyword(val: &LispVal, kind: TokenType) -> bool {
+    matches!(val, LispVal::Atom(x) if x.kind == kind)
+}
+
+// eq? tells objects apart by identity. Fixnums, flonums, booleans and symbols are held directly
+// rather than pointed to, so for them it is the same as eqv?
+pub(crate) fn is_eq(a: &LispVal, b: &LispVal) -> bool {
+    match (a, b) {
+        (LispVal::BigInt(x), LispVal::BigInt(y)) => Rc::ptr_eq(x, y),
+        (LispVal::Rational(x), LispVal::Rational(y)) => Rc::ptr_eq(x, y),
+        _ => is_eqv(a, b),
+    }
+}
+
+// eqv? also used to match case data against the key. Numbers are only eqv? when they have the
+// same exactness and value, and inexact numbers are compared by their bits, so 0.0 and -0.0
+// differ and a NaN is eqv? to itself
+pub(crate) fn is_eqv(a: &LispVal, b: &LispVal) -> bool {
+    match (a, b) {
+        (LispVal::Symbol(x), LispVal::Symbol(y)) => x == y,
+        (LispVal::Number(x), LispVal::Number(y)) => x == y,
+        (LispVal::BigInt(x), LispVal::BigInt(y)) => x == y,
+        (LispVal::Rational(x), LispVal::Rational(y)) => x == y,
+        (LispVal::Float(x), LispVal::Float(y)) => x.to_bits() == y.to_bits(),
+        (LispVal::Complex(x), LispVal::Complex(y)) => {
+            x.re.to_bits() == y.re.to_bits() && x.im.to_bits() == y.im.to_bits()
+        }
+        (LispVal::Bool(x), LispVal::Bool(y)) => x == y,
+        (LispVal::Nil, LispVal::Nil) | (LispVal::Void, LispVal::Void) => true,
+        (LispVal::Char(x), LispVal::Char(y)) => x == y,
+        (LispVal::String(x), LispVal::String(y)) => Rc::ptr_eq(x, y),
+        (LispVal::Pair(x), LispVal::Pair(y)) => Rc::ptr_eq(x, y),
+        (LispVal::Fun(x), LispVal::Fun(y)) => x == y,
+        (LispVal::Lamda(x), LispVal::Lamda(y)) => Rc::ptr_eq(x, y),
+        (LispVal::CaseLamda(x), LispVal::CaseLamda(y)) => Rc::ptr_eq(x, y),
+        (LispVal::Error(x), LispVal::Error(y)) => Rc::ptr_eq(x, y),
+        (LispVal::RandomSource(x), LispVal::RandomSource(y)) => Rc::ptr_eq(x, y),
+        _ => false,
+    }
+}
+
+// equal? compares pairs and strings by their contents and anything else with eqv?
+pub(crate) fn is_equal(a: &LispVal, b: &LispVal) -> bool {
+    is_equal_in(a, b, &mut HashSet::new())
+}
+
+// Pairs already being compared count as equal when they are met again. Circular structures then
+// come back to a comparison in progress instead of going around forever
+fn is_equal_in(a: &LispVal, b: &LispVal, seen: &mut HashSet<(*const Pair, *const Pair)>) -> bool {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    loop {
+        let (x, y) = match (&a, &b) {
+            (LispVal::Pair(x), LispVal::Pair(y)) => (x.clone(), y.clone()),
+            (LispVal::String(x), LispVal::String(y)) => return x == y,
+            _ => return is_eqv(&a, &b),
+        };
+        if Rc::ptr_eq(&x, &y) || !seen.insert((Rc::as_ptr(&x), Rc::as_ptr(&y))) {
+            return true;
+        }
+        if !is_equal_in(&x.car.borrow(), &y.car.borrow(), seen) {
+            return false;
+        }
+        a = x.cdr.borrow().clone();
+        b = y.cdr.borrow().clone();
+    }
+}
+
+fn eval_is_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [a, b] = fixed_args(list)?;
+    Ok(LispVal::Bool(is_eq(&a, &b)))
+}
+
+fn eval_is_eqv(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [a, b] = fixed_args(list)?;
+    Ok(LispVal::Bool(is_eqv(&a, &b)))
+}
+
+fn eval_is_equal(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [a, b] = fixed_args(list)?;
+    Ok(LispVal::Bool(is_equal(&a, &b)))
+}
+
+// let evaluates every init in the enclosing scope before any of the names are visible. A name
+// in place of the bindings makes it a named let, whose name is bound to a procedure over the body
+fn eval_let(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    if list.len() < 3 {
+        return Err(EvalError::syntax(
+            "let should have bindings and a body (let ((x 5)) x)",
+            list,
+        ));
+    }
+    if let LispVal::Atom(name) = &list[1] {
+        return eval_named_let(list, name.name.clone(), env);
+    }
+    let mut envr = env.new_enclosed(env.clone());
+    for (name, init) in eval_let_pair(&list[1])? {
+        let val = eval(init, env)?;
+        envr.define(name, val);
+    }
+    eval_body(&list[2..], &mut envr)
+}
+
+// (let loop ((i 0)) body) calls a procedure named loop with i bound to 0. The procedure is only
+// visible inside its own body
+fn eval_named_let(
+    list: &[LispVal],
+    name: Symbol,
+    env: &mut Environment,
+) -> Result<Tail, EvalError> {
+    if list.len() < 4 {
+        return Err(EvalError::syntax(
+            "named let should have a name, bindings and a body (let loop ((i 0)) i)",
+            list,
+        ));
+    }
+    let mut params = Vec::<Symbol>::new();
+    let mut args = Vec::<LispVal>::new();
+    for (param, init) in eval_let_pair(&list[2])? {
+        params.push(param);
+        args.push(eval(init, env)?);
+    }
+    let mut loop_env = env.new_enclosed(env.clone());
+    let proc = LispVal::Lamda(Rc::new(Lambda {
+        name: Some(name.name().to_owned()),
+        params,
+        rest: None,
+        body: list[3..].to_vec(),
+        env: loop_env.scope(),
+    }));
+    loop_env.define(name, proc.clone());
+    apply_proc(proc, args, location_of(&list[1]), env)
+}
+
+// let* binds each name in a new scope nested inside the previous one, so later inits see the
+// earlier names
+fn eval_let_star(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    if list.len() < 3 {
+        return Err(EvalError::syntax(
+            "let* should have bindings and a body (let* ((x 5) (y x)) y)",
+            list,
+        ));
+    }
+    let mut envr = env.new_enclosed(env.clone());
+    for (name, init) in eval_let_pair(&list[1])? {
+        let val = eval(init, &mut envr)?;
+        envr = envr.new_enclosed(envr.clone());
+        envr.define(name, val);
+    }
+    eval_body(&list[2..], &mut envr)
+}
+
+// letrec and letrec* bind every name before evaluating the inits, so the inits can refer to each
+// other. letrec* assigns each value as soon as it is evaluated, letrec only after all of them are
+fn eval_letrec(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    if list.len() < 3 {
+        return Err(EvalError::syntax(
+            "letrec should have bindings and a body (letrec ((f (lambda () 1))) (f))",
+            list,
+        ));
+    }
+    let sequential = is_keyword(&list[0], TokenType::LETRECSTAR);
+    let pairs = eval_let_pair(&list[1])?;
+    let mut envr = env.new_enclosed(env.clone());
+    for (name, _) in &pairs {
+        envr.define(name.clone(), LispVal::Undefined);
+    }
+    let mut vals = Vec::<(Symbol, LispVal)>::new();
+    for (name, init) in pairs {
+        let val = eval(init, &mut envr)?;
+        if sequential {
+            envr.define(name, val);
+        } else {
+            vals.push((name, val));
+        }
+    }
+    for (name, val) in vals {
+        envr.define(name, val);
+    }
+    eval_body(&list[2..], &mut envr)
+}
+
+// let-values binds the formals of each binding to the values returned by its init. As with let
+// the inits are evaluated in the enclosing scope, let*-values evaluates them one scope at a time
+fn eval_let_values(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    if list.len() < 3 {
+        return Err(EvalError::syntax(
+            "let-values should have bindings and a body (let-values (((a b) (values 1 2))) a)",
+            list,
+        ));
+    }
+    let sequential = is_keyword(&list[0], TokenType::LETSTARVALUES);
+    let mut envr = env.new_enclosed(env.clone());
+    for (formals, init) in binding_pairs(&list[1])? {
+        let vals = if sequential {
+            let vals = eval(init, &mut envr)?;
+            envr = envr.new_enclosed(envr.clone());
+            vals
+        } else {
+            eval(init, env)?
+        };
+        bind_formals(&formals, values_of(vals), &mut envr)?;
+    }
+    eval_body(&list[2..], &mut envr)
+}
+
+// Splits let style bindings ((x 1) (y 2)) into the bound name and its unevaluated init
+fn eval_let_pair(list: &LispVal) -> Result<Vec<(Symbol, LispVal)>, EvalError> {
+    let mut pairs = Vec::<(Symbol, LispVal)>::new();
+    for (name, init) in binding_pairs(list)? {
+        match name {
+            LispVal::Atom(x) => pairs.push((x.name, init)),
+            _ => {
+                return Err(EvalError::syntax(
+                    "first of let pair of variable binding should be an identifier",
+                    &[name, init],
+                ))
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+fn binding_pairs(list: &LispVal) -> Result<Vec<(LispVal, LispVal)>, EvalError> {
+    let list = match list {
+        LispVal::List(x) => x.as_slice(),
+        LispVal::Nil => &[],
+        _ => {
+            return Err(EvalError::syntax(
+                "let bindings should be a list of pairs",
+                std::slice::from_ref(list),
+            ))
+        }
+    };
+    let mut pairs = Vec::<(LispVal, LispVal)>::new();
+    for pair in list {
+        match pair {
+            LispVal::List(x) if x.len() == 2 => pairs.push((x[0].clone(), x[1].clone())),
+            _ => {
+                return Err(EvalError::syntax(
+                    "variable bindings in let statement should be in pairs. (let ((x 5)(y 6))...",
+                    std::slice::from_ref(pair),
+                ))
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+// Binds formals such as (a b), (a . rest) or args to a list of values
+fn bind_formals(
+    formals: &LispVal,
+    vals: Vec<LispVal>,
+    env: &mut Environment,
+) -> Result<(), EvalError> {
+    let (params, rest) = parse_formals(formals)?;
+    bind_args(&params, &rest, vals, env)
+}
+
+// Splits formals into the required parameter names and the optional rest parameter name
+fn parse_formals(formals: &LispVal) -> Result<(Vec<Symbol>, Option<Symbol>), EvalError> {
+    let (names, rest): (&[LispVal], Option<&LispVal>) = match formals {
+        LispVal::List(x) => (x, None),
+        LispVal::DottedList(x, y) => (x, Some(y)),
+        LispVal::Nil => (&[], None),
+        x => (&[], Some(x)),
+    };
+    let mut params = Vec::<Symbol>::new();
+    for name in names.iter().chain(rest) {
+        match name {
+            LispVal::Atom(x) => params.push(x.name.clone()),
+            _ => {
+                return Err(EvalError::syntax(
+                    "parameters should be identifiers",
+                    std::slice::from_ref(formals),
+                ))
+            }
+        }
+    }
+    let rest = if rest.is_some() { params.pop() } else { None };
+    Ok((params, rest))
+}
+
+fn accepts_args(params: &[Symbol], rest: &Option<Symbol>, count: usize) -> bool {
+    count == params.len() || (rest.is_some() && count > params.len())
+}
+
+fn bind_args(
+    params: &[Symbol],
+    rest: &Option<Symbol>,
+    vals: Vec<LispVal>,
+    env: &mut Environment,
+) -> Result<(), EvalError> {
+    if !accepts_args(params, rest, vals.len()) {
+        let max = match rest {
+            Some(_) => None,
+            None => Some(params.len()),
+        };
+        return Err(EvalError::arity(params.len(), max, vals.len()));
+    }
+    let mut vals = vals.into_iter();
+    for (param, val) in params.iter().zip(vals.by_ref()) {
+        env.define(param.clone(), val);
+    }
+    if let Some(rest) = rest {
+        env.define(rest.clone(), vec_to_list(vals.collect()));
+    }
+    Ok(())
+}
+
+pub fn vec_to_list(vals: Vec<LispVal>) -> LispVal {
+    vals.into_iter()
+        .rev()
+        .fold(LispVal::Nil, |acc, x| LispVal::cons(x, acc))
+}
+
+// Multiple values travel as a single Values object, any other value is one value
+fn values_of(val: LispVal) -> Vec<LispVal> {
+    match val {
+        LispVal::Values(x) => x,
+        x => vec![x],
+    }
+}
+
+fn eval_values(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    if list.len() == 1 {
+        return Ok(list[0].clone());
+    }
+    Ok(LispVal::Values(list))
+}
+
+fn eval_call_with_values(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [producer, consumer] = fixed_args(list)?;
+    let vals = eval_proc(producer, vec![], env)?;
+    eval_proc(consumer, values_of(vals), env)
+}
+
+// Checks that a builtin got exactly N arguments and hands them back by position
+pub(crate) fn fixed_args<const N: usize>(list: Vec<LispVal>) -> Result<[LispVal; N], EvalError> {
+    let count = list.len();
+    list.try_into()
+        .map_err(|_| EvalError::arity(N, Some(N), count))
+}
+
+// The comparison procedures such as < and string=? test every pair of neighbouring arguments with
+// one ordering predicate like Ordering::is_le. Each argument goes through key before any pair is
+// compared, and neighbours that are unordered, as with NaN, fail every predicate
+pub(crate) fn compare_chain<'a, T>(
+    list: &'a [LispVal],
+    key: impl Fn(&'a LispVal) -> Result<T, EvalError>,
+    order: impl Fn(&T, &T) -> Result<Option<cmp::Ordering>, EvalError>,
+    accepts: fn(cmp::Ordering) -> bool,
+) -> Result<LispVal, EvalError> {
+    if list.len() < 2 {
+        return Err(EvalError::arity(2, None, list.len()));
+    }
+    let keys = list
+        .iter()
+        .map(key)
+        .collect::<Result<Vec<T>, EvalError>>()?;
+    for pair in keys.windows(2) {
+        if !order(&pair[0], &pair[1])?.is_some_and(accepts) {
+            return Ok(LispVal::Bool(false));
+        }
+    }
+    Ok(LispVal::Bool(true))
+}
+
+// (guard (e clause...) body...) evaluates body and, if it raises, binds e to the raised object
+// and picks a clause like cond does. When no clause matches the object is raised again
+fn eval_guard(list: &[LispVal], env: &mut Environment) -> Result<Tail, EvalError> {
+    let spec = match list.get(1) {
+        Some(LispVal::List(x)) if list.len() > 2 => x,
+        _ => {
+            return Err(EvalError::syntax(
+                "guard should have a variable, clauses and a body (guard (e (#t 0)) (raise 1))",
+                list,
+            ))
+        }
+    };
+    let name = match &spec[0] {
+        LispVal::Atom(x) => x.name.clone(),
+        _ => {
+            return Err(EvalError::syntax(
+                "guard variable should be an identifier",
+                list,
+            ))
+        }
+    };
+    env.push_handler(None);
+    let mut body_env = env.new_enclosed(env.clone());
+    let result = eval_body(&list[2..], &mut body_env).and_then(force);
+    env.pop_handler();
+    let err = match result {
+        Ok(x) => return Ok(Tail::Value(x)),
+        // an interrupt stops the whole evaluation, the program cannot handle it
+        Err(e) if e.kind == ErrorKind::Interrupted => return Err(e),
+        Err(e) => e,
+    };
+    let mut envr = env.new_enclosed(env.clone());
+    envr.define(name, condition_of(err.clone()));
+    match match_clauses(list, &spec[1..], &mut envr)? {
+        Some(tail) => Ok(tail),
+        None => Err(err),
+    }
+}
+
+// The object a handler receives: what the program raised, or an error object for any other error
+fn condition_of(err: EvalError) -> LispVal {
+    match err.kind {
+        ErrorKind::Raised(x) => x,
+        _ => LispVal::Error(Rc::new(err)),
+    }
+}
+
+// Raising an error object again keeps the original error
+fn raised(obj: LispVal) -> EvalError {
+    match obj {
+        LispVal::Error(x) => (*x).clone(),
+        x => ErrorKind::Raised(x).into(),
+    }
+}
+
+fn eval_raise(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [obj] = fixed_args(list)?;
+    Err(raised(obj))
+}
+
+// The innermost handler is called right away, with the handlers outside it installed, and its
+// result is returned. A guard in between catches the object instead
+fn eval_raise_continuable(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [obj] = fixed_args(list)?;
+    let handler = match env.current_handler() {
+        Some(x) => x,
+        None => return Err(raised(obj)),
+    };
+    env.pop_handler();
+    let result = eval_proc(handler.clone(), vec![obj], env);
+    env.push_handler(Some(handler));
+    if result.is_err() {
+        env.dynamic.handler_failed.set(true);
+    }
+    result
+}
+
+// Errors that are not continuable reach the handler once they have unwound to it. If the handler
+// returns, that is an error of its own
+fn eval_with_exception_handler(
+    list: Vec<LispVal>,
+    env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let [handler, thunk] = fixed_args(list)?;
+    env.push_handler(Some(handler.clone()));
+    let result = eval_proc(thunk, vec![], env);
+    env.pop_handler();
+    let err = match result {
+        Ok(x) => return Ok(x),
+        // the handler already saw this error, it was raised by the handler itself
+        Err(e) if env.dynamic.handler_failed.replace(false) => return Err(e),
+        Err(e) if e.kind == ErrorKind::Interrupted => return Err(e),
+        Err(e) => e,
+    };
+    let obj = condition_of(err);
+    eval_proc(handler, vec![obj.clone()], env)?;
+    Err(ErrorKind::HandlerReturned(obj).into())
+}
+
+// (error "message" irritant...) raises an error object
+fn eval_error(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let mut list = list.into_iter();
+    let message = match list.next() {
+        Some(LispVal::String(x)) => x.borrow().clone(),
+        Some(x) => return Err(EvalError::wrong_type("a string", &x)),
+        None => return Err(EvalError::arity(1, None, 0)),
+    };
+    Err(ErrorKind::User {
+        message,
+        irritants: list.collect(),
+    }
+    .into())
+}
+
+fn error_object(val: &LispVal) -> Result<&EvalError, EvalError> {
+    match val {
+        LispVal::Error(x) => Ok(x),
+        x => Err(EvalError::wrong_type("an error object", x)),
+    }
+}
+
+fn eval_is_error_object(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [obj] = fixed_args(list)?;
+    Ok(LispVal::Bool(matches!(obj, LispVal::Error(_))))
+}
+
+fn eval_error_object_message(
+    list: Vec<LispVal>,
+    _env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let [obj] = fixed_args(list)?;
+    Ok(LispVal::string(error_object(&obj)?.kind.message()))
+}
+
+fn eval_error_object_irritants(
+    list: Vec<LispVal>,
+    _env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let [obj] = fixed_args(list)?;
+    Ok(vec_to_list(error_object(&obj)?.kind.irritants()))
+}
+
+// Nothing in the interpreter reads files or data yet, so no error is a file or read error
+fn eval_is_file_error(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [_] = fixed_args(list)?;
+    Ok(LispVal::Bool(false))
+}
+
+fn eval_is_read_error(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [_] = fixed_args(list)?;
+    Ok(LispVal::Bool(false))
+}
+
+fn eval_define(list: &[LispVal], env: &mut Environment) -> Result<LispVal, EvalError> {
+    // first index should be the define atom
+    // second index should be either a procedure or a variable that is later assigned a value or body of function
+    // a variable takes exactly one value, a procedure takes a body of one or more expressions
+
+    if list.len() < 3 {
+        return Err(EvalError::syntax(
+            "define should have at least 3 parts (define (x) (3))",
+            list,
+        ));
+    }
+    match &list[1] {
+        LispVal::Atom(_) if list.len() != 3 => Err(EvalError::syntax(
+            "define of a variable should have 3 parts (define x 3)",
+            list,
+        )),
+        LispVal::Atom(x) => {
+            let val = eval(list[2].clone(), env)?;
+            env.define(x.name.clone(), named(val, x.name.name()));
+            Ok(LispVal::Void)
+        }
+        // (define (f x . rest) body) has the rest parameter after the dot
+        LispVal::List(x) | LispVal::DottedList(x, _) => match &x[0] {
+            LispVal::Atom(y) => {
+                let params = match &list[1] {
+                    LispVal::DottedList(_, rest) if x.len() == 1 => *rest.clone(),
+                    LispVal::DottedList(_, rest) => {
+                        LispVal::DottedList(x[1..].to_vec(), rest.clone())
+                    }
+                    _ if x.len() == 1 => LispVal::Nil,
+                    _ => LispVal::List(x[1..].to_vec()),
+                };
+                let proc = make_lambda(&params, &list[2..], env)?;
+                env.define(y.name.clone(), named(proc, y.name.name()));
+                Ok(LispVal::Void)
             }
-            LispVal::Atom(x) => params.push(LispVal::Atom(x)),
-            _ => return Err("error evaluating params for lambda".to_owned()),
+            _ => Err(EvalError::syntax(
+                "first part to define a procedure should be the name of the procedure",
+                list,
+            )),
         },
-        _ => return Err("error evaluating paramaters for lambda".to_owned()),
+
+        _ => Err(EvalError::syntax(
+            "define should be a list of identifier or just an identifier",
+            list,
+        )),
     }
-    if params.len() != list[1..].len() {
-        return Err("number of params in lambda do not match the amount applied".to_owned());
+}
+
+// set! changes the nearest existing binding, it never creates a new one
+fn eval_set(list: &[LispVal], env: &mut Environment) -> Result<LispVal, EvalError> {
+    if list.len() != 3 {
+        return Err(EvalError::syntax(
+            "set! should have a variable and a value (set! x 3)",
+            list,
+        ));
     }
-    let mut o_env = env.new_enclosed(env.clone());
-    for (index, val) in params.iter().enumerate() {
-        match val {
-            LispVal::Atom(x) => {
-                o_env.set(x.literal.clone(), list[index + 1].clone());
+    let name = match &list[1] {
+        LispVal::Atom(x) => x.name.clone(),
+        _ => {
+            return Err(EvalError::syntax(
+                "set! should be given an identifier",
+                list,
+            ))
+        }
+    };
+    let val = eval(list[2].clone(), env)?;
+    env.set(name, val)?;
+    Ok(LispVal::Void)
+}
+
+// Applies an evaluated procedure to already evaluated arguments
+pub fn eval_proc(
+    proc: LispVal,
+    list: Vec<LispVal>,
+    env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let base = env.open_calls();
+    let result = apply_proc(proc, list, None, env).and_then(force);
+    env.close_calls(base, result)
+}
+
+// Evaluates whatever a form left in tail position
+fn force(tail: Tail) -> Result<LispVal, EvalError> {
+    match tail {
+        Tail::Value(x) => Ok(x),
+        Tail::Expr(x, mut e) => eval(x, &mut e),
+    }
+}
+
+// Binds the arguments of a lambda and leaves its body in tail position
+fn apply_proc(
+    proc: LispVal,
+    list: Vec<LispVal>,
+    call_site: Option<Location>,
+    env: &mut Environment,
+) -> Result<Tail, EvalError> {
+    let lambda = match proc {
+        LispVal::Lamda(x) => x,
+        LispVal::CaseLamda(clauses) => {
+            // the first clause whose formals accept the arguments is the one applied
+            match clauses
+                .iter()
+                .find(|x| accepts_args(&x.params, &x.rest, list.len()))
+            {
+                Some(x) => x.clone(),
+                None => return Err(case_lambda_arity(&clauses, list.len())),
             }
-            _ => return Err("should of been an atom for lambda".to_owned()),
         }
+        LispVal::Fun(x) => return (x.func)(list, env).map(Tail::Value),
+        _ => return Err(ErrorKind::NotApplicable(proc).into()),
+    };
+    env.push_call(CallFrame {
+        name: lambda.name.clone(),
+        call_site,
+        tail_calls: 0,
+    });
+    let mut o_env = env.new_enclosed(env.in_scope(&lambda.env));
+    bind_args(&lambda.params, &lambda.rest, list, &mut o_env)?;
+    eval_body(&lambda.body, &mut o_env)
+}
+
+fn eval_lambda(list: &[LispVal], env: &mut Environment) -> Result<LispVal, EvalError> {
+    if list.len() < 3 {
+        return Err(EvalError::syntax(
+            "lambda should have parameters and a body (lambda (x) (* x x))",
+            list,
+        ));
     }
-    match &list[0] {
-        LispVal::Lamda(_,y) => return eval(*y.clone(), &mut o_env),
-        _ => return Err("error evaluating body for lambda".to_owned()),
+    make_lambda(&list[1], &list[2..], env)
+}
+
+// (case-lambda ((x) x) ((x y) (+ x y))) picks the clause to run from the number of arguments
+fn eval_case_lambda(list: &[LispVal], env: &mut Environment) -> Result<LispVal, EvalError> {
+    let mut clauses = Vec::<Rc<Lambda>>::new();
+    for clause in &list[1..] {
+        match clause {
+            LispVal::List(x) if x.len() > 1 => match make_lambda(&x[0], &x[1..], env)? {
+                LispVal::Lamda(lambda) => clauses.push(lambda),
+                x => return Err(EvalError::wrong_type("a procedure", &x)),
+            },
+            _ => {
+                return Err(EvalError::syntax(
+                    "case-lambda clauses should have formals and a body ((x) x)",
+                    list,
+                ))
+            }
+        }
+    }
+    Ok(LispVal::CaseLamda(Rc::new(clauses)))
+}
+
+// Reports the range of argument counts accepted by all the clauses together
+fn case_lambda_arity(clauses: &[Rc<Lambda>], got: usize) -> EvalError {
+    let min = clauses.iter().map(|x| x.params.len()).min().unwrap_or(0);
+    let max = clauses
+        .iter()
+        .map(|x| x.rest.as_ref().map_or(Some(x.params.len()), |_| None))
+        .try_fold(0, |max, x| x.map(|x| max.max(x)));
+    EvalError::arity(min, max, got)
+}
+
+fn make_lambda(
+    params: &LispVal,
+    body: &[LispVal],
+    env: &Environment,
+) -> Result<LispVal, EvalError> {
+    let (params, rest) = parse_formals(params)?;
+    env.charge(size_of::<Lambda>() + size_of_val(body));
+    Ok(LispVal::Lamda(Rc::new(Lambda {
+        name: None,
+        params,
+        rest,
+        body: body.to_vec(),
+        env: env.scope(),
+    })))
+}
+
+// Gives an anonymous procedure the name it is being defined with
+fn named(val: LispVal, name: &str) -> LispVal {
+    let with_name = |x: &Rc<Lambda>| match x.name {
+        Some(_) => x.clone(),
+        None => Rc::new(Lambda {
+            name: Some(name.to_owned()),
+            ..(**x).clone()
+        }),
+    };
+    match val {
+        LispVal::Lamda(x) => LispVal::Lamda(with_name(&x)),
+        LispVal::CaseLamda(x) => LispVal::CaseLamda(Rc::new(x.iter().map(with_name).collect())),
+        x => x,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::test_util::show;
+
+    fn run(input: &str) -> Result<Vec<LispVal>, EvalError> {
+        run_in(input, &mut Environment::init_env())
+    }
+
+    fn run_in(input: &str, env: &mut Environment) -> Result<Vec<LispVal>, EvalError> {
+        let mut lex = Lexer::init_lex(input.to_owned());
+        let mut par = Parser::init_parser(&mut lex).expect("parser should start");
+        let prog = par.parse_program().expect("program should parse");
+        eval_prog(prog, env)
+    }
+
+    fn run_last(input: &str) -> LispVal {
+        match run(input) {
+            Ok(mut x) => x.pop().unwrap_or(LispVal::Void),
+            Err(e) => panic!("{} failed: {}", input, e),
+        }
+    }
+
+    fn symbol(x: &LispVal) -> &str {
+        match x {
+            LispVal::Symbol(x) => x.name(),
+            _ => panic!("expected a symbol got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn eval_if_truthiness() {
+        assert_eq!(run_last("(if (< 1 2) 10 20)"), LispVal::Number(10));
+        assert_eq!(run_last("(if #f 10 20)"), LispVal::Number(20));
+        // only #f is false
+        assert_eq!(run_last("(if 0 10 20)"), LispVal::Number(10));
+        assert_eq!(run_last("(if '() 10 20)"), LispVal::Number(10));
+        assert_eq!(run_last("(if #f 10)"), LispVal::Void);
+        assert!(run("(if)").is_err());
+    }
+
+    #[test]
+    fn eval_if_recursion() {
+        let input = "(define (fact n) (if (= n 0) 1 (* n (fact (- n 1))))) (fact 10)";
+        assert_eq!(run_last(input), LispVal::Number(3628800));
+    }
+
+    #[test]
+    fn eval_quote_forms() {
+        assert_eq!(symbol(&run_last("(quote x)")), "x");
+        assert_eq!(show("(quote (1 2))"), "(1 2)");
+        assert_eq!(run_last("(quote ())"), LispVal::Nil);
+        assert_eq!(show("'(quote x)"), "(quote x)");
+        assert_eq!(show("(car ''x)"), "quote");
+        assert!(run("(quote)").is_err());
+    }
+
+    #[test]
+    fn eval_cond_forms() {
+        let input = "(define (sign x) (cond ((< x 0) 'neg) ((= x 0) 'zero) (else 'pos)))";
+        assert_eq!(symbol(&run_last(&format!("{} (sign -4)", input))), "neg");
+        assert_eq!(symbol(&run_last(&format!("{} (sign 0)", input))), "zero");
+        assert_eq!(symbol(&run_last(&format!("{} (sign 9)", input))), "pos");
+        assert_eq!(run_last("(cond (#f 1))"), LispVal::Void);
+        assert_eq!(run_last("(cond (3))"), LispVal::Number(3));
+        assert_eq!(
+            run_last("(cond ((+ 1 2) => (lambda (x) (* x x))))"),
+            LispVal::Number(9)
+        );
+        assert!(run("(cond (else 1) (#t 2))").is_err());
+    }
+
+    #[test]
+    fn eval_case_forms() {
+        let input = "(define (kind x) (case x ((1 2 3) 'small) ((a b) 'letter) (else 'other)))";
+        assert_eq!(symbol(&run_last(&format!("{} (kind 2)", input))), "small");
+        assert_eq!(symbol(&run_last(&format!("{} (kind 'b)", input))), "letter");
+        assert_eq!(symbol(&run_last(&format!("{} (kind 7)", input))), "other");
+        assert_eq!(
+            run_last("(case 5 ((5) => (lambda (x) (+ x 1))))"),
+            LispVal::Number(6)
+        );
+        assert_eq!(
+            run_last("(case 4 ((5) 1) (else => (lambda (x) (* x 2))))"),
+            LispVal::Number(8)
+        );
+        assert_eq!(run_last("(case 4 ((5) 1))"), LispVal::Void);
+    }
+
+    #[test]
+    fn eval_and_or_not() {
+        assert_eq!(run_last("(and)"), LispVal::Bool(true));
+        assert_eq!(run_last("(and 1 2 3)"), LispVal::Number(3));
+        assert_eq!(run_last("(and 1 #f 3)"), LispVal::Bool(false));
+        assert_eq!(run_last("(or)"), LispVal::Bool(false));
+        assert_eq!(run_last("(or #f 2 3)"), LispVal::Number(2));
+        assert_eq!(run_last("(not #f)"), LispVal::Bool(true));
+        assert_eq!(run_last("(not 0)"), LispVal::Bool(false));
+        // short circuiting never reaches the unbound identifier
+        assert_eq!(run_last("(and #f undefined)"), LispVal::Bool(false));
+        assert_eq!(run_last("(or 1 undefined)"), LispVal::Number(1));
+    }
+
+    #[test]
+    fn eval_when_unless() {
+        assert_eq!(run_last("(when (< 1 2) 1 2 3)"), LispVal::Number(3));
+        assert_eq!(run_last("(when (> 1 2) 1)"), LispVal::Void);
+        assert_eq!(run_last("(unless (> 1 2) 4)"), LispVal::Number(4));
+        assert_eq!(run_last("(unless (< 1 2) 4)"), LispVal::Void);
+    }
+
+    #[test]
+    fn eval_closure_captures_parameter() {
+        let input = "(define (flip fn) (lambda (a b) (fn b a))) ((flip -) 5 8)";
+        assert_eq!(run_last(input), LispVal::Number(3));
+    }
+
+    #[test]
+    fn eval_set_mutates_nearest_binding() {
+        assert_eq!(run_last("(define x 1) (set! x 2) x"), LispVal::Number(2));
+        // the let binding shadows x so only it changes
+        let input = "(define x 1) (let ((x 5)) (set! x 6)) x";
+        assert_eq!(run_last(input), LispVal::Number(1));
+        let input = "(define x 1) (define (bump y) (set! x (+ x y))) (bump 4) (bump 5) x";
+        assert_eq!(run_last(input), LispVal::Number(10));
+        assert_eq!(run_last("(define x 1) (set! x 2)"), LispVal::Void);
+        assert!(run("(set! undefined 1)").is_err());
+    }
+
+    #[test]
+    fn eval_define_replaces_global() {
+        assert_eq!(
+            run_last("(define x 1) (define x (+ x 1)) x"),
+            LispVal::Number(2)
+        );
+        assert_eq!(
+            run_last("(define (f) 1) (define (f) 2) (f)"),
+            LispVal::Number(2)
+        );
+        assert_eq!(run_last("(define x 3)"), LispVal::Void);
+    }
+
+    #[test]
+    fn eval_define_evaluates_once() {
+        let input = "(define n 0) (define x (set! n (+ n 1))) n";
+        assert_eq!(run_last(input), LispVal::Number(1));
+    }
+
+    #[test]
+    fn eval_begin_sequences() {
+        assert_eq!(run_last("(begin 1 2 3)"), LispVal::Number(3));
+        assert_eq!(run_last("(begin)"), LispVal::Void);
+        let input = "(define x 0) (begin (set! x 5) (define y (+ x 1))) y";
+        assert_eq!(run_last(input), LispVal::Number(6));
+    }
+
+    #[test]
+    fn eval_multi_expression_bodies() {
+        let input = "(define log 0) (define (f x) (set! log x) (* x 2)) (+ (f 4) log)";
+        assert_eq!(run_last(input), LispVal::Number(12));
+        assert_eq!(
+            run_last("((lambda (x) (set! x (+ x 1)) x) 1)"),
+            LispVal::Number(2)
+        );
+        assert_eq!(run_last("(let ((x 1)) (set! x 7) x)"), LispVal::Number(7));
+        assert!(run("(define x 1 2)").is_err());
+    }
+
+    #[test]
+    fn eval_let_inits_use_outer_scope() {
+        assert_eq!(
+            run_last("(define x 1) (let ((x 2) (y x)) y)"),
+            LispVal::Number(1)
+        );
+        assert_eq!(run_last("(let () 5)"), LispVal::Number(5));
+    }
+
+    #[test]
+    fn eval_let_star_is_sequential() {
+        assert_eq!(
+            run_last("(define x 1) (let* ((x 2) (y x)) y)"),
+            LispVal::Number(2)
+        );
+        let input = "(let* ((x 1) (f (lambda () x)) (x 2)) (f))";
+        assert_eq!(run_last(input), LispVal::Number(1));
+    }
+
+    #[test]
+    fn eval_letrec_mutual_recursion() {
+        let input = "(letrec ((even? (lambda (n) (if (= n 0) #t (odd? (- n 1)))))
+                              (odd? (lambda (n) (if (= n 0) #f (even? (- n 1))))))
+                       (even? 100))";
+        assert_eq!(run_last(input), LispVal::Bool(true));
+        let input = "(letrec* ((a 1) (b (+ a 1))) b)";
+        assert_eq!(run_last(input), LispVal::Number(2));
+        // letrec inits cannot use each other's values, only refer to them from closures
+        assert!(run("(letrec ((a 1) (b (+ a 1))) b)").is_err());
+        assert!(run("(define x 0) (letrec ((x x)) x)").is_err());
+    }
+
+    #[test]
+    fn eval_named_let_loop() {
+        let input = "(let loop ((i 0) (acc 0)) (if (= i 100000) acc (loop (+ i 1) (+ acc 2))))";
+        assert_eq!(run_last(input), LispVal::Number(200000));
+        // the loop name does not leak out of the let
+        assert!(run("(let loop ((i 0)) i) (loop 1)").is_err());
+    }
+
+    #[test]
+    fn eval_let_values_forms() {
+        let input = "(let-values (((a b) (values 1 2)) ((c) (values 3))) (+ a b c))";
+        assert_eq!(run_last(input), LispVal::Number(6));
+        let input = "(let-values (((a . rest) (values 1 2 3))) rest)";
+        assert_eq!(show(input), "(2 3)");
+        let input = "(let*-values (((a b) (values 1 2)) ((c) (values (+ a b)))) c)";
+        assert_eq!(run_last(input), LispVal::Number(3));
+        assert!(run("(let-values (((a b) (values 1))) a)").is_err());
+        let input = "(call-with-values (lambda () (values 4 5)) (lambda (a b) (* a b)))";
+        assert_eq!(run_last(input), LispVal::Number(20));
+    }
+
+    #[test]
+    fn eval_rest_parameters() {
+        assert_eq!(show("((lambda args args) 1 2 3)"), "(1 2 3)");
+        assert_eq!(run_last("((lambda args args))"), LispVal::Nil);
+        assert_eq!(show("((lambda (a b . rest) rest) 1 2 3 4)"), "(3 4)");
+        assert_eq!(run_last("((lambda (a b . rest) rest) 1 2)"), LispVal::Nil);
+        assert_eq!(show("(define (f x . more) more) (f 1 2)"), "(2)");
+        assert_eq!(show("(define (g . all) all) (g 5 6)"), "(5 6)");
+    }
+
+    #[test]
+    fn eval_arity_errors_state_counts() {
+        let err = run("(define (f x y) x) (f 1 2 3)").unwrap_err();
+        assert!(err.to_string().contains("expected 2 but got 3"), "{}", err);
+        let err = run("(define (f x . y) x) (f)").unwrap_err();
+        assert!(
+            err.to_string().contains("expected at least 1 but got 0"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn eval_case_lambda_dispatch() {
+        let input = "(define area (case-lambda ((r) (* 3 r r)) ((w h) (* w h)) ((a b . c) c)))";
+        assert_eq!(
+            run_last(&format!("{} (area 2)", input)),
+            LispVal::Number(12)
+        );
+        assert_eq!(
+            run_last(&format!("{} (area 2 5)", input)),
+            LispVal::Number(10)
+        );
+        assert_eq!(show(&format!("{} (area 1 2 3)", input)), "(3)");
+        assert!(run(&format!("{} (area)", input)).is_err());
+    }
+
+    #[test]
+    fn eval_internal_definitions() {
+        let input = "(define (parity n)
+                       (define (ev? n) (if (= n 0) #t (od? (- n 1))))
+                       (define (od? n) (if (= n 0) #f (ev? (- n 1))))
+                       (ev? n))
+                     (parity 10)";
+        assert_eq!(run_last(input), LispVal::Bool(true));
+        let input = "(let ((x 1)) (define y (+ x 1)) (define z (* y 2)) z)";
+        assert_eq!(run_last(input), LispVal::Number(4));
+        let input = "((lambda () (begin (define a 1) (define b 2)) (+ a b)))";
+        assert_eq!(run_last(input), LispVal::Number(3));
+    }
+
+    #[test]
+    fn eval_internal_definitions_do_not_leak() {
+        assert!(run("(define (f) (define inner 1) inner) (f) inner").is_err());
+        let input = "(define x 1) (define (f) (define x 2) x) (f) x";
+        assert_eq!(run_last(input), LispVal::Number(1));
+        assert!(run("(let () (define hidden 1) hidden) hidden").is_err());
+    }
+
+    #[test]
+    fn eval_internal_definitions_scope() {
+        // x in the body always means the inner x, even before its definition has run
+        let input = "(define x 1) (define (f) (define y x) (define x 2) y) (f)";
+        assert!(run(input).is_err());
+        assert!(run("(define (f) 1 (define x 2) x) (f)").is_err());
+    }
+
+    #[test]
+    fn eval_error_kinds() {
+        let kind = |input: &str| run(input).unwrap_err().kind;
+        assert_eq!(
+            kind("undefined"),
+            ErrorKind::UnboundVariable(Symbol::intern("undefined"))
+        );
+        assert_eq!(
+            kind("(+ 1 \"a\")").to_string(),
+            "wrong type: expected a number but got a"
+        );
+        assert_eq!(
+            kind("((lambda (x) x))"),
+            ErrorKind::Arity {
+                min: 1,
+                max: Some(1),
+                got: 0
+            }
+        );
+        assert_eq!(kind("(/ 4 0)"), ErrorKind::DivisionByZero);
+        assert_eq!(kind("(5 1)"), ErrorKind::NotApplicable(LispVal::Number(5)));
+        assert!(matches!(kind("(if)"), ErrorKind::Syntax { .. }));
+    }
+
+    #[test]
+    fn eval_error_locations() {
+        let err = run("(define x 1)\n(+ x\n   y)").unwrap_err();
+        assert_eq!(err.location, Some(Location { line: 3, column: 4 }));
+        assert_eq!(err.to_string(), "unbound variable: y at line 3, column 4");
+        // the innermost form is reported, not the call that contains it
+        let err = run("(define (f x) (* x \"a\"))\n(f 2)").unwrap_err();
+        assert_eq!(
+            err.location,
+            Some(Location {
+                line: 1,
+                column: 16
+            })
+        );
+    }
+
+    #[test]
+    fn eval_guard_catches_raised_objects() {
+        assert_eq!(
+            run_last("(guard (e (#t e)) (raise 42))"),
+            LispVal::Number(42)
+        );
+        let input = "(guard (e ((string? e) 's) ((number? e) (* e 2))) (+ 1 (raise 21)))";
+        assert_eq!(run_last(input), LispVal::Number(42));
+        let input = "(guard (e ((< e 0) 'neg) (else 'other)) (raise 5))";
+        assert_eq!(symbol(&run_last(input)), "other");
+        assert_eq!(
+            run_last("(guard (e ((+ e 1) => (lambda (x) (* x 10)))) (raise 4))"),
+            LispVal::Number(50)
+        );
+        assert_eq!(run_last("(guard (e (#f 0)) 7)"), LispVal::Number(7));
+        // without a matching clause the object goes on to the outer guard
+        let input = "(guard (outer (#t (list-of outer))) (guard (e ((= e 1) 'one)) (raise 2)))";
+        assert!(run(input).is_err());
+        let input = "(guard (outer (#t (+ outer 100))) (guard (e ((= e 1) 'one)) (raise 2)))";
+        assert_eq!(run_last(input), LispVal::Number(102));
+        let err = run("(raise 'boom)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "uncaught exception: boom at line 1, column 2"
+        );
+    }
+
+    #[test]
+    fn eval_builtin_errors_are_error_objects() {
+        let input = "(guard (e ((error-object? e) (error-object-message e))) (/ 1 0))";
+        assert_eq!(show(input), "division by zero");
+        let input = "(guard (e (#t (error-object-irritants e))) (+ 1 \"a\"))";
+        assert_eq!(show(input), "(a)");
+        let input = "(guard (e (#t (error-object-message e))) (error \"bad thing:\" 1 2))";
+        assert_eq!(show(input), "bad thing:");
+        let input = "(guard (e (#t (error-object-irritants e))) (error \"bad thing:\" 1 2))";
+        assert_eq!(show(input), "(1 2)");
+        assert_eq!(
+            run_last("(guard (e (#t (error-object? e))) (raise 1))"),
+            LispVal::Bool(false)
+        );
+        assert_eq!(
+            run_last("(guard (e (#t (file-error? e))) (error \"x\"))"),
+            LispVal::Bool(false)
+        );
+        let err = run("(error \"bad thing:\" 1 2)").unwrap_err();
+        assert_eq!(
+            err.kind,
+            ErrorKind::User {
+                message: "bad thing:".to_owned(),
+                irritants: vec![LispVal::Number(1), LispVal::Number(2)]
+            }
+        );
+        // raising a caught error object again keeps the original error
+        let err = run("(guard (e (#f 0)) (nothing 1))").unwrap_err();
+        assert_eq!(
+            err.kind,
+            ErrorKind::UnboundVariable(Symbol::intern("nothing"))
+        );
+        let err = run("(guard (e ((raise e) 0)) (/ 1 0))").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::DivisionByZero);
+    }
+
+    #[test]
+    fn eval_with_exception_handler() {
+        let input =
+            "(with-exception-handler (lambda (c) 42) (lambda () (+ (raise-continuable 'oops) 1)))";
+        assert_eq!(run_last(input), LispVal::Number(43));
+        // a handler that returns from raise is itself an error
+        let input = "(with-exception-handler (lambda (c) 0) (lambda () (raise 'oops)))";
+        assert!(matches!(
+            run(input).unwrap_err().kind,
+            ErrorKind::HandlerReturned(_)
+        ));
+        let input = "(define seen 0)
+                     (guard (e (#t e))
+                       (with-exception-handler
+                         (lambda (c) (set! seen c))
+                         (lambda () (raise 7))))
+                     seen";
+        assert_eq!(run_last(input), LispVal::Number(7));
+        // handlers run with the outer handlers installed
+        let input = "(with-exception-handler (lambda (c) (* c 10))
+                       (lambda () (with-exception-handler (lambda (c) (raise-continuable (+ c 1)))
+                                    (lambda () (raise-continuable 1)))))";
+        assert_eq!(run_last(input), LispVal::Number(20));
+        // an error raised by the handler is not handled by the same handler again
+        let input = "(guard (e (#t e))
+                       (with-exception-handler (lambda (c) (raise (+ c 1)))
+                         (lambda () (raise-continuable 1))))";
+        assert_eq!(run_last(input), LispVal::Number(2));
+        let input = "(guard (e (#t e)) (with-exception-handler (lambda (c) (raise 'inner)) (lambda () (car))))";
+        assert_eq!(symbol(&run_last(input)), "inner");
+    }
+
+    #[test]
+    fn eval_error_backtraces() {
+        let input = "(define (check x) (if (< x 0) (error \"negative:\" x) x))
+                     (define (count n) (if (= n 0) (check -1) (+ 1 (count (- n 1)))))
+                     (count 3)";
+        let err = run(input).unwrap_err();
+        let names: Vec<_> = err.backtrace.iter().map(|x| x.name.clone()).collect();
+        let count = Some("count".to_owned());
+        assert_eq!(
+            names,
+            vec![
+                count.clone(),
+                count.clone(),
+                count.clone(),
+                Some("check".to_owned())
+            ]
+        );
+        assert_eq!(
+            err.backtrace[0].call_site,
+            Some(Location {
+                line: 3,
+                column: 23
+            })
+        );
+        // the call to check is a tail call, so it replaced the frame of the last count
+        assert_eq!(err.backtrace[3].tail_calls, 1);
+        assert!(err
+            .backtrace_report()
+            .contains("in count called at line 2, column 69 (repeated 1 more times)"));
+    }
+
+    #[test]
+    fn eval_backtraces_collapse_tail_calls() {
+        let input = "(let loop ((i 0)) (if (= i 1000) (car i) (loop (+ i 1))))";
+        let err = run(input).unwrap_err();
+        assert_eq!(err.backtrace.len(), 1);
+        assert_eq!(err.backtrace[0].name, Some("loop".to_owned()));
+        assert_eq!(err.backtrace[0].tail_calls, 1000);
+        // calls that already returned are not part of the backtrace
+        let input = "(define (f) 1) (define g (lambda () (f) (car))) (g)";
+        let err = run(input).unwrap_err();
+        let names: Vec<_> = err.backtrace.iter().map(|x| x.name.clone()).collect();
+        assert_eq!(names, vec![Some("g".to_owned())]);
+        assert!(run("(car)").unwrap_err().backtrace.is_empty());
+    }
+
+    #[test]
+    fn eval_fuel_limit() {
+        let mut env = Environment::init_env();
+        env.set_limits(Limits {
+            fuel: Some(10_000),
+            ..Limits::default()
+        });
+        let err = run_in("(define (f) (f)) (f)", &mut env).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LimitExceeded(Limit::Fuel));
+        // the environment keeps its definitions and the fuel is counted again for each program
+        let input = "(define (count n) (if (= n 0) 'done (count (- n 1)))) (count 100)";
+        assert_eq!(
+            symbol(run_in(input, &mut env).unwrap().last().unwrap()),
+            "done"
+        );
+        assert_eq!(
+            run_in("(+ 1 2)", &mut env).unwrap(),
+            vec![LispVal::Number(3)]
+        );
+        assert!(run_in("(count 100000)", &mut env).is_err());
+        env.set_limits(Limits::default());
+        assert_eq!(
+            symbol(&run_in("(count 100000)", &mut env).unwrap()[0]),
+            "done"
+        );
+    }
+
+    #[test]
+    fn eval_depth_limit() {
+        let mut env = Environment::init_env();
+        env.set_limits(Limits {
+            max_depth: Some(100),
+            ..Limits::default()
+        });
+        let input = "(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1)))))";
+        run_in(input, &mut env).unwrap();
+        let err = run_in("(f 1000)", &mut env).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LimitExceeded(Limit::Depth));
+        assert_eq!(
+            run_in("(f 50)", &mut env).unwrap(),
+            vec![LispVal::Number(50)]
+        );
+        // tail calls do not nest, so loops are not limited by the depth
+        let input = "(let loop ((i 0)) (if (= i 5000) i (loop (+ i 1))))";
+        assert_eq!(
+            run_in(input, &mut env).unwrap(),
+            vec![LispVal::Number(5000)]
+        );
+        // the error is a condition like any other once the stack has unwound
+        let input = "(guard (e (#t (error-object-message e))) (f 1000))";
+        let message = run_in(input, &mut env).unwrap().pop().unwrap();
+        assert_eq!(
+            message.show_val().trim_end(),
+            "maximum evaluation depth exceeded"
+        );
+    }
+
+    #[test]
+    fn eval_stack_limit_on_a_default_thread() {
+        // spawned threads get a 2MB stack, less than the depth limit alone would need
+        let depth_error = |result: Result<Vec<LispVal>, EvalError>| {
+            result.is_err_and(|e| e.kind == ErrorKind::LimitExceeded(Limit::Depth))
+        };
+        let input = "(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1)))))";
+        let (shallow, deep, unbounded) = std::thread::spawn(move || {
+            let mut env = Environment::init_env();
+            run_in(input, &mut env).unwrap();
+            let shallow = run_in("(f 10)", &mut env).as_deref() == Ok(&[LispVal::Number(10)]);
+            // past the default depth limit whichever limit stops it first
+            let deep = depth_error(run_in("(f 2000)", &mut env));
+            env.set_limits(Limits {
+                max_depth: None,
+                ..Limits::default()
+            });
+            // only the stack limit is left to stop this
+            (shallow, deep, depth_error(run_in("(f 100000)", &mut env)))
+        })
+        .join()
+        .expect("the interpreter thread should not overflow its stack");
+        assert!(shallow);
+        assert!(deep);
+        assert!(unbounded);
+    }
+
+    #[test]
+    fn eval_dropping_the_environment_frees_closures() {
+        let mut env = Environment::init_env();
+        let input = "(define (f n) (if (= n 0) 0 (f (- n 1)))) \
+                     (define (make) (define (g) g) g) \
+                     (list f (make))";
+        let list = run_in(input, &mut env).unwrap().pop().unwrap();
+        let closures = crate::list::list_items(&list).unwrap();
+        let closures: Vec<Rc<Lambda>> = closures
+            .into_iter()
+            .map(|x| match x {
+                LispVal::Lamda(x) => x,
+                x => panic!("expected a closure got {:?}", x),
+            })
+            .collect();
+        drop(list);
+        // each is also bound in the frame it closes over
+        assert!(closures.iter().all(|x| Rc::strong_count(x) == 2));
+        drop(env);
+        assert!(closures.iter().all(|x| Rc::strong_count(x) == 1));
+    }
+
+    #[test]
+    fn eval_heap_and_time_limits() {
+        let mut env = Environment::init_env();
+        env.set_limits(Limits {
+            max_heap: Some(1 << 20),
+            ..Limits::default()
+        });
+        let input = "(let loop ((i 0)) (loop ((lambda args i) 1 2 3)))";
+        let err = run_in(input, &mut env).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LimitExceeded(Limit::Heap));
+        // too large to make at all, which is not the heap limit
+        let err = run("(make-string 1000000000000 #\\a)").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::AllocationTooLarge(1000000000000));
+        env.set_limits(Limits {
+            timeout: Some(Duration::from_millis(20)),
+            ..Limits::default()
+        });
+        let err = run_in("(define (f) (f)) (f)", &mut env).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LimitExceeded(Limit::Time));
+        assert_eq!(
+            run_in("(+ 1 2)", &mut env).unwrap(),
+            vec![LispVal::Number(3)]
+        );
+    }
+
+    #[test]
+    fn eval_time_limit_stops_bignum_division() {
+        let mut env = Environment::init_env();
+        run_in(
+            "(define x (expt 7 300000)) (define y (+ (expt 3 140000) 1))",
+            &mut env,
+        )
+        .unwrap();
+        env.set_limits(Limits {
+            timeout: Some(Duration::from_millis(50)),
+            ..Limits::default()
+        });
+        // each takes seconds without the limit
+        for input in ["(exact-integer-sqrt x)", "(/ x y)", "(gcd x y)"] {
+            let start = Instant::now();
+            let err = run_in(input, &mut env).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::LimitExceeded(Limit::Time), "{}", input);
+            assert!(start.elapsed() < Duration::from_secs(1), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_interrupt_from_another_thread() {
+        let mut env = Environment::init_env();
+        let interrupt_soon = |env: &Environment| {
+            let handle = env.interrupt_handle();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                handle.interrupt();
+            })
+        };
+        let interrupter = interrupt_soon(&env);
+        let err = run_in("(define (f) (f)) (f)", &mut env).unwrap_err();
+        interrupter.join().unwrap();
+        assert_eq!(err.kind, ErrorKind::Interrupted);
+        // the interrupt is used up, later evaluations run normally
+        assert_eq!(
+            run_in("(+ 1 2)", &mut env).unwrap(),
+            vec![LispVal::Number(3)]
+        );
+        // handlers in the program cannot catch it and carry on
+        let input = "(define (spin) (spin))
+            (define (f) (guard (e (#t (f))) (spin)))
+            (define (g) (with-exception-handler (lambda (e) (g)) spin))";
+        run_in(input, &mut env).unwrap();
+        for input in ["(f)", "(g)"] {
+            let interrupter = interrupt_soon(&env);
+            let err = run_in(input, &mut env).unwrap_err();
+            interrupter.join().unwrap();
+            assert_eq!(err.kind, ErrorKind::Interrupted);
+        }
+        env.interrupt_handle().interrupt();
+        env.interrupt_handle().clear();
+        assert_eq!(
+            run_in("(+ 1 2)", &mut env).unwrap(),
+            vec![LispVal::Number(3)]
+        );
+    }
+
+    #[test]
+    fn eval_bigint_promotion() {
+        assert_eq!(
+            show("(* 99999999999 99999999999)"),
+            "9999999999800000000001"
+        );
+        assert_eq!(show("(* 9223372036854775807 2)"), "18446744073709551614");
+        assert_eq!(show("(/ -9223372036854775808 -1)"), "9223372036854775808");
+        assert_eq!(show("(- -9223372036854775808 1)"), "-9223372036854775809");
+        assert_eq!(
+            run_last("(- (+ 9223372036854775807 1) 1)"),
+            LispVal::Number(i64::MAX)
+        );
+        assert_eq!(
+            run_last("(/ 100000000000000000000000 10000000000000000000000)"),
+            LispVal::Number(10)
+        );
+        assert_eq!(
+            run_last("(< 9223372036854775807 100000000000000000000)"),
+            LispVal::Bool(true)
+        );
+        assert_eq!(
+            run_last("(= 100000000000000000000 (* 10000000000 10000000000))"),
+            LispVal::Bool(true)
+        );
+    }
+
+    #[test]
+    fn read_bigint_literals() {
+        assert_eq!(
+            run_last("-123456789012345678901234567890")
+                .show_val()
+                .trim_end(),
+            "-123456789012345678901234567890"
+        );
+        assert_eq!(run_last("00000000000000000000042"), LispVal::Number(42));
+        assert_eq!(
+            run_last("(/ 4 100000000000000000000)")
+                .show_val()
+                .trim_end(),
+            "1/25000000000000000000"
+        );
+    }
+
+    #[test]
+    fn eval_numeric_contagion() {
+        assert_eq!(show("(+ 1 2.5)"), "3.5");
+        assert_eq!(show("(* 2 1.5)"), "3.0");
+        assert_eq!(show("(- 1/2 1/3)"), "1/6");
+        assert_eq!(show("(+ 1/2 1/2)"), "1");
+        assert_eq!(show("(* 2/3 0.5)"), "0.3333333333333333");
+        assert_eq!(show("(/ 1/2 3)"), "1/6");
+        assert_eq!(show("(/ 1 2.0)"), "0.5");
+        assert_eq!(show("(+ 1000000000000000000000 0.5)"), "1e21");
+        assert_eq!(run_last("(< 1/3 0.34)"), LispVal::Bool(true));
+        assert_eq!(run_last("(= 1/2 0.5)"), LispVal::Bool(true));
+        assert_eq!(
+            run_last("(= 9007199254740993 9007199254740992.0)"),
+            LispVal::Bool(false)
+        );
+        assert_eq!(run_last("(< 1 +inf.0)"), LispVal::Bool(true));
+        assert_eq!(run_last("(= +nan.0 +nan.0)"), LispVal::Bool(false));
+    }
+
+    #[test]
+    fn eval_arithmetic_arity() {
+        assert_eq!(run_last("(+)"), LispVal::Number(0));
+        assert_eq!(run_last("(*)"), LispVal::Number(1));
+        assert_eq!(show("(+ 1/2)"), "1/2");
+        assert_eq!(show("(* 2.5)"), "2.5");
+        assert_eq!(run_last("(- 5)"), LispVal::Number(-5));
+        assert_eq!(show("(- 1/2)"), "-1/2");
+        assert_eq!(show("(- 0.0)"), "-0.0");
+        assert_eq!(show("(- -9223372036854775808)"), "9223372036854775808");
+        assert_eq!(show("(- 1+2i)"), "-1.0-2.0i");
+        assert!(run("(-)").is_err());
+        assert!(run("(+ 'a)").is_err());
+        assert!(run("(* \"2\")").is_err());
+    }
+
+    #[test]
+    fn eval_exactness() {
+        assert_eq!(show("(exact 2.5)"), "5/2");
+        assert_eq!(show("(exact 3.0)"), "3");
+        assert_eq!(show("(inexact 1/4)"), "0.25");
+        assert_eq!(show("(inexact 1)"), "1.0");
+        assert_eq!(show("(exact? 1/2)"), "#t");
+        assert_eq!(show("(inexact? 1/2)"), "#f");
+        assert_eq!(show("(exact-integer? 100000000000000000000)"), "#t");
+        assert_eq!(show("(exact-integer? 2.0)"), "#f");
+        assert_eq!(show("(integer? 2.0)"), "#t");
+        assert_eq!(show("(integer? \"2\")"), "#f");
+        assert_eq!(show("(rational? 1.5)"), "#t");
+        assert_eq!(show("(rational? +inf.0)"), "#f");
+        assert_eq!(show("(nan? (- +inf.0 +inf.0))"), "#t");
+        assert_eq!(show("(infinite? -inf.0)"), "#t");
+        assert_eq!(show("(finite? 1/3)"), "#t");
+        let kind = |input: &str| run(input).unwrap_err().kind;
+        assert!(matches!(
+            kind("(exact +nan.0)"),
+            ErrorKind::WrongType { .. }
+        ));
+        assert!(matches!(kind("(exact? #t)"), ErrorKind::WrongType { .. }));
+    }
+
+    #[test]
+    fn eval_division() {
+        assert_eq!(show("(/ 1 3)"), "1/3");
+        assert_eq!(show("(/ 6 3)"), "2");
+        assert_eq!(show("(/ 5)"), "1/5");
+        assert_eq!(show("(/ 0.5)"), "2.0");
+        assert_eq!(show("(/ 60 2 3)"), "10");
+        assert_eq!(show("(/ 1.0 0.0)"), "+inf.0");
+        let kind = |input: &str| run(input).unwrap_err().kind;
+        assert_eq!(kind("(/ 0 0)"), ErrorKind::DivisionByZero);
+        assert_eq!(kind("(/ 1.5 0)"), ErrorKind::DivisionByZero);
+        assert_eq!(kind("(/ 0)"), ErrorKind::DivisionByZero);
+        assert!(matches!(kind("(/)"), ErrorKind::Arity { .. }));
+    }
+
+    #[test]
+    fn eval_integer_division() {
+        assert_eq!(show("(quotient -7 2)"), "-3");
+        assert_eq!(show("(remainder -7 2)"), "-1");
+        assert_eq!(show("(modulo -7 2)"), "1");
+        assert_eq!(show("(modulo 7 -2)"), "-1");
+        assert_eq!(show("(floor/ -7 2)"), "-4 1");
+        assert_eq!(show("(truncate/ -7 2)"), "-3 -1");
+        assert_eq!(show("(floor-quotient 7 -2)"), "-4");
+        assert_eq!(show("(floor-remainder -9223372036854775808 -1)"), "0");
+        assert_eq!(
+            show("(floor-quotient -9223372036854775808 -1)"),
+            "9223372036854775808"
+        );
+        assert_eq!(show("(modulo -100000000000000000001 10)"), "9");
+        assert_eq!(show("(quotient 7.0 2)"), "3.0");
+        assert_eq!(show("(modulo -7.0 2)"), "1.0");
+        assert_eq!(show("(exact-integer-sqrt 17)"), "4 1");
+        assert_eq!(
+            show("(exact-integer-sqrt 100000000000000000000000000000000000000)"),
+            "10000000000000000000 0"
+        );
+        let kind = |input: &str| run(input).unwrap_err().kind;
+        assert_eq!(kind("(modulo 1 0)"), ErrorKind::DivisionByZero);
+        assert!(matches!(
+            kind("(quotient 1/2 1)"),
+            ErrorKind::WrongType { .. }
+        ));
+        assert!(matches!(
+            kind("(exact-integer-sqrt -1)"),
+            ErrorKind::WrongType { .. }
+        ));
+    }
+
+    #[test]
+    fn eval_comparison_chains() {
+        assert_eq!(run_last("(< 1 2 3 4)"), LispVal::Bool(true));
+        assert_eq!(run_last("(< 1 3 2)"), LispVal::Bool(false));
+        assert_eq!(run_last("(<= 1 1 2.0 5/2)"), LispVal::Bool(true));
+        assert_eq!(run_last("(= 1 1.0 2/2)"), LispVal::Bool(true));
+        assert_eq!(run_last("(> 3 2 2)"), LispVal::Bool(false));
+        assert_eq!(run_last("(>= 3 2 2 -1/2)"), LispVal::Bool(true));
+        assert_eq!(run_last("(< 1 +nan.0 2)"), LispVal::Bool(false));
+        assert_eq!(run_last("(>= +nan.0 +nan.0)"), LispVal::Bool(false));
+        assert_eq!(
+            run_last("(< -inf.0 -100000000000000000000 0 1/3 +inf.0)"),
+            LispVal::Bool(true)
+        );
+        let kind = |input: &str| run(input).unwrap_err().kind;
+        assert_eq!(
+            kind("(< 2 1 \"a\")").to_string(),
+            "wrong type: expected a number but got a"
+        );
+        assert!(matches!(kind("(= 1)"), ErrorKind::Arity { .. }));
+    }
+
+    #[test]
+    fn eval_math_library() {
+        assert_eq!(show("(abs -9223372036854775808)"), "9223372036854775808");
+        assert_eq!(show("(abs -1/2)"), "1/2");
+        assert_eq!(show("(abs -0.0)"), "0.0");
+        assert_eq!(show("(min 3 1 2)"), "1");
+        assert_eq!(show("(max 3 4.0)"), "4.0");
+        assert_eq!(show("(max 1 +nan.0)"), "+nan.0");
+        assert_eq!(show("(gcd 32 -36)"), "4");
+        assert_eq!(show("(gcd)"), "0");
+        assert_eq!(show("(lcm 32 -36)"), "288");
+        assert_eq!(show("(lcm 4.0 6)"), "12.0");
+        assert_eq!(show("(expt 2 100)"), "1267650600228229401496703205376");
+        assert_eq!(show("(expt 2/3 -2)"), "9/4");
+        assert_eq!(show("(expt 2.0 3)"), "8.0");
+        assert_eq!(show("(expt 4 0.5)"), "2.0");
+        assert_eq!(show("(square 1/3)"), "1/9");
+        assert_eq!(show("(sqrt 16)"), "4");
+        assert_eq!(show("(sqrt 9/4)"), "3/2");
+        assert_eq!(show("(sqrt 2)"), "1.4142135623730951");
+        assert_eq!(show("(exp 0)"), "1.0");
+        assert_eq!(show("(log 1)"), "0.0");
+        assert_eq!(show("(log 100 10)"), "2.0");
+        assert_eq!(show("(round (log (expt 10 400)))"), "921.0");
+        assert_eq!(show("(atan 1 1)"), "0.7853981633974483");
+        assert_eq!(show("(sin 0)"), "0.0");
+        let kind = |input: &str| run(input).unwrap_err().kind;
+        assert_eq!(kind("(expt 0 -1)"), ErrorKind::DivisionByZero);
+        assert!(matches!(kind("(gcd 1/2)"), ErrorKind::WrongType { .. }));
+        assert!(matches!(kind("(log 1 2 3)"), ErrorKind::Arity { .. }));
+    }
+
+    #[test]
+    fn eval_rounding() {
+        assert_eq!(show("(floor -7/2)"), "-4");
+        assert_eq!(show("(ceiling -7/2)"), "-3");
+        assert_eq!(show("(truncate -7/2)"), "-3");
+        assert_eq!(show("(round -7/2)"), "-4");
+        assert_eq!(show("(round 5/2)"), "2");
+        assert_eq!(show("(round 7/3)"), "2");
+        assert_eq!(show("(round 2.5)"), "2.0");
+        assert_eq!(show("(round 3.5)"), "4.0");
+        assert_eq!(show("(floor 2.7)"), "2.0");
+        assert_eq!(show("(round 7)"), "7");
+        assert_eq!(show("(numerator 6/4)"), "3");
+        assert_eq!(show("(denominator 6/4)"), "2");
+        assert_eq!(show("(denominator 5)"), "1");
+        assert_eq!(show("(denominator 0.5)"), "2.0");
+    }
+
+    #[test]
+    fn eval_number_predicates() {
+        assert_eq!(show("(zero? 0.0)"), "#t");
+        assert_eq!(show("(zero? 1/2)"), "#f");
+        assert_eq!(show("(positive? 100000000000000000000)"), "#t");
+        assert_eq!(show("(negative? -1/2)"), "#t");
+        assert_eq!(show("(positive? +nan.0)"), "#f");
+        assert_eq!(show("(odd? -3)"), "#t");
+        assert_eq!(show("(even? 100000000000000000000)"), "#t");
+        assert_eq!(show("(even? 4.0)"), "#t");
+        let kind = |input: &str| run(input).unwrap_err().kind;
+        assert!(matches!(kind("(odd? 1.5)"), ErrorKind::WrongType { .. }));
+        assert!(matches!(kind("(zero? \"0\")"), ErrorKind::WrongType { .. }));
+    }
+
+    #[test]
+    fn eval_number_string_conversion() {
+        assert_eq!(show("(number->string 255 16)"), "ff");
+        assert_eq!(show("(number->string -5/3 2)"), "-101/11");
+        assert_eq!(show("(number->string 1.5)"), "1.5");
+        assert_eq!(show("(string->number \"ff\" 16)"), "255");
+        assert_eq!(show("(string->number \"-101/11\" 2)"), "-5/3");
+        assert_eq!(show("(string->number \"1e3\")"), "1000.0");
+        assert_eq!(show("(string->number \"12x\")"), "#f");
+        assert_eq!(show("(string->number \"2\" 2)"), "#f");
+        let kind = |input: &str| run(input).unwrap_err().kind;
+        assert!(matches!(
+            kind("(number->string 1 3)"),
+            ErrorKind::WrongType { .. }
+        ));
+        assert!(matches!(
+            kind("(number->string 1.5 2)"),
+            ErrorKind::WrongType { .. }
+        ));
+    }
+
+    #[test]
+    fn eval_expt_respects_heap_limit() {
+        let mut env = Environment::init_env();
+        env.set_limits(Limits {
+            max_heap: Some(1 << 20),
+            ..Limits::default()
+        });
+        let err = run_in("(expt 2 1000000000)", &mut env).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LimitExceeded(Limit::Heap));
+    }
+
+    #[test]
+    fn eval_bignum_arithmetic_respects_limits() {
+        // each squaring doubles the size of x, so the last multiplications alone would run for
+        // hours
+        let input = "(define (sq x n) (if (= n 0) (integer-length x) (sq (* x x) (- n 1))))
+            (sq 3 40)";
+        let limits = [
+            (
+                Limits {
+                    fuel: Some(1000),
+                    ..Limits::default()
+                },
+                Limit::Fuel,
+            ),
+            (
+                Limits {
+                    max_heap: Some(1 << 16),
+                    ..Limits::default()
+                },
+                Limit::Heap,
+            ),
+            (
+                Limits {
+                    timeout: Some(Duration::from_millis(500)),
+                    ..Limits::default()
+                },
+                Limit::Time,
+            ),
+        ];
+        for (limits, limit) in limits {
+            let mut env = Environment::init_env();
+            env.set_limits(limits);
+            let err = run_in(input, &mut env).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::LimitExceeded(limit));
+        }
+        let mut env = Environment::init_env();
+        env.set_limits(Limits {
+            fuel: Some(1000),
+            ..Limits::default()
+        });
+        let input = "(define x (expt 7 200000)) (string-length (number->string x))";
+        let err = run_in(input, &mut env).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LimitExceeded(Limit::Fuel));
+    }
+
+    #[test]
+    fn eval_complex_numbers() {
+        assert_eq!(show("1+2i"), "1.0+2.0i");
+        assert_eq!(show("-i"), "0.0-1.0i");
+        assert_eq!(show("1.5e2-2.5i"), "150.0-2.5i");
+        assert_eq!(show("3+0i"), "3");
+        assert_eq!(show("2@0"), "2");
+        assert_eq!(show("(+ 1+2i 3-1i)"), "4.0+1.0i");
+        assert_eq!(show("(* +i +i)"), "-1.0");
+        assert_eq!(show("(/ 1+2i 3+4i)"), "0.44+0.08i");
+        assert_eq!(show("(sqrt -4)"), "0.0+2.0i");
+        assert_eq!(show("(expt -8 1/3)"), "1.0+1.732050807568877i");
+        assert_eq!(show("(expt 1+i 2)"), "0.0+2.0i");
+        assert_eq!(show("(log -1)"), "0.0+3.141592653589793i");
+        assert_eq!(
+            show("(exp +3.141592653589793i)"),
+            "-1.0+1.2246467991473532e-16i"
+        );
+        assert_eq!(show("(asin 2)"), "1.5707963267948966-1.3169578969248166i");
+        assert_eq!(show("(magnitude 3+4i)"), "5.0");
+        assert_eq!(show("(magnitude -5)"), "5");
+        assert_eq!(show("(angle -1)"), "3.141592653589793");
+        assert_eq!(show("(angle 1)"), "0");
+        assert_eq!(show("(real-part 1+2i)"), "1.0");
+        assert_eq!(show("(imag-part 1+2i)"), "2.0");
+        assert_eq!(show("(imag-part 5)"), "0");
+        assert_eq!(show("(make-rectangular 1 2)"), "1.0+2.0i");
+        assert_eq!(show("(make-rectangular 1/2 0)"), "1/2");
+        assert_eq!(show("(make-polar 2 0)"), "2");
+        assert_eq!(show("(= 1+2i (make-rectangular 1 2))"), "#t");
+        assert_eq!(show("(complex? 1+2i)"), "#t");
+        assert_eq!(show("(real? 1+2i)"), "#f");
+        assert_eq!(show("(exact? 1+2i)"), "#f");
+        assert_eq!(show("(nan? (make-rectangular 1 +nan.0))"), "#t");
+        assert_eq!(show("(string->number \"1-2i\")"), "1.0-2.0i");
+        let kind = |input: &str| run(input).unwrap_err().kind;
+        assert!(matches!(kind("(< 1+i 2)"), ErrorKind::WrongType { .. }));
+        assert!(matches!(kind("(exact 1+i)"), ErrorKind::WrongType { .. }));
+        assert!(matches!(kind("(floor 1+i)"), ErrorKind::WrongType { .. }));
+    }
+
+    #[test]
+    fn eval_equivalence_predicates() {
+        assert_eq!(show("(eq? 'a 'a)"), "#t");
+        assert_eq!(show("(eq? '() '())"), "#t");
+        assert_eq!(show("(eq? (list 1) (list 1))"), "#f");
+        assert_eq!(show("(let ((p (list 1))) (eq? p p))"), "#t");
+        assert_eq!(show("(eq? car car)"), "#t");
+        assert_eq!(show("(let ((f (lambda () 1))) (eq? f f))"), "#t");
+        assert_eq!(show("(eq? (lambda () 1) (lambda () 1))"), "#f");
+        assert_eq!(
+            show("(eqv? 100000000000000000000 100000000000000000000)"),
+            "#t"
+        );
+        assert_eq!(show("(eqv? 1/2 (/ 2 4))"), "#t");
+        assert_eq!(show("(eqv? 2 2.0)"), "#f");
+        assert_eq!(show("(eqv? 0.0 -0.0)"), "#f");
+        assert_eq!(show("(eqv? +nan.0 +nan.0)"), "#t");
+        assert_eq!(show("(let ((s \"ab\")) (eqv? s s))"), "#t");
+        assert_eq!(show("(eqv? \"ab\" (string-copy \"ab\"))"), "#f");
+        assert_eq!(show("(equal? \"ab\" (string-copy \"ab\"))"), "#t");
+        assert_eq!(
+            show("(equal? '(1 (2 3) \"x\") (list 1 (list 2 3) \"x\"))"),
+            "#t"
+        );
+        assert_eq!(show("(equal? '(1 . 2) '(1 . 3))"), "#f");
+        assert_eq!(show("(equal? 2 2.0)"), "#f");
+        // circular lists are compared without going around them forever
+        let input = "(define a (list 1 2)) (set-cdr! (cdr a) a)
+                     (define b (list 1 2 1 2)) (set-cdr! (cdr (cddr b)) b)";
+        assert_eq!(show(&format!("{} (equal? a b)", input)), "#t");
+        assert_eq!(
+            show(&format!("{} (set-car! b 5) (equal? a b)", input)),
+            "#f"
+        );
+        // so is == on the values themselves, which holds only for the same pair
+        let mut values = run(&format!("{} a b", input)).unwrap();
+        let (b, a) = (values.pop().unwrap(), values.pop().unwrap());
+        assert!(a == a.clone() && a != b);
+        assert_eq!(show("(memq 'c '(a b c))"), "(c)");
+        assert_eq!(show("(memv 1.0 '(1 1.0))"), "(1.0)");
+        assert_eq!(show("(assoc (list 'b) '(((a) 1) ((b) 2)))"), "((b) 2)");
+        assert_eq!(show("(case 2.0 ((2) 'exact) ((2.0) 'inexact))"), "inexact");
     }
 }
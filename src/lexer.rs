@@ -1,44 +1,62 @@
-use crate::token::{Token, TokenType};
+use crate::token::{Location, Token, TokenType};
 use std::collections::HashMap;
+use std::fmt;
 
 #[derive(Debug)]
 pub enum LexerError {
     UnknownToken(String),
     MultiLine(String),
     ReadString(String),
-    ReadIdentifier(String),
-    ReadNumber(String),
+}
+
+impl fmt::Display for LexerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexerError::UnknownToken(x) => write!(f, "unknown token: {}", x),
+            LexerError::MultiLine(x) | LexerError::ReadString(x) => write!(f, "{}", x),
+        }
+    }
 }
 
 pub struct Lexer {
-    pub input: String,
+    pub input: Vec<char>,
     pub position: usize,
     pub read_position: usize,
     pub ch: char,
     pub keyword: HashMap<String, TokenType>,
     pub end: bool,
+    line_starts: Vec<usize>, // Position of the first char of every line
 }
 
 impl Lexer {
     pub fn init_lex(input: String) -> Self {
         let keys = generate_keywords();
-        let c = input.as_bytes()[0].clone();
-        Lexer {
-            input: input,
+        let input: Vec<char> = input.chars().collect();
+        let mut line_starts = vec![0];
+        for (i, ch) in input.iter().enumerate() {
+            if *ch == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        let mut lex = Lexer {
+            input,
             position: 0,
-            read_position: 1,
-            ch: c as char,
+            read_position: 0,
+            ch: '\0',
             keyword: keys,
             end: false,
-        }
+            line_starts,
+        };
+        lex.read_char();
+        lex
     }
 
     pub fn read_char(&mut self) {
         if self.read_position >= self.input.len() {
+            self.ch = '\0';
             self.end = true;
-            return;
         } else {
-            self.ch = self.input.as_bytes()[self.read_position] as char;
+            self.ch = self.input[self.read_position];
         }
         self.position = self.read_position;
         self.read_position += 1;
@@ -48,76 +66,47 @@ impl Lexer {
         let mut tok: Token = Token {
             literal: "".to_owned(),
             kind: TokenType::ILLEGAL,
+            loc: Location::default(),
         };
         while self.ch.is_whitespace() {
             self.read_char();
         }
-        if self.end == true {
+        let loc = self.location();
+        if self.end {
             tok = self.new_token("\0".to_owned(), TokenType::EOF);
+            tok.loc = loc;
             return Ok(tok);
         }
         // INFO: whitespaces are importart in Scheme so skipping whitespaces at the beginning
         // only to avoid the default error
         match self.ch {
+            '#' if self.peek_ch() == '\\' => {
+                tok.kind = TokenType::CHAR;
+                tok.literal = self.read_character()?;
+            }
             '#' => {
-                if self.peek_ch() == 'f' {
-                    tok = self.new_token("#f".to_owned(), TokenType::FALSE);
-                    self.read_char();
-                } else if self.peek_ch() == 't' {
-                    tok = self.new_token("#t".to_owned(), TokenType::TRUE);
-                    self.read_char();
-                } else {
-                    tok = self.new_token("#".to_owned(), TokenType::POUND);
+                let lit = self.read_identifier();
+                match lit.as_str() {
+                    "#t" | "#true" => tok = self.new_token(lit, TokenType::TRUE),
+                    "#f" | "#false" => tok = self.new_token(lit, TokenType::FALSE),
+                    "#" => tok = self.new_token(lit, TokenType::POUND),
+                    _ => return Err(LexerError::UnknownToken(lit)),
                 }
             }
             ',' => tok = self.new_token(",".to_owned(), TokenType::COMMA),
-            '@' => tok = self.new_token("@".to_owned(), TokenType::AT),
-            // Conditionals
-            '=' => tok = self.new_token("=".to_owned(), TokenType::EQ),
-            '<' => {
-                if self.peek_ch() == '=' {
-                    self.read_char();
-                    tok = self.new_token("<=".to_owned(), TokenType::LTEQ);
-                } else {
-                    tok = self.new_token("<".to_owned(), TokenType::LT);
-                }
-            }
-            '>' => {
-                if self.peek_ch() == '=' {
-                    self.read_char();
-                    tok = self.new_token(">=".to_owned(), TokenType::GTEQ);
-                } else {
-                    tok = self.new_token(">".to_owned(), TokenType::GT);
-                }
-            }
-
-            // Arithmetic Operations
-            '/' => tok = self.new_token("/".to_owned(), TokenType::SLASH),
-            '+' => tok = self.new_token("+".to_owned(), TokenType::PLUS),
-            '*' => tok = self.new_token("*".to_owned(), TokenType::ASTERICK),
             // List
             '(' => tok = self.new_token("(".to_owned(), TokenType::LPAREN),
             ')' => tok = self.new_token(")".to_owned(), TokenType::RPAREN),
             '\'' => tok = self.new_token("\'".to_owned(), TokenType::QUOTE),
+            ';' => {
+                self.read_single_line_comment();
+                tok = self.new_token("single-line-comment".to_owned(), TokenType::COMMENT);
+            }
 
             // Comments: following version 2 of write you a scheme (I think this is how comments are written in Haskell)
-            // FIXME: semicolons are supposed to be used for comments in scheme
-            // FIXME: also minus can lead to a negative number only if the next char is not a whitespace
-            '-' => {
-                // Check if single line comment
-                if self.peek_ch() == '-' {
-                    self.read_single_line_comment();
-                    tok = self.new_token("single-line-comment".to_owned(), TokenType::COMMENT);
-                } else if self.peek_ch().is_numeric() {
-                    tok.kind = TokenType::INT;
-                    let num = self.read_number();
-                    match num {
-                        Ok(s) => tok.literal = s,
-                        Err(s) => return Err(s),
-                    }
-                } else {
-                    tok = self.new_token("-".to_owned(), TokenType::MINUS);
-                }
+            '-' if self.peek_ch() == '-' => {
+                self.read_single_line_comment();
+                tok = self.new_token("single-line-comment".to_owned(), TokenType::COMMENT);
             }
             '{' => {
                 if self.peek_ch() != '-' {
@@ -125,50 +114,52 @@ impl Lexer {
                         "Multiline comment must start with a bracket followed by a minus symbol {-"
                             .to_owned(),
                     ));
-                } else {
-                    self.read_char();
-                    self.read_char(); // two are need to consume - and move to next minus char
-                    self.read_multiline_comment();
                 }
+                self.read_char();
+                self.read_char(); // two are need to consume - and move to next minus char
+                self.read_multiline_comment()?;
+                tok = self.new_token("multi-line-comment".to_owned(), TokenType::COMMENT);
             }
 
             // Read String
             '"' => {
                 tok.kind = TokenType::STRING;
-                let str = self.read_string();
-                match str {
-                    Ok(s) => tok.literal = s,
-                    Err(s) => return Err(s),
-                }
+                tok.literal = self.read_string()?;
             }
             _ => {
-                if self.ch.is_alphabetic() {
-                    let str = self.read_identifier();
-                    match str {
-                        Ok(s) => tok.literal = s,
-                        Err(s) => return Err(s),
-                    }
-                    tok.kind = self.lookup_identifier(tok.literal.as_str()); //self.lookup_identifier(tok.literal.as_str());
-                } else if self.ch.is_numeric() {
-                    tok.kind = TokenType::INT;
-                    let num = self.read_number();
-                    match num {
-                        Ok(s) => tok.literal = s,
-                        Err(s) => return Err(s),
-                    }
-                } else {
+                if !is_subsequent(self.ch) {
                     return Err(LexerError::UnknownToken(self.ch.to_string()));
                 }
+                tok.literal = self.read_identifier();
+                tok.kind = if is_number_start(&tok.literal) {
+                    TokenType::INT
+                } else {
+                    self.lookup_identifier(tok.literal.as_str())
+                };
             }
         }
         self.read_char();
+        tok.loc = loc;
         Ok(tok)
     }
 
     // Helper function to create tokens
     #[inline(always)]
     fn new_token(&self, lit: String, kind: TokenType) -> Token {
-        Token { literal: lit, kind }
+        Token {
+            literal: lit,
+            kind,
+            loc: Location::default(),
+        }
+    }
+
+    // Line and column of the current char
+    fn location(&self) -> Location {
+        let line = self.line_starts.partition_point(|x| *x <= self.position);
+        Location {
+            line,
+            column: self.position - self.line_starts[line - 1] + 1,
+        }
     }
 
     // Helper function to peek at next char (should be the read position)
@@ -177,107 +168,213 @@ impl Lexer {
         if self.read_position >= self.input.len() {
             '\0'
         } else {
-            self.input.as_bytes()[self.read_position] as char
+            self.input[self.read_position]
         }
     }
 
     // Helper function to skip through single line comments
     #[inline(always)]
     fn read_single_line_comment(&mut self) {
-        while self.ch != '\n' {
+        while self.ch != '\n' && !self.end {
             self.read_char();
         }
     }
 
     // Helper function to skip through multiline comments
-    fn read_multiline_comment(&mut self) {
-        while self.ch != '-' {
-            self.read_char();
-        }
-        if self.peek_ch() == '}' {
+    fn read_multiline_comment(&mut self) -> Result<(), LexerError> {
+        loop {
+            if self.end {
+                return Err(LexerError::MultiLine(
+                    "Multiline comment is missing its closing -}".to_owned(),
+                ));
+            }
+            if self.ch == '-' && self.peek_ch() == '}' {
+                self.read_char();
+                return Ok(());
+            }
             self.read_char();
-            return;
-        } else {
-            self.read_multiline_comment();
         }
     }
 
+    // Reads a string literal and replaces its escapes with the chars they stand for
     fn read_string(&mut self) -> Result<String, LexerError> {
-        let pos = self.position + 1; // After the beginning quotation
+        let mut text = String::new();
         self.read_char(); // Consume beginning quotation
-                          // TODO: Check if a loop is a good choice for this part
-                          // Used in Monkey lang lexer not sure its a good idea here
-        loop {
-            if self.ch == '"' || self.end {
-                break;
+        while self.ch != '"' {
+            if self.end {
+                return Err(LexerError::ReadString(
+                    "String is missing its closing quotation".to_owned(),
+                ));
+            }
+            if self.ch == '\\' {
+                self.read_char();
+                self.read_escape(&mut text)?;
+            } else {
+                text.push(self.ch);
             }
             self.read_char();
         }
-        let buf = &(self.input.as_bytes()[pos..self.position]);
-        let x = std::str::from_utf8(buf);
-        match x {
-            Ok(s) => Ok(s.to_owned()),
-            Err(err) => Err(LexerError::ReadString(err.to_string())),
+        Ok(text)
+    }
+
+    // Reads the escape whose backslash was just consumed and leaves the current char on its last
+    // char. A backslash at the end of a line joins the next line on, without the blanks around
+    // the line break
+    fn read_escape(&mut self, text: &mut String) -> Result<(), LexerError> {
+        if self.end {
+            return Err(LexerError::ReadString(
+                "String is missing its closing quotation".to_owned(),
+            ));
         }
+        let ch = match self.ch {
+            'a' => '\u{7}',
+            'b' => '\u{8}',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            '"' | '\\' | '|' => self.ch,
+            'x' => self.read_hex_escape()?,
+            ' ' | '\t' | '\r' | '\n' => {
+                while self.ch == ' ' || self.ch == '\t' {
+                    self.read_char();
+                }
+                if self.ch == '\r' && self.peek_ch() == '\n' {
+                    self.read_char();
+                }
+                if self.ch != '\n' && self.ch != '\r' {
+                    return Err(LexerError::ReadString(
+                        "Only blanks may follow a backslash that ends a line in a string"
+                            .to_owned(),
+                    ));
+                }
+                while self.peek_ch() == ' ' || self.peek_ch() == '\t' {
+                    self.read_char();
+                }
+                return Ok(());
+            }
+            ch => {
+                return Err(LexerError::ReadString(format!(
+                    "Unknown escape \\{} in string",
+                    ch
+                )))
+            }
+        };
+        text.push(ch);
+        Ok(())
     }
 
-    fn read_identifier(&mut self) -> Result<String, LexerError> {
-        let pos = self.position;
-        while self.ch.is_alphanumeric() {
+    // Reads the hex digits and the semicolon of an escape such as \x41;
+    fn read_hex_escape(&mut self) -> Result<char, LexerError> {
+        let mut digits = String::new();
+        while self.peek_ch().is_ascii_hexdigit() {
             self.read_char();
+            digits.push(self.ch);
         }
-        let buf = &(self.input.as_bytes()[pos..self.position]);
-        let x = std::str::from_utf8(buf);
-        self.position -= 1;
-        self.read_position -= 1;
-        match x {
-            Ok(s) => return Ok(s.to_owned()),
-            Err(err) => Err(LexerError::ReadIdentifier(err.to_string())),
+        self.read_char();
+        let code = u32::from_str_radix(&digits, 16).ok();
+        match code.and_then(char::from_u32) {
+            Some(ch) if self.ch == ';' => Ok(ch),
+            _ => Err(LexerError::ReadString(format!(
+                "Bad escape \\x{} in string, it should be hex digits ended by ;",
+                digits
+            ))),
         }
     }
 
-    fn lookup_identifier(&self, identifier: &str) -> TokenType {
-        let result = self.keyword.get(identifier);
-        match result {
-            Some(x) => *x,
-            None => TokenType::IDENT,
+    // Reads what follows #\ in a character literal. The first char is taken whatever it is, so #\(
+    // and #\  are characters too, and names such as space run on to the next delimiter
+    fn read_character(&mut self) -> Result<String, LexerError> {
+        self.read_char(); // Move from the # to the backslash
+        self.read_char();
+        if self.end {
+            return Err(LexerError::UnknownToken("#\\".to_owned()));
         }
+        let mut name = self.ch.to_string();
+        while is_subsequent(self.peek_ch()) {
+            self.read_char();
+            name.push(self.ch);
+        }
+        Ok(name)
     }
 
-    fn read_number(&mut self) -> Result<String, LexerError> {
+    // Reads identifiers, operators and number literals. They all run until the next delimiter
+    fn read_identifier(&mut self) -> String {
         let pos = self.position;
         self.read_char();
-        while self.ch.is_numeric() {
+        while is_subsequent(self.ch) {
             self.read_char();
         }
-        let buf = &(self.input.as_bytes()[pos..self.position]);
-        let x = std::str::from_utf8(buf);
-        // Decrement position and read position by one to compensate for the whole number for
-        // being consumed
+        let s = self.input[pos..self.position].iter().collect();
+        // Decrement position and read position by one to compensate for the last char being
+        // consumed by next_token
         self.position -= 1;
         self.read_position -= 1;
-        match x {
-            Ok(s) => return Ok(s.to_owned()),
-            Err(err) => Err(LexerError::ReadNumber(err.to_string())),
+        s
+    }
+
+    fn lookup_identifier(&self, identifier: &str) -> TokenType {
+        let result = self.keyword.get(identifier);
+        match result {
+            Some(x) => *x,
+            None => TokenType::IDENT,
         }
     }
 }
 
+// Characters that can continue an identifier or number once it has started
+fn is_subsequent(ch: char) -> bool {
+    ch.is_alphanumeric() || "!$%&*/:<=>?^_~+-.@#".contains(ch)
+}
+
+// Numbers start with a digit, or a sign or a dot directly followed by a digit
+fn is_number_start(lit: &str) -> bool {
+    let special = ["+inf.0", "-inf.0", "+nan.0", "-nan.0"];
+    if matches!(lit, "+i" | "-i") || special.iter().any(|x| lit.starts_with(x)) {
+        return true;
+    }
+    let mut chars = lit.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_digit() => true,
+        Some('+') | Some('-') | Some('.') => matches!(chars.next(), Some(c) if c.is_ascii_digit()),
+        _ => false,
+    }
+}
+
 fn generate_keywords() -> HashMap<String, TokenType> {
-    let keys = HashMap::from([
+    HashMap::from([
         ("let".to_owned(), TokenType::LET),
+        ("let*".to_owned(), TokenType::LETSTAR),
+        ("letrec".to_owned(), TokenType::LETREC),
+        ("letrec*".to_owned(), TokenType::LETRECSTAR),
+        ("let-values".to_owned(), TokenType::LETVALUES),
+        ("let*-values".to_owned(), TokenType::LETSTARVALUES),
         ("lambda".to_owned(), TokenType::LAMBDA),
+        ("case-lambda".to_owned(), TokenType::CASELAMBDA),
         ("if".to_owned(), TokenType::IF),
-        ("#t".to_owned(), TokenType::TRUE),
-        ("#f".to_owned(), TokenType::FALSE),
-        ("\'".to_owned(), TokenType::QUOTE),
+        ("cond".to_owned(), TokenType::COND),
+        ("case".to_owned(), TokenType::CASE),
+        ("when".to_owned(), TokenType::WHEN),
+        ("guard".to_owned(), TokenType::GUARD),
+        ("unless".to_owned(), TokenType::UNLESS),
         ("quote".to_owned(), TokenType::QUOTE),
         ("define".to_owned(), TokenType::DEFINE),
+        ("set!".to_owned(), TokenType::SET),
         ("begin".to_owned(), TokenType::BEGIN),
         ("else".to_owned(), TokenType::ELSE),
+        ("=>".to_owned(), TokenType::ARROW),
         ("and".to_owned(), TokenType::AND),
         ("or".to_owned(), TokenType::OR),
         ("not".to_owned(), TokenType::NOT),
-    ]);
-    keys
+        (".".to_owned(), TokenType::DOT),
+        ("@".to_owned(), TokenType::AT),
+        ("+".to_owned(), TokenType::PLUS),
+        ("-".to_owned(), TokenType::MINUS),
+        ("*".to_owned(), TokenType::ASTERICK),
+        ("/".to_owned(), TokenType::SLASH),
+        ("=".to_owned(), TokenType::EQ),
+        ("<".to_owned(), TokenType::LT),
+        ("<=".to_owned(), TokenType::LTEQ),
+        (">".to_owned(), TokenType::GT),
+        (">=".to_owned(), TokenType::GTEQ),
+    ])
 }
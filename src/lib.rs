@@ -0,0 +1,15 @@
+pub mod bigint;
+pub mod bitwise;
+pub mod character;
+pub mod error;
+pub mod eval;
+pub mod lexer;
+pub mod list;
+pub mod number;
+pub mod parser;
+pub mod random;
+pub mod string;
+pub mod symbol;
+#[cfg(test)]
+mod test_util;
+pub mod token;
@@ -0,0 +1,496 @@
+use std::mem::size_of;
+use std::rc::Rc;
+
+use crate::error::EvalError;
+use crate::eval::{
+    eval_proc, fixed_args, is_eq, is_equal, is_eqv, is_true, vec_to_list, Environment,
+};
+use crate::token::{Builtin, LispVal, Pair};
+
+// Pairs and the list procedures of R7RS. Lists are chains of pairs, so cons takes constant time
+// and lists can share their tails
+
+pub const LIST_BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "cons",
+        func: eval_cons,
+    },
+    Builtin {
+        name: "car",
+        func: eval_car,
+    },
+    Builtin {
+        name: "cdr",
+        func: eval_cdr,
+    },
+    Builtin {
+        name: "caar",
+        func: eval_caar,
+    },
+    Builtin {
+        name: "cadr",
+        func: eval_cadr,
+    },
+    Builtin {
+        name: "cdar",
+        func: eval_cdar,
+    },
+    Builtin {
+        name: "cddr",
+        func: eval_cddr,
+    },
+    Builtin {
+        name: "set-car!",
+        func: eval_set_car,
+    },
+    Builtin {
+        name: "set-cdr!",
+        func: eval_set_cdr,
+    },
+    Builtin {
+        name: "pair?",
+        func: eval_is_pair,
+    },
+    Builtin {
+        name: "null?",
+        func: eval_is_null,
+    },
+    Builtin {
+        name: "list?",
+        func: eval_is_list,
+    },
+    Builtin {
+        name: "list",
+        func: eval_list,
+    },
+    Builtin {
+        name: "make-list",
+        func: eval_make_list,
+    },
+    Builtin {
+        name: "length",
+        func: eval_length,
+    },
+    Builtin {
+        name: "append",
+        func: eval_append,
+    },
+    Builtin {
+        name: "reverse",
+        func: eval_reverse,
+    },
+    Builtin {
+        name: "list-tail",
+        func: eval_list_tail,
+    },
+    Builtin {
+        name: "list-ref",
+        func: eval_list_ref,
+    },
+    Builtin {
+        name: "list-set!",
+        func: eval_list_set,
+    },
+    Builtin {
+        name: "list-copy",
+        func: eval_list_copy,
+    },
+    Builtin {
+        name: "memq",
+        func: eval_memq,
+    },
+    Builtin {
+        name: "memv",
+        func: eval_memv,
+    },
+    Builtin {
+        name: "member",
+        func: eval_member,
+    },
+    Builtin {
+        name: "assq",
+        func: eval_assq,
+    },
+    Builtin {
+        name: "assv",
+        func: eval_assv,
+    },
+    Builtin {
+        name: "assoc",
+        func: eval_assoc,
+    },
+];
+
+fn pair_of(val: &LispVal) -> Result<&Rc<Pair>, EvalError> {
+    match val {
+        LispVal::Pair(x) => Ok(x),
+        _ => Err(EvalError::wrong_type("a pair", val)),
+    }
+}
+
+fn car(val: &LispVal) -> Result<LispVal, EvalError> {
+    Ok(pair_of(val)?.car.borrow().clone())
+}
+
+fn cdr(val: &LispVal) -> Result<LispVal, EvalError> {
+    Ok(pair_of(val)?.cdr.borrow().clone())
+}
+
+// The pairs of a chain up to the first cdr that is not a pair, and that cdr. The cdr is None when
+// the chain loops back on itself
+fn walk(val: &LispVal) -> (Vec<Rc<Pair>>, Option<LispVal>) {
+    let mut pairs = Vec::<Rc<Pair>>::new();
+    let mut next = val.clone();
+    loop {
+        let pair = match next {
+            LispVal::Pair(x) => x,
+            x => return (pairs, Some(x)),
+        };
+        next = pair.cdr.borrow().clone();
+        pairs.push(pair);
+        // the pair halfway along the chain is only ever reached again if the chain loops
+        if let LispVal::Pair(x) = &next {
+            if Rc::ptr_eq(x, &pairs[pairs.len() / 2]) {
+                return (pairs, None);
+            }
+        }
+    }
+}
+
+// The pairs of a proper list, None for an improper or circular one
+fn list_pairs(val: &LispVal) -> Option<Vec<Rc<Pair>>> {
+    match walk(val) {
+        (pairs, Some(LispVal::Nil)) => Some(pairs),
+        _ => None,
+    }
+}
+
+// The elements of a proper list
+pub(crate) fn list_items(val: &LispVal) -> Result<Vec<LispVal>, EvalError> {
+    match list_pairs(val) {
+        Some(pairs) => Ok(pairs.iter().map(|x| x.car.borrow().clone()).collect()),
+        None => Err(EvalError::wrong_type("a list", val)),
+    }
+}
+
+// Counts and indices have to be non-negative fixnums
+pub(crate) fn index_of(val: &LispVal) -> Result<usize, EvalError> {
+    match val {
+        LispVal::Number(x) if *x >= 0 => Ok(*x as usize),
+        _ => Err(EvalError::wrong_type("a non-negative index", val)),
+    }
+}
+
+fn eval_cons(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [car, cdr] = fixed_args(list)?;
+    env.allocate(size_of::<Pair>())?;
+    Ok(LispVal::cons(car, cdr))
+}
+
+fn eval_car(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    car(&x)
+}
+
+fn eval_cdr(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    cdr(&x)
+}
+
+fn eval_caar(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    car(&car(&x)?)
+}
+
+fn eval_cadr(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    car(&cdr(&x)?)
+}
+
+fn eval_cdar(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    cdr(&car(&x)?)
+}
+
+fn eval_cddr(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    cdr(&cdr(&x)?)
+}
+
+fn eval_set_car(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [pair, val] = fixed_args(list)?;
+    pair_of(&pair)?.car.replace(val);
+    Ok(LispVal::Void)
+}
+
+fn eval_set_cdr(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [pair, val] = fixed_args(list)?;
+    pair_of(&pair)?.cdr.replace(val);
+    Ok(LispVal::Void)
+}
+
+fn eval_is_pair(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(matches!(x, LispVal::Pair(_))))
+}
+
+fn eval_is_null(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(matches!(x, LispVal::Nil)))
+}
+
+// Only proper lists count, a circular list is not one
+fn eval_is_list(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(list_pairs(&x).is_some()))
+}
+
+fn eval_list(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    env.allocate(list.len() * size_of::<Pair>())?;
+    Ok(vec_to_list(list))
+}
+
+// (make-list k fill) with fill left out gives a list of #f
+fn eval_make_list(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let (k, fill) = match list.as_slice() {
+        [k] => (index_of(k)?, LispVal::Bool(false)),
+        [k, fill] => (index_of(k)?, fill.clone()),
+        _ => return Err(EvalError::arity(1, Some(2), list.len())),
+    };
+    env.allocate(k.saturating_mul(size_of::<Pair>()))?;
+    Ok((0..k).fold(LispVal::Nil, |acc, _| LispVal::cons(fill.clone(), acc)))
+}
+
+fn eval_length(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    match list_pairs(&x) {
+        Some(pairs) => Ok(LispVal::Number(pairs.len() as i64)),
+        None => Err(EvalError::wrong_type("a list", &x)),
+    }
+}
+
+// Every list but the last is copied, the result shares the last argument, which can be any value
+fn eval_append(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let (last, init) = match list.split_last() {
+        Some(x) => x,
+        None => return Ok(LispVal::Nil),
+    };
+    let mut items = Vec::<LispVal>::new();
+    for x in init {
+        items.extend(list_items(x)?);
+    }
+    env.allocate(items.len() * size_of::<Pair>())?;
+    Ok(items
+        .into_iter()
+        .rev()
+        .fold(last.clone(), |acc, x| LispVal::cons(x, acc)))
+}
+
+fn eval_reverse(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    let items = list_items(&x)?;
+    env.allocate(items.len() * size_of::<Pair>())?;
+    Ok(items
+        .into_iter()
+        .fold(LispVal::Nil, |acc, x| LispVal::cons(x, acc)))
+}
+
+// What is left of the list after dropping its first k pairs
+fn list_tail(list: &LispVal, k: &LispVal) -> Result<LispVal, EvalError> {
+    let mut tail = list.clone();
+    for _ in 0..index_of(k)? {
+        tail = match &tail {
+            LispVal::Pair(x) => x.cdr.borrow().clone(),
+            _ => return Err(EvalError::wrong_type("an index within the list", k)),
+        };
+    }
+    Ok(tail)
+}
+
+// The pair holding element k of the list
+fn list_pair(list: &LispVal, k: &LispVal) -> Result<Rc<Pair>, EvalError> {
+    match list_tail(list, k)? {
+        LispVal::Pair(x) => Ok(x),
+        _ => Err(EvalError::wrong_type("an index within the list", k)),
+    }
+}
+
+fn eval_list_tail(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [list, k] = fixed_args(list)?;
+    list_tail(&list, &k)
+}
+
+fn eval_list_ref(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [list, k] = fixed_args(list)?;
+    let pair = list_pair(&list, &k)?;
+    let item = pair.car.borrow().clone();
+    Ok(item)
+}
+
+fn eval_list_set(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [list, k, val] = fixed_args(list)?;
+    list_pair(&list, &k)?.car.replace(val);
+    Ok(LispVal::Void)
+}
+
+// Copies the pairs of the list and keeps whatever ends it, so any value other than a circular list
+// can be copied
+fn eval_list_copy(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    let (pairs, tail) = walk(&x);
+    let tail = match tail {
+        Some(tail) => tail,
+        None => return Err(EvalError::wrong_type("a list that does not loop", &x)),
+    };
+    env.allocate(pairs.len() * size_of::<Pair>())?;
+    Ok(pairs
+        .iter()
+        .rev()
+        .fold(tail, |acc, x| LispVal::cons(x.car.borrow().clone(), acc)))
+}
+
+// Looks for x in the list, comparing with the procedure given as the optional last argument or
+// with same. Gives the first pair whose element matches, or for an association list the first
+// element whose car matches. #f when nothing does
+fn search(
+    list: Vec<LispVal>,
+    env: &mut Environment,
+    same: fn(&LispVal, &LispVal) -> bool,
+    optional_compare: bool,
+    by_key: bool,
+) -> Result<LispVal, EvalError> {
+    let max = if optional_compare { 3 } else { 2 };
+    let (x, items, compare) = match list.as_slice() {
+        [x, items] => (x, items, None),
+        [x, items, compare] if optional_compare => (x, items, Some(compare)),
+        _ => return Err(EvalError::arity(2, Some(max), list.len())),
+    };
+    let pairs = list_pairs(items).ok_or_else(|| EvalError::wrong_type("a list", items))?;
+    for pair in pairs {
+        let item = pair.car.borrow().clone();
+        let key = if by_key {
+            car(&item).map_err(|_| EvalError::wrong_type("an association list", items))?
+        } else {
+            item.clone()
+        };
+        let matched = match compare {
+            Some(compare) => is_true(&eval_proc(compare.clone(), vec![x.clone(), key], env)?),
+            None => same(x, &key),
+        };
+        if matched {
+            return Ok(if by_key { item } else { LispVal::Pair(pair) });
+        }
+    }
+    Ok(LispVal::Bool(false))
+}
+
+fn eval_memq(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    search(list, env, is_eq, false, false)
+}
+
+fn eval_memv(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    search(list, env, is_eqv, false, false)
+}
+
+fn eval_member(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    search(list, env, is_equal, true, false)
+}
+
+fn eval_assq(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    search(list, env, is_eq, false, true)
+}
+
+fn eval_assv(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    search(list, env, is_eqv, false, true)
+}
+
+fn eval_assoc(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    search(list, env, is_equal, true, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::show;
+
+    #[test]
+    fn pairs_share_and_mutate() {
+        assert_eq!(show("(cons 1 2)"), "(1 . 2)");
+        assert_eq!(show("(cons 1 '(2 3))"), "(1 2 3)");
+        assert_eq!(show("'(1 (2 . 3) . 4)"), "(1 (2 . 3) . 4)");
+        assert_eq!(show("(cadr '(1 2 3))"), "2");
+        assert_eq!(show("(cddr '(1 2 3))"), "(3)");
+        // both lists share the tail, so a change to it shows in both
+        let input = "(define tail (list 2 3)) (define a (cons 1 tail)) (define b (cons 0 tail))
+                     (set-car! tail 5) (list a b)";
+        assert_eq!(show(input), "((1 5 3) (0 5 3))");
+        assert_eq!(
+            show("(define p (list 1 2)) (set-cdr! (cdr p) '(3)) p"),
+            "(1 2 3)"
+        );
+        assert_eq!(show("(pair? '())"), "#f");
+        assert_eq!(show("(null? '())"), "#t");
+        assert_eq!(show("(list? '(1 2))"), "#t");
+        assert_eq!(show("(list? '(1 . 2))"), "#f");
+        assert_eq!(
+            show("(define p (list 1 2)) (set-cdr! (cdr p) p) (list? p)"),
+            "#f"
+        );
+        assert_eq!(
+            show("(define p (list 1 2)) (set-cdr! (cdr p) p) p"),
+            "#0=(1 2 . #0#)"
+        );
+        assert_eq!(show("(define p (list 1 2)) (set-car! p p) p"), "#0=(#0# 2)");
+        assert!(show("(car '())").starts_with("error: wrong type"));
+        assert!(show("(set-car! '() 1)").starts_with("error: wrong type"));
+    }
+
+    #[test]
+    fn list_procedures() {
+        assert_eq!(show("(list)"), "()");
+        assert_eq!(show("(make-list 3 'x)"), "(x x x)");
+        assert_eq!(show("(length '(1 2 3))"), "3");
+        assert_eq!(show("(append '(1) '(2 3) '() '(4))"), "(1 2 3 4)");
+        assert_eq!(show("(append '(1) 2)"), "(1 . 2)");
+        assert_eq!(show("(append)"), "()");
+        assert_eq!(show("(reverse '(1 2 3))"), "(3 2 1)");
+        assert_eq!(show("(list-tail '(1 2 3) 2)"), "(3)");
+        assert_eq!(show("(list-ref '(1 2 3) 1)"), "2");
+        assert_eq!(
+            show("(define l (list 1 2 3)) (list-set! l 0 'a) l"),
+            "(a 2 3)"
+        );
+        assert_eq!(
+            show("(define a (list 1 2)) (define b (list-copy a)) (set-car! b 9) (list a b)"),
+            "((1 2) (9 2))"
+        );
+        assert_eq!(show("(list-copy 5)"), "5");
+        assert!(show("(list-ref '(1 2) 2)").starts_with("error: wrong type"));
+        assert!(show("(length '(1 . 2))").starts_with("error: wrong type"));
+        assert!(
+            show("(define p (list 1)) (set-cdr! p p) (length p)").starts_with("error: wrong type")
+        );
+    }
+
+    #[test]
+    fn list_searches() {
+        assert_eq!(show("(memq 'c '(a b c d))"), "(c d)");
+        assert_eq!(show("(memv 101 '(100 101 102))"), "(101 102)");
+        assert_eq!(show("(memq 'z '(a b))"), "#f");
+        assert_eq!(show("(member \"b\" '(\"a\" \"b\"))"), "(b)");
+        assert_eq!(show("(member '(1) '((0) (1) (2)))"), "((1) (2))");
+        assert_eq!(show("(member 2.0 '(1 2 3) =)"), "(2 3)");
+        assert_eq!(show("(assq 'b '((a 1) (b 2)))"), "(b 2)");
+        assert_eq!(show("(assv 2 '((1 one) (2 two)))"), "(2 two)");
+        assert_eq!(show("(assoc '(a) '(((a) 1)))"), "((a) 1)");
+        assert_eq!(show("(assoc 2.0 '((1 1) (2 4)) =)"), "(2 4)");
+        assert_eq!(show("(assq 'x '())"), "#f");
+        assert!(show("(assq 'a '(1))").starts_with("error: wrong type"));
+    }
+
+    #[test]
+    fn long_lists_do_not_overflow_the_stack() {
+        let input = "(define (build n acc) (if (= n 0) acc (build (- n 1) (cons n acc))))
+                     (length (build 200000 '()))";
+        assert_eq!(show(input), "200000");
+    }
+}
@@ -1,51 +1,115 @@
-mod eval;
-mod lexer;
-mod parser;
-mod token;
 use std::io::{stdin, stdout, Write};
+use std::{fs, process, thread};
+
+use scheme_lang::error::EvalError;
+use scheme_lang::eval::{eval_prog, Environment, InterruptHandle, Limits};
+use scheme_lang::lexer::Lexer;
+use scheme_lang::parser::Parser;
+use scheme_lang::token;
+
+// The interpreter runs on its own thread with a large stack so programs can recurse deeply
+const STACK_SIZE: usize = 512 << 20;
+const MAX_DEPTH: usize = 100_000;
 
-use eval::{eval_prog, Environment};
-use lexer::Lexer;
-use parser::Parser;
 fn main() {
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("Failed to start the interpreter thread");
+    if interpreter.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn run() {
+    let env = Environment::init_env();
+    env.set_limits(Limits {
+        max_depth: Some(MAX_DEPTH),
+        max_stack: Some(STACK_SIZE / 2),
+        ..Limits::default()
+    });
+    if let Some(path) = std::env::args().nth(1) {
+        run_script(&path, env);
+        return;
+    }
+    catch_ctrl_c(env.interrupt_handle());
     println!("Scheme Interpreter");
     let _ = stdout().write(">>>".to_string().as_bytes());
+    // Shared by every line so definitions persist for the whole session
+    let mut env = env;
     loop {
+        let _ = stdout().flush();
         let mut input_string = String::new();
-        stdin()
+        let read = stdin()
             .read_line(&mut input_string)
-            .ok()
             .expect("Failed to read line");
+        if read == 0 {
+            break;
+        }
         input_string = input_string.trim().to_string();
-        let _ = stdout().flush();
+        // Ctrl-C while reading the line should not stop its evaluation
+        env.interrupt_handle().clear();
         let mut lex = Lexer::init_lex(input_string.to_owned());
         let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
+        let prog = par.and_then(|mut x| x.parse_program());
         match prog {
             Ok(x) => {
-                let mut env = Environment::init_env();
                 let y = eval_prog(x, &mut env);
                 match y {
                     Ok(y) => {
                         for i in y {
-                            let _ = stdout().write(format!("{}\n", i.show_val()).as_bytes());
+                            if i != token::LispVal::Void {
+                                let _ = stdout().write(format!("{}\n", i.show_val()).as_bytes());
+                            }
                         }
                     }
-                    Err(x) => eprint!("{}", x),
+                    Err(x) => report(&x),
                 }
             }
             Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
+                eprintln!("{}", e);
             }
         }
         let _ = stdout().write(">>>".to_string().as_bytes());
     }
 }
+
+// Runs a whole file, stopping at the first error
+fn run_script(path: &str, mut env: Environment) {
+    let input = match fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("cannot read {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let mut lex = Lexer::init_lex(input);
+    let prog = Parser::init_parser(&mut lex).and_then(|mut x| x.parse_program());
+    let prog = match prog {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = eval_prog(prog, &mut env) {
+        report(&e);
+        process::exit(1);
+    }
+}
+
+fn report(err: &EvalError) {
+    eprintln!("{}", err);
+    if !err.backtrace.is_empty() {
+        eprint!("backtrace:\n{}", err.backtrace_report());
+    }
+}
+
+// The handler runs on a thread of its own and only raises the interrupt, which the evaluation
+// notices at its next step. Reading a line is not interrupted, so Ctrl-C at an idle prompt does
+// nothing and the session ends with Ctrl-D
+fn catch_ctrl_c(handle: InterruptHandle) {
+    if let Err(e) = ctrlc::set_handler(move || handle.interrupt()) {
+        eprintln!("Ctrl-C will not interrupt evaluations: {}", e);
+    }
+}
@@ -0,0 +1,1730 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::bigint::{unmetered, BigInt, Tick};
+use crate::error::{ErrorKind, EvalError};
+use crate::eval::{compare_chain, fixed_args, Environment};
+use crate::token::{Builtin, LispVal};
+
+// The numeric tower. Exact integers are fixnums while they fit in an i64 and bignums once they do
+// not, exact rationals are kept in lowest terms and inexact reals are doubles. Complex numbers are
+// always inexact. Every result goes back to the simplest of these that holds it, so the
+// representations never overlap
+
+// Exact fraction in lowest terms with a denominator above 1
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rational {
+    numer: BigInt,
+    denom: BigInt,
+}
+
+impl Rational {
+    // None when the denominator is zero
+    pub fn new(numer: BigInt, denom: BigInt) -> Option<Self> {
+        unmetered(|tick| Rational::new_metered(numer, denom, tick))
+    }
+
+    // Reducing to lowest terms is most of the work of exact arithmetic, so it is counted
+    pub fn new_metered<E>(numer: BigInt, denom: BigInt, tick: Tick<E>) -> Result<Option<Self>, E> {
+        if denom.is_zero() {
+            return Ok(None);
+        }
+        let gcd = numer.gcd_metered(&denom, tick)?;
+        let (Some((mut numer, _)), Some((mut denom, _))) = (
+            numer.div_rem_metered(&gcd, tick)?,
+            denom.div_rem_metered(&gcd, tick)?,
+        ) else {
+            return Ok(None);
+        };
+        if denom.is_negative() {
+            numer = numer.neg();
+            denom = denom.neg();
+        }
+        Ok(Some(Rational { numer, denom }))
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        &self.numer
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        &self.denom
+    }
+
+    fn add<E>(&self, other: &Rational, tick: Tick<E>) -> Result<Option<Rational>, E> {
+        let numer = self.numer.mul_metered(&other.denom, tick)?;
+        let numer = numer.add(&other.numer.mul_metered(&self.denom, tick)?);
+        let denom = self.denom.mul_metered(&other.denom, tick)?;
+        Rational::new_metered(numer, denom, tick)
+    }
+
+    fn sub<E>(&self, other: &Rational, tick: Tick<E>) -> Result<Option<Rational>, E> {
+        let negated = Rational {
+            numer: other.numer.neg(),
+            denom: other.denom.clone(),
+        };
+        self.add(&negated, tick)
+    }
+
+    fn mul<E>(&self, other: &Rational, tick: Tick<E>) -> Result<Option<Rational>, E> {
+        let numer = self.numer.mul_metered(&other.numer, tick)?;
+        let denom = self.denom.mul_metered(&other.denom, tick)?;
+        Rational::new_metered(numer, denom, tick)
+    }
+
+    fn div<E>(&self, other: &Rational, tick: Tick<E>) -> Result<Option<Rational>, E> {
+        let numer = self.numer.mul_metered(&other.denom, tick)?;
+        let denom = self.denom.mul_metered(&other.numer, tick)?;
+        Rational::new_metered(numer, denom, tick)
+    }
+
+    fn cmp(&self, other: &Rational) -> Ordering {
+        self.numer
+            .mul(&other.denom)
+            .cmp(&other.numer.mul(&self.denom))
+    }
+
+    // Divides with enough bits in the quotient to fill a double before scaling it back
+    fn to_f64(&self) -> f64 {
+        let shift = 64 + self.denom.bits() as i64 - self.numer.bits() as i64;
+        let quotient = if shift >= 0 {
+            self.numer.shl(shift as u64).div_rem(&self.denom)
+        } else {
+            self.numer.div_rem(&self.denom.shl(-shift as u64))
+        };
+        match quotient {
+            Some((q, _)) => scale(q.to_f64(), -shift),
+            None => f64::NAN,
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numer, self.denom)
+    }
+}
+
+// x * 2^exp without overflowing the intermediate powers of two
+fn scale(mut x: f64, mut exp: i64) -> f64 {
+    while exp > 1000 {
+        x *= 2f64.powi(1000);
+        exp -= 1000;
+    }
+    while exp < -1000 {
+        x *= 2f64.powi(-1000);
+        exp += 1000;
+    }
+    x * 2f64.powi(exp as i32)
+}
+
+// Inexact complex number in rectangular form with a nonzero imaginary part
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+const I: Complex = Complex { re: 0.0, im: 1.0 };
+const ONE: Complex = Complex { re: 1.0, im: 0.0 };
+
+impl Complex {
+    fn polar(magnitude: f64, angle: f64) -> Self {
+        Complex {
+            re: magnitude * angle.cos(),
+            im: magnitude * angle.sin(),
+        }
+    }
+
+    fn add(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re + other.re,
+            im: self.im + other.im,
+        }
+    }
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re - other.re,
+            im: self.im - other.im,
+        }
+    }
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+
+    fn div(self, other: Complex) -> Complex {
+        let norm = other.re * other.re + other.im * other.im;
+        Complex {
+            re: (self.re * other.re + self.im * other.im) / norm,
+            im: (self.im * other.re - self.re * other.im) / norm,
+        }
+    }
+
+    fn magnitude(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn angle(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    fn exp(self) -> Complex {
+        Complex::polar(self.re.exp(), self.im)
+    }
+
+    // Principal value, with the angle in (-pi, pi]
+    fn ln(self) -> Complex {
+        Complex {
+            re: self.magnitude().ln(),
+            im: self.angle(),
+        }
+    }
+
+    // Principal root, computed without going through the angle so exact squares stay exact
+    fn sqrt(self) -> Complex {
+        if self.re == 0.0 && self.im == 0.0 {
+            return self;
+        }
+        let t = ((self.re.abs() + self.magnitude()) / 2.0).sqrt();
+        if self.re >= 0.0 {
+            Complex {
+                re: t,
+                im: self.im / (2.0 * t),
+            }
+        } else {
+            Complex {
+                re: self.im.abs() / (2.0 * t),
+                im: t.copysign(self.im),
+            }
+        }
+    }
+
+    fn pow(self, power: Complex) -> Complex {
+        if self.re == 0.0 && self.im == 0.0 {
+            return if power.re == 0.0 && power.im == 0.0 {
+                ONE
+            } else {
+                self
+            };
+        }
+        power.mul(self.ln()).exp()
+    }
+
+    fn sin(self) -> Complex {
+        Complex {
+            re: self.re.sin() * self.im.cosh(),
+            im: self.re.cos() * self.im.sinh(),
+        }
+    }
+
+    fn cos(self) -> Complex {
+        Complex {
+            re: self.re.cos() * self.im.cosh(),
+            im: -self.re.sin() * self.im.sinh(),
+        }
+    }
+
+    fn tan(self) -> Complex {
+        self.sin().div(self.cos())
+    }
+
+    // asin z = -i ln(iz + sqrt(1 - z^2))
+    fn asin(self) -> Complex {
+        let root = ONE.sub(self.mul(self)).sqrt();
+        Complex { re: 0.0, im: -1.0 }.mul(I.mul(self).add(root).ln())
+    }
+
+    fn acos(self) -> Complex {
+        Complex {
+            re: std::f64::consts::FRAC_PI_2,
+            im: 0.0,
+        }
+        .sub(self.asin())
+    }
+
+    // atan z = i/2 (ln(1 - iz) - ln(1 + iz))
+    fn atan(self) -> Complex {
+        let iz = I.mul(self);
+        let diff = ONE.sub(iz).ln().sub(ONE.add(iz).ln());
+        Complex { re: 0.0, im: 0.5 }.mul(diff)
+    }
+}
+
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let im = show_float(self.im);
+        let sign = if im.starts_with(['+', '-']) { "" } else { "+" };
+        write!(f, "{}{}{}i", show_float(self.re), sign, im)
+    }
+}
+
+// A complex result with no imaginary part is a real number
+fn complex(z: Complex) -> LispVal {
+    if z.im == 0.0 {
+        return LispVal::Float(z.re);
+    }
+    LispVal::Complex(z)
+}
+
+fn to_complex(val: &LispVal) -> Complex {
+    match val {
+        LispVal::Complex(z) => *z,
+        _ => Complex {
+            re: to_f64(val),
+            im: 0.0,
+        },
+    }
+}
+
+pub fn integer(x: BigInt) -> LispVal {
+    match x.to_i64() {
+        Some(x) => LispVal::Number(x),
+        None => LispVal::BigInt(Rc::new(x)),
+    }
+}
+
+fn exact(x: Rational) -> LispVal {
+    if x.denom == BigInt::from_i64(1) {
+        return integer(x.numer);
+    }
+    LispVal::Rational(Rc::new(x))
+}
+
+pub fn to_bigint(val: &LispVal) -> Option<BigInt> {
+    match val {
+        LispVal::Number(x) => Some(BigInt::from_i64(*x)),
+        LispVal::BigInt(x) => Some((**x).clone()),
+        _ => None,
+    }
+}
+
+fn to_rational(val: &LispVal) -> Option<Rational> {
+    match val {
+        LispVal::Rational(x) => Some((**x).clone()),
+        _ => Some(Rational {
+            numer: to_bigint(val)?,
+            denom: BigInt::from_i64(1),
+        }),
+    }
+}
+
+fn to_f64(val: &LispVal) -> f64 {
+    match val {
+        LispVal::Number(x) => *x as f64,
+        LispVal::BigInt(x) => x.to_f64(),
+        LispVal::Rational(x) => x.to_f64(),
+        LispVal::Float(x) => *x,
+        _ => f64::NAN,
+    }
+}
+
+// The exact number a finite double stands for
+fn float_to_exact(x: f64) -> Option<LispVal> {
+    if !x.is_finite() {
+        return None;
+    }
+    let bits = x.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i64;
+    let fraction = (bits & ((1 << 52) - 1)) as i64;
+    let (mantissa, exp) = match biased {
+        0 => (fraction, -1074),
+        _ => (fraction | 1 << 52, biased - 1075),
+    };
+    let mantissa = BigInt::from_i64(if x < 0.0 { -mantissa } else { mantissa });
+    if exp >= 0 {
+        return Some(integer(mantissa.shl(exp as u64)));
+    }
+    Rational::new(mantissa, BigInt::from_i64(1).shl(-exp as u64)).map(exact)
+}
+
+// Where a number sits in the tower. Operands are converted up to the higher of their two ranks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    Integer,
+    Rational,
+    Real,
+    Complex,
+}
+
+fn rank(val: &LispVal) -> Result<Rank, EvalError> {
+    match val {
+        LispVal::Number(_) | LispVal::BigInt(_) => Ok(Rank::Integer),
+        LispVal::Rational(_) => Ok(Rank::Rational),
+        LispVal::Float(_) => Ok(Rank::Real),
+        LispVal::Complex(_) => Ok(Rank::Complex),
+        _ => Err(EvalError::wrong_type("a number", val)),
+    }
+}
+
+// Rank of an argument that has to be on the real line
+fn real_rank(val: &LispVal) -> Result<Rank, EvalError> {
+    match rank(val)? {
+        Rank::Complex => Err(EvalError::wrong_type("a real number", val)),
+        x => Ok(x),
+    }
+}
+
+type RationalOp = fn(&Rational, &Rational, Tick<EvalError>) -> Result<Option<Rational>, EvalError>;
+
+// Each exact operation returns None when the result is undefined, which only division can do.
+// They count the work of long multiplications and divisions against the limits
+struct Ops {
+    integer: fn(&LispVal, &LispVal, &Environment) -> Result<Option<LispVal>, EvalError>,
+    rational: RationalOp,
+    real: fn(f64, f64) -> f64,
+    complex: fn(Complex, Complex) -> Complex,
+}
+
+// Bits of an exact operand that is not a fixnum, 0 for every other number
+fn exact_bits(val: &LispVal) -> u64 {
+    match val {
+        LispVal::BigInt(x) => x.bits(),
+        LispVal::Rational(x) => x.numer.bits() + x.denom.bits(),
+        _ => 0,
+    }
+}
+
+fn arith(a: &LispVal, b: &LispVal, ops: &Ops, env: &Environment) -> Result<LispVal, EvalError> {
+    let rank = rank(a)?.max(rank(b)?);
+    // no exact result needs more bits than its operands together, so it is charged before the
+    // work starts
+    let bits = exact_bits(a) + exact_bits(b);
+    if bits > 0 && rank <= Rank::Rational {
+        env.allocate((bits / 8) as usize)?;
+    }
+    let result = match rank {
+        Rank::Integer => (ops.integer)(a, b, env)?,
+        Rank::Rational => match to_rational(a).zip(to_rational(b)) {
+            Some((x, y)) => (ops.rational)(&x, &y, &mut |work| env.work(work))?.map(exact),
+            None => None,
+        },
+        Rank::Real => Some(LispVal::Float((ops.real)(to_f64(a), to_f64(b)))),
+        Rank::Complex => Some(complex((ops.complex)(to_complex(a), to_complex(b)))),
+    };
+    result.ok_or_else(|| ErrorKind::DivisionByZero.into())
+}
+
+const ADD: Ops = Ops {
+    integer: int_add,
+    rational: Rational::add,
+    real: |x, y| x + y,
+    complex: Complex::add,
+};
+
+const SUB: Ops = Ops {
+    integer: int_sub,
+    rational: Rational::sub,
+    real: |x, y| x - y,
+    complex: Complex::sub,
+};
+
+const MUL: Ops = Ops {
+    integer: int_mul,
+    rational: Rational::mul,
+    real: |x, y| x * y,
+    complex: Complex::mul,
+};
+
+const DIV: Ops = Ops {
+    integer: int_div,
+    rational: Rational::div,
+    real: |x, y| x / y,
+    complex: Complex::div,
+};
+
+// The integer operations take the fixnum path when it cannot overflow
+fn int_add(a: &LispVal, b: &LispVal, _env: &Environment) -> Result<Option<LispVal>, EvalError> {
+    if let (LispVal::Number(x), LispVal::Number(y)) = (a, b) {
+        if let Some(sum) = x.checked_add(*y) {
+            return Ok(Some(LispVal::Number(sum)));
+        }
+    }
+    Ok(to_bigint(a)
+        .zip(to_bigint(b))
+        .map(|(x, y)| integer(x.add(&y))))
+}
+
+fn int_sub(a: &LispVal, b: &LispVal, _env: &Environment) -> Result<Option<LispVal>, EvalError> {
+    if let (LispVal::Number(x), LispVal::Number(y)) = (a, b) {
+        if let Some(diff) = x.checked_sub(*y) {
+            return Ok(Some(LispVal::Number(diff)));
+        }
+    }
+    Ok(to_bigint(a)
+        .zip(to_bigint(b))
+        .map(|(x, y)| integer(x.sub(&y))))
+}
+
+fn int_mul(a: &LispVal, b: &LispVal, env: &Environment) -> Result<Option<LispVal>, EvalError> {
+    if let (LispVal::Number(x), LispVal::Number(y)) = (a, b) {
+        if let Some(product) = x.checked_mul(*y) {
+            return Ok(Some(LispVal::Number(product)));
+        }
+    }
+    let (Some(x), Some(y)) = (to_bigint(a), to_bigint(b)) else {
+        return Ok(None);
+    };
+    let product = x.mul_metered(&y, &mut |ops| env.work(ops))?;
+    Ok(Some(integer(product)))
+}
+
+// Exact quotient, a fraction when the division leaves a remainder
+fn int_div(a: &LispVal, b: &LispVal, env: &Environment) -> Result<Option<LispVal>, EvalError> {
+    if let (LispVal::Number(x), LispVal::Number(y)) = (a, b) {
+        if x.checked_rem(*y) == Some(0) {
+            return Ok(x.checked_div(*y).map(LispVal::Number));
+        }
+    }
+    let (Some(x), Some(y)) = (to_bigint(a), to_bigint(b)) else {
+        return Ok(None);
+    };
+    Ok(Rational::new_metered(x, y, &mut |ops| env.work(ops))?.map(exact))
+}
+
+fn is_integer(val: &LispVal) -> bool {
+    match val {
+        LispVal::Number(_) | LispVal::BigInt(_) => true,
+        LispVal::Float(f) => f.is_finite() && f.fract() == 0.0,
+        _ => false,
+    }
+}
+
+// Quotient and remainder of a division of integers, with the quotient rounded down when floor is
+// set and towards zero otherwise. The results are inexact when either operand is
+fn divide_integers(
+    a: &LispVal,
+    b: &LispVal,
+    floor: bool,
+    env: &Environment,
+) -> Result<[LispVal; 2], EvalError> {
+    for x in [a, b] {
+        if !is_integer(x) {
+            return Err(EvalError::wrong_type("an integer", x));
+        }
+    }
+    if let (LispVal::Float(_), _) | (_, LispVal::Float(_)) = (a, b) {
+        let (x, y) = (to_f64(a), to_f64(b));
+        if y == 0.0 {
+            return Err(ErrorKind::DivisionByZero.into());
+        }
+        let mut rem = x % y;
+        if floor && rem != 0.0 && (rem < 0.0) != (y < 0.0) {
+            rem += y;
+        }
+        return Ok([LispVal::Float(((x - rem) / y).round()), LispVal::Float(rem)]);
+    }
+    if let (LispVal::Number(x), LispVal::Number(y)) = (a, b) {
+        if let (Some(mut quot), Some(mut rem)) = (x.checked_div(*y), x.checked_rem(*y)) {
+            if floor && rem != 0 && (rem < 0) != (*y < 0) {
+                quot -= 1;
+                rem += y;
+            }
+            return Ok([LispVal::Number(quot), LispVal::Number(rem)]);
+        }
+    }
+    let (Some(x), Some(y)) = (to_bigint(a), to_bigint(b)) else {
+        return Err(EvalError::wrong_type("an integer", a));
+    };
+    let (mut quot, mut rem) = x
+        .div_rem_metered(&y, &mut |ops| env.work(ops))?
+        .ok_or(ErrorKind::DivisionByZero)?;
+    if floor && !rem.is_zero() && rem.is_negative() != y.is_negative() {
+        quot = quot.sub(&BigInt::from_i64(1));
+        rem = rem.add(&y);
+    }
+    Ok([integer(quot), integer(rem)])
+}
+
+// Largest integer whose square is at most n, by Newton's method from a power of two above the root
+fn isqrt<E>(n: &BigInt, tick: Tick<E>) -> Result<BigInt, E> {
+    if n.is_zero() {
+        return Ok(BigInt::zero());
+    }
+    let mut x = BigInt::from_i64(1).shl(n.bits() / 2 + 1);
+    loop {
+        let next = match n.div_rem_metered(&x, tick)? {
+            Some((q, _)) => x.add(&q).shr(1),
+            None => return Ok(x),
+        };
+        if next >= x {
+            return Ok(x);
+        }
+        x = next;
+    }
+}
+
+// None when either number is NaN or when complex numbers differ, since those are not ordered. An
+// exact number is compared with the exact value of a double, so the comparison never loses
+// precision
+fn compare(a: &LispVal, b: &LispVal) -> Result<Option<Ordering>, EvalError> {
+    Ok(match rank(a)?.max(rank(b)?) {
+        Rank::Integer => match (a, b) {
+            (LispVal::Number(x), LispVal::Number(y)) => Some(x.cmp(y)),
+            _ => to_bigint(a).zip(to_bigint(b)).map(|(x, y)| x.cmp(&y)),
+        },
+        Rank::Rational => to_rational(a).zip(to_rational(b)).map(|(x, y)| x.cmp(&y)),
+        Rank::Real => match (a, b) {
+            (LispVal::Float(x), LispVal::Float(y)) => x.partial_cmp(y),
+            (LispVal::Float(x), y) => compare_float(*x, y),
+            (x, LispVal::Float(y)) => compare_float(*y, x).map(Ordering::reverse),
+            _ => None,
+        },
+        Rank::Complex => (to_complex(a) == to_complex(b)).then_some(Ordering::Equal),
+    })
+}
+
+fn compare_float(x: f64, exact: &LispVal) -> Option<Ordering> {
+    if x.is_infinite() {
+        return Some(if x > 0.0 {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        });
+    }
+    let x = to_rational(&float_to_exact(x)?)?;
+    Some(x.cmp(&to_rational(exact)?))
+}
+
+// Reads a number literal in the given radix, either real or complex in rectangular form like 1+2i
+// or polar form like 1@3.14. None when the text is not a number
+pub fn parse_number(text: &str, radix: u32) -> Option<LispVal> {
+    if let Some((magnitude, angle)) = text.split_once('@') {
+        let (magnitude, angle) = (parse_real(magnitude, radix)?, parse_real(angle, radix)?);
+        if matches!(angle, LispVal::Number(0)) {
+            return Some(magnitude);
+        }
+        return Some(complex(Complex::polar(to_f64(&magnitude), to_f64(&angle))));
+    }
+    let Some(body) = text.strip_suffix('i') else {
+        return parse_real(text, radix);
+    };
+    // the imaginary part starts at the last sign that does not belong to an exponent
+    let bytes = body.as_bytes();
+    let (split, _) = body.char_indices().rev().find(|&(i, c)| {
+        let exponent = radix == 10 && i > 0 && matches!(bytes[i - 1], b'e' | b'E');
+        matches!(c, '+' | '-') && !exponent
+    })?;
+    let (real, imag) = body.split_at(split);
+    let real = match real {
+        "" => LispVal::Number(0),
+        _ => parse_real(real, radix)?,
+    };
+    let imag = match imag {
+        "+" => LispVal::Number(1),
+        "-" => LispVal::Number(-1),
+        _ => parse_real(imag, radix)?,
+    };
+    if matches!(imag, LispVal::Number(0)) {
+        return Some(real);
+    }
+    Some(complex(Complex {
+        re: to_f64(&real),
+        im: to_f64(&imag),
+    }))
+}
+
+// An integer, a fraction like 1/3 or, in radix 10, a decimal with an optional exponent
+fn parse_real(text: &str, radix: u32) -> Option<LispVal> {
+    match text {
+        "+inf.0" => return Some(LispVal::Float(f64::INFINITY)),
+        "-inf.0" => return Some(LispVal::Float(f64::NEG_INFINITY)),
+        "+nan.0" | "-nan.0" => return Some(LispVal::Float(f64::NAN)),
+        _ => {}
+    }
+    if radix == 10 {
+        if let Ok(x) = text.parse::<i64>() {
+            return Some(LispVal::Number(x));
+        }
+    }
+    if let Some(x) = BigInt::parse(text, radix) {
+        return Some(integer(x));
+    }
+    if let Some((numer, denom)) = text.split_once('/') {
+        if denom.starts_with(['+', '-']) {
+            return None;
+        }
+        let numer = BigInt::parse(numer, radix)?;
+        return Rational::new(numer, BigInt::parse(denom, radix)?).map(exact);
+    }
+    // f64 parsing also takes words like inf and nan, which are not Scheme numbers
+    let decimal = text.chars().all(|c| "0123456789+-.eE".contains(c));
+    if radix != 10 || !decimal {
+        return None;
+    }
+    text.parse::<f64>().ok().map(LispVal::Float)
+}
+
+// Inexact numbers always print with a point or an exponent so they read back as inexact
+pub fn show_float(x: f64) -> String {
+    if x.is_nan() {
+        return "+nan.0".to_owned();
+    }
+    if x.is_infinite() {
+        return if x > 0.0 { "+inf.0" } else { "-inf.0" }.to_owned();
+    }
+    if x != 0.0 && (x.abs() >= 1e21 || x.abs() < 1e-7) {
+        return format!("{:e}", x);
+    }
+    let st = x.to_string();
+    if st.contains('.') {
+        st
+    } else {
+        st + ".0"
+    }
+}
+
+pub const NUMBER_BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "+",
+        func: eval_bin_add,
+    },
+    Builtin {
+        name: "-",
+        func: eval_bin_sub,
+    },
+    Builtin {
+        name: "*",
+        func: eval_bin_mult,
+    },
+    Builtin {
+        name: "/",
+        func: eval_bin_div,
+    },
+    Builtin {
+        name: "<",
+        func: eval_num_less,
+    },
+    Builtin {
+        name: "<=",
+        func: eval_num_less_eq,
+    },
+    Builtin {
+        name: "=",
+        func: eval_num_eq,
+    },
+    Builtin {
+        name: ">",
+        func: eval_num_greater,
+    },
+    Builtin {
+        name: ">=",
+        func: eval_num_greater_eq,
+    },
+    Builtin {
+        name: "exact",
+        func: eval_exact,
+    },
+    Builtin {
+        name: "inexact",
+        func: eval_inexact,
+    },
+    Builtin {
+        name: "exact?",
+        func: eval_is_exact,
+    },
+    Builtin {
+        name: "inexact?",
+        func: eval_is_inexact,
+    },
+    Builtin {
+        name: "number?",
+        func: eval_is_number,
+    },
+    Builtin {
+        name: "complex?",
+        func: eval_is_number,
+    },
+    Builtin {
+        name: "real?",
+        func: eval_is_real,
+    },
+    Builtin {
+        name: "exact-integer?",
+        func: eval_is_exact_integer,
+    },
+    Builtin {
+        name: "rational?",
+        func: eval_is_rational,
+    },
+    Builtin {
+        name: "integer?",
+        func: eval_is_integer,
+    },
+    Builtin {
+        name: "nan?",
+        func: eval_is_nan,
+    },
+    Builtin {
+        name: "infinite?",
+        func: eval_is_infinite,
+    },
+    Builtin {
+        name: "finite?",
+        func: eval_is_finite,
+    },
+    Builtin {
+        name: "quotient",
+        func: eval_truncate_quotient,
+    },
+    Builtin {
+        name: "remainder",
+        func: eval_truncate_remainder,
+    },
+    Builtin {
+        name: "modulo",
+        func: eval_floor_remainder,
+    },
+    Builtin {
+        name: "truncate/",
+        func: eval_truncate_div,
+    },
+    Builtin {
+        name: "truncate-quotient",
+        func: eval_truncate_quotient,
+    },
+    Builtin {
+        name: "truncate-remainder",
+        func: eval_truncate_remainder,
+    },
+    Builtin {
+        name: "floor/",
+        func: eval_floor_div,
+    },
+    Builtin {
+        name: "floor-quotient",
+        func: eval_floor_quotient,
+    },
+    Builtin {
+        name: "floor-remainder",
+        func: eval_floor_remainder,
+    },
+    Builtin {
+        name: "exact-integer-sqrt",
+        func: eval_exact_integer_sqrt,
+    },
+    Builtin {
+        name: "abs",
+        func: eval_abs,
+    },
+    Builtin {
+        name: "min",
+        func: eval_min,
+    },
+    Builtin {
+        name: "max",
+        func: eval_max,
+    },
+    Builtin {
+        name: "gcd",
+        func: eval_gcd,
+    },
+    Builtin {
+        name: "lcm",
+        func: eval_lcm,
+    },
+    Builtin {
+        name: "expt",
+        func: eval_expt,
+    },
+    Builtin {
+        name: "square",
+        func: eval_square,
+    },
+    Builtin {
+        name: "sqrt",
+        func: eval_sqrt,
+    },
+    Builtin {
+        name: "exp",
+        func: eval_exp,
+    },
+    Builtin {
+        name: "log",
+        func: eval_log,
+    },
+    Builtin {
+        name: "sin",
+        func: eval_sin,
+    },
+    Builtin {
+        name: "cos",
+        func: eval_cos,
+    },
+    Builtin {
+        name: "tan",
+        func: eval_tan,
+    },
+    Builtin {
+        name: "asin",
+        func: eval_asin,
+    },
+    Builtin {
+        name: "acos",
+        func: eval_acos,
+    },
+    Builtin {
+        name: "atan",
+        func: eval_atan,
+    },
+    Builtin {
+        name: "make-rectangular",
+        func: eval_make_rectangular,
+    },
+    Builtin {
+        name: "make-polar",
+        func: eval_make_polar,
+    },
+    Builtin {
+        name: "real-part",
+        func: eval_real_part,
+    },
+    Builtin {
+        name: "imag-part",
+        func: eval_imag_part,
+    },
+    Builtin {
+        name: "magnitude",
+        func: eval_magnitude,
+    },
+    Builtin {
+        name: "angle",
+        func: eval_angle,
+    },
+    Builtin {
+        name: "floor",
+        func: eval_floor,
+    },
+    Builtin {
+        name: "ceiling",
+        func: eval_ceiling,
+    },
+    Builtin {
+        name: "round",
+        func: eval_round,
+    },
+    Builtin {
+        name: "truncate",
+        func: eval_truncate,
+    },
+    Builtin {
+        name: "numerator",
+        func: eval_numerator,
+    },
+    Builtin {
+        name: "denominator",
+        func: eval_denominator,
+    },
+    Builtin {
+        name: "zero?",
+        func: eval_is_zero,
+    },
+    Builtin {
+        name: "positive?",
+        func: eval_is_positive,
+    },
+    Builtin {
+        name: "negative?",
+        func: eval_is_negative,
+    },
+    Builtin {
+        name: "odd?",
+        func: eval_is_odd,
+    },
+    Builtin {
+        name: "even?",
+        func: eval_is_even,
+    },
+    Builtin {
+        name: "number->string",
+        func: eval_number_to_string,
+    },
+    Builtin {
+        name: "string->number",
+        func: eval_string_to_number,
+    },
+];
+
+// Every argument is checked to be a number before any is compared. Only = takes complex numbers
+fn compare_numbers(
+    list: Vec<LispVal>,
+    check: fn(&LispVal) -> Result<Rank, EvalError>,
+    accepts: fn(Ordering) -> bool,
+) -> Result<LispVal, EvalError> {
+    compare_chain(
+        &list,
+        |x| check(x).map(|_| x),
+        |a, b| compare(a, b),
+        accepts,
+    )
+}
+
+fn eval_num_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_numbers(list, rank, Ordering::is_eq)
+}
+
+fn eval_num_less(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_numbers(list, real_rank, Ordering::is_lt)
+}
+
+fn eval_num_greater(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_numbers(list, real_rank, Ordering::is_gt)
+}
+
+fn eval_num_less_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_numbers(list, real_rank, Ordering::is_le)
+}
+
+fn eval_num_greater_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_numbers(list, real_rank, Ordering::is_ge)
+}
+
+// Folds the arguments of + or * from the left. With no arguments the result is the identity of
+// the operation, and a lone argument only has to be a number
+fn fold_bin(
+    list: Vec<LispVal>,
+    ops: &Ops,
+    identity: i64,
+    env: &Environment,
+) -> Result<LispVal, EvalError> {
+    let mut args = list.into_iter();
+    let Some(mut result) = args.next() else {
+        return Ok(LispVal::Number(identity));
+    };
+    rank(&result)?;
+    for x in args {
+        result = arith(&result, &x, ops, env)?;
+    }
+    Ok(result)
+}
+
+fn eval_bin_mult(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    fold_bin(list, &MUL, 1, env)
+}
+
+// (- x) is the negation of x
+fn eval_bin_sub(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let mut args = list.into_iter();
+    let mut result = match (args.next(), args.len()) {
+        (Some(x), 0) => return negate(&x, env),
+        (Some(x), _) => x,
+        (None, _) => return Err(EvalError::arity(1, None, 0)),
+    };
+    for x in args {
+        result = arith(&result, &x, &SUB, env)?;
+    }
+    Ok(result)
+}
+
+fn eval_bin_add(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    fold_bin(list, &ADD, 0, env)
+}
+
+// Inexact zeros keep their sign, which subtracting from an exact 0 would lose
+fn negate(x: &LispVal, env: &Environment) -> Result<LispVal, EvalError> {
+    match x {
+        LispVal::Float(f) => Ok(LispVal::Float(-f)),
+        LispVal::Complex(z) => Ok(LispVal::Complex(Complex {
+            re: -z.re,
+            im: -z.im,
+        })),
+        _ => arith(&LispVal::Number(0), x, &SUB, env),
+    }
+}
+
+// (/ x) is the reciprocal of x. Dividing by an exact zero is an error even when the dividend is
+// inexact, while an inexact zero gives an infinity or NaN
+fn eval_bin_div(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let mut args = list.into_iter();
+    let mut result = match (args.next(), args.len()) {
+        (Some(x), 0) => {
+            args = vec![x].into_iter();
+            LispVal::Number(1)
+        }
+        (Some(x), _) => x,
+        (None, _) => return Err(EvalError::arity(1, None, 0)),
+    };
+    for x in args {
+        if matches!(x, LispVal::Number(0)) {
+            return Err(ErrorKind::DivisionByZero.into());
+        }
+        result = arith(&result, &x, &DIV, env)?;
+    }
+    Ok(result)
+}
+
+fn eval_truncate_div(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [a, b] = fixed_args(list)?;
+    Ok(LispVal::Values(
+        divide_integers(&a, &b, false, env)?.to_vec(),
+    ))
+}
+
+fn eval_floor_div(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [a, b] = fixed_args(list)?;
+    Ok(LispVal::Values(
+        divide_integers(&a, &b, true, env)?.to_vec(),
+    ))
+}
+
+fn eval_truncate_quotient(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [a, b] = fixed_args(list)?;
+    let [quot, _] = divide_integers(&a, &b, false, env)?;
+    Ok(quot)
+}
+
+fn eval_truncate_remainder(
+    list: Vec<LispVal>,
+    env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let [a, b] = fixed_args(list)?;
+    let [_, rem] = divide_integers(&a, &b, false, env)?;
+    Ok(rem)
+}
+
+fn eval_floor_quotient(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [a, b] = fixed_args(list)?;
+    let [quot, _] = divide_integers(&a, &b, true, env)?;
+    Ok(quot)
+}
+
+fn eval_floor_remainder(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [a, b] = fixed_args(list)?;
+    let [_, rem] = divide_integers(&a, &b, true, env)?;
+    Ok(rem)
+}
+
+// Gives the root and what is left over, k = s*s + r
+fn eval_exact_integer_sqrt(
+    list: Vec<LispVal>,
+    env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let [k] = fixed_args(list)?;
+    let n = match to_bigint(&k) {
+        Some(n) if !n.is_negative() => n,
+        _ => return Err(EvalError::wrong_type("a non-negative exact integer", &k)),
+    };
+    let tick = &mut |ops| env.work(ops);
+    let root = isqrt(&n, tick)?;
+    let rest = n.sub(&root.mul_metered(&root, tick)?);
+    Ok(LispVal::Values(vec![integer(root), integer(rest)]))
+}
+
+fn eval_exact(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    match x {
+        LispVal::Float(f) => {
+            float_to_exact(f).ok_or_else(|| EvalError::wrong_type("a finite number", &x))
+        }
+        _ => real_rank(&x).map(|_| x),
+    }
+}
+
+fn eval_inexact(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    if rank(&x)? == Rank::Complex {
+        return Ok(x);
+    }
+    Ok(LispVal::Float(to_f64(&x)))
+}
+
+fn eval_is_exact(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(rank(&x)? < Rank::Real))
+}
+
+fn eval_is_inexact(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(rank(&x)? >= Rank::Real))
+}
+
+// The type predicates take any object and are false for non-numbers
+fn eval_is_number(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(rank(&x).is_ok()))
+}
+
+fn eval_is_real(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(real_rank(&x).is_ok()))
+}
+
+fn eval_is_exact_integer(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(matches!(
+        x,
+        LispVal::Number(_) | LispVal::BigInt(_)
+    )))
+}
+
+fn eval_is_rational(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(match x {
+        LispVal::Number(_) | LispVal::BigInt(_) | LispVal::Rational(_) => true,
+        LispVal::Float(f) => f.is_finite(),
+        _ => false,
+    }))
+}
+
+fn eval_is_integer(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(is_integer(&x)))
+}
+
+// Tests both parts of a number, the imaginary part of a real number being zero. Exact numbers are
+// never NaN or infinite
+fn float_test(list: Vec<LispVal>, test: fn(Complex) -> bool) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    rank(&x)?;
+    Ok(LispVal::Bool(test(to_complex(&x))))
+}
+
+fn eval_is_nan(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    float_test(list, |z| z.re.is_nan() || z.im.is_nan())
+}
+
+fn eval_is_infinite(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    float_test(list, |z| z.re.is_infinite() || z.im.is_infinite())
+}
+
+fn eval_is_finite(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    float_test(list, |z| z.re.is_finite() && z.im.is_finite())
+}
+
+fn inexact(val: &LispVal) -> LispVal {
+    LispVal::Float(to_f64(val))
+}
+
+fn eval_abs(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    real_rank(&x)?;
+    abs(x, env)
+}
+
+fn abs(x: LispVal, env: &Environment) -> Result<LispVal, EvalError> {
+    if let LispVal::Float(f) = x {
+        return Ok(LispVal::Float(f.abs()));
+    }
+    if compare(&x, &LispVal::Number(0))? == Some(Ordering::Less) {
+        return negate(&x, env);
+    }
+    Ok(x)
+}
+
+// The result is inexact when any argument is, and NaN when any argument is NaN
+fn extremum(list: Vec<LispVal>, wanted: Ordering) -> Result<LispVal, EvalError> {
+    let mut args = list.into_iter();
+    let Some(mut result) = args.next() else {
+        return Err(EvalError::arity(1, None, 0));
+    };
+    let mut any_inexact = real_rank(&result)? == Rank::Real;
+    for x in args {
+        any_inexact |= real_rank(&x)? == Rank::Real;
+        match compare(&x, &result)? {
+            Some(ordering) if ordering == wanted => result = x,
+            None => result = LispVal::Float(f64::NAN),
+            _ => {}
+        }
+    }
+    Ok(if any_inexact {
+        inexact(&result)
+    } else {
+        result
+    })
+}
+
+fn eval_min(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    extremum(list, Ordering::Less)
+}
+
+fn eval_max(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    extremum(list, Ordering::Greater)
+}
+
+// The arguments of gcd and lcm as exact integers, and whether any of them was inexact
+fn integer_args(list: &[LispVal]) -> Result<(Vec<BigInt>, bool), EvalError> {
+    let mut ints = Vec::with_capacity(list.len());
+    let mut any_inexact = false;
+    for x in list {
+        let exact = match x {
+            LispVal::Float(f) if is_integer(x) => {
+                any_inexact = true;
+                float_to_exact(*f).as_ref().and_then(to_bigint)
+            }
+            _ => to_bigint(x),
+        };
+        ints.push(exact.ok_or_else(|| EvalError::wrong_type("an integer", x))?);
+    }
+    Ok((ints, any_inexact))
+}
+
+fn eval_gcd(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let (ints, any_inexact) = integer_args(&list)?;
+    let tick = &mut |ops| env.work(ops);
+    let mut gcd = BigInt::zero();
+    for x in ints {
+        gcd = gcd.gcd_metered(&x, tick)?;
+    }
+    let result = integer(gcd);
+    Ok(if any_inexact {
+        inexact(&result)
+    } else {
+        result
+    })
+}
+
+fn eval_lcm(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let (ints, any_inexact) = integer_args(&list)?;
+    let tick = &mut |ops| env.work(ops);
+    let mut lcm = BigInt::from_i64(1);
+    for x in ints {
+        let product = lcm.mul_metered(&x, tick)?.abs();
+        match product.div_rem_metered(&lcm.gcd_metered(&x, tick)?, tick)? {
+            Some((q, _)) => lcm = q,
+            None => lcm = BigInt::zero(),
+        }
+    }
+    let result = integer(lcm);
+    Ok(if any_inexact {
+        inexact(&result)
+    } else {
+        result
+    })
+}
+
+// An exact base to an exact integer power stays exact, and a complex base is multiplied out for
+// one. Anything else goes through doubles, and over the complex numbers when the result is not
+// real, like a negative base to a fractional power
+fn eval_expt(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [base, power] = fixed_args(list)?;
+    let base_rank = rank(&base)?;
+    if let LispVal::Number(n) = power {
+        if base_rank != Rank::Real {
+            return exact_pow(&base, n, env);
+        }
+    }
+    if base_rank.max(rank(&power)?) != Rank::Complex {
+        let (x, y) = (to_f64(&base), to_f64(&power));
+        let result = x.powf(y);
+        if !result.is_nan() || x.is_nan() || y.is_nan() {
+            return Ok(LispVal::Float(result));
+        }
+    }
+    Ok(complex(to_complex(&base).pow(to_complex(&power))))
+}
+
+fn exact_pow(base: &LispVal, n: i64, env: &Environment) -> Result<LispVal, EvalError> {
+    let bits = to_rational(base).map_or(0, |x| x.numer.bits().max(x.denom.bits()));
+    env.allocate((bits.saturating_mul(n.unsigned_abs()) / 8) as usize)?;
+    let mut result = LispVal::Number(1);
+    let mut square = base.clone();
+    let mut k = n.unsigned_abs();
+    while k > 0 {
+        if k & 1 == 1 {
+            result = arith(&result, &square, &MUL, env)?;
+        }
+        k >>= 1;
+        if k > 0 {
+            square = arith(&square, &square, &MUL, env)?;
+        }
+    }
+    if n < 0 {
+        return arith(&LispVal::Number(1), &result, &DIV, env);
+    }
+    Ok(result)
+}
+
+fn eval_square(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    arith(&x, &x, &MUL, env)
+}
+
+// Exact when the argument is an exact square of a rational, like 9/4
+fn eval_sqrt(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    if rank(&x)? < Rank::Real {
+        if let Some(r) = to_rational(&x).filter(|r| !r.numer.is_negative()) {
+            let tick = &mut |ops| env.work(ops);
+            let (numer, denom) = (isqrt(&r.numer, tick)?, isqrt(&r.denom, tick)?);
+            if numer.mul_metered(&numer, tick)? == r.numer
+                && denom.mul_metered(&denom, tick)? == r.denom
+            {
+                return Ok(exact(Rational { numer, denom }));
+            }
+        }
+    }
+    transcendental(vec![x], f64::sqrt, Complex::sqrt)
+}
+
+// Natural logarithm that also works for exact numbers too large for a double
+fn ln(val: &LispVal) -> f64 {
+    let big_ln = |x: &BigInt| {
+        let shift = x.bits().saturating_sub(64);
+        x.shr(shift).to_f64().ln() + shift as f64 * std::f64::consts::LN_2
+    };
+    match to_rational(val) {
+        Some(r) if !r.numer.is_negative() => big_ln(&r.numer) - big_ln(&r.denom),
+        _ => to_f64(val).ln(),
+    }
+}
+
+// Applies a function to a single number of any exactness. It is taken over the complex numbers
+// when the argument is complex or outside the real domain, like the square root of -4
+fn transcendental(
+    list: Vec<LispVal>,
+    real: fn(f64) -> f64,
+    over_complex: fn(Complex) -> Complex,
+) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    if rank(&x)? != Rank::Complex {
+        let f = to_f64(&x);
+        let result = real(f);
+        if !result.is_nan() || f.is_nan() {
+            return Ok(LispVal::Float(result));
+        }
+    }
+    Ok(complex(over_complex(to_complex(&x))))
+}
+
+fn eval_exp(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    transcendental(list, f64::exp, Complex::exp)
+}
+
+fn natural_log(x: &LispVal) -> Result<LispVal, EvalError> {
+    if rank(x)? != Rank::Complex {
+        let result = ln(x);
+        if !result.is_nan() || to_f64(x).is_nan() {
+            return Ok(LispVal::Float(result));
+        }
+    }
+    Ok(complex(to_complex(x).ln()))
+}
+
+// (log z) is the natural logarithm and (log z b) the logarithm to base b
+fn eval_log(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    match list.as_slice() {
+        [x] => natural_log(x),
+        [x, base] => arith(&natural_log(x)?, &natural_log(base)?, &DIV, env),
+        _ => Err(EvalError::arity(1, Some(2), list.len())),
+    }
+}
+
+fn eval_sin(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    transcendental(list, f64::sin, Complex::sin)
+}
+
+fn eval_cos(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    transcendental(list, f64::cos, Complex::cos)
+}
+
+fn eval_tan(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    transcendental(list, f64::tan, Complex::tan)
+}
+
+fn eval_asin(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    transcendental(list, f64::asin, Complex::asin)
+}
+
+fn eval_acos(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    transcendental(list, f64::acos, Complex::acos)
+}
+
+// (atan y x) is the angle of the point (x, y), which has to be real
+fn eval_atan(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    match list.as_slice() {
+        [_] => transcendental(list, f64::atan, Complex::atan),
+        [y, x] => {
+            real_rank(y)?;
+            real_rank(x)?;
+            Ok(LispVal::Float(to_f64(y).atan2(to_f64(x))))
+        }
+        _ => Err(EvalError::arity(1, Some(2), list.len())),
+    }
+}
+
+fn eval_magnitude(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [z] = fixed_args(list)?;
+    match z {
+        LispVal::Complex(z) => Ok(LispVal::Float(z.magnitude())),
+        _ => abs(z, env),
+    }
+}
+
+// The angle of an exact non-negative number is exact zero
+fn eval_angle(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [z] = fixed_args(list)?;
+    match rank(&z)? {
+        Rank::Integer | Rank::Rational
+            if compare(&z, &LispVal::Number(0))? != Some(Ordering::Less) =>
+        {
+            Ok(LispVal::Number(0))
+        }
+        _ => Ok(LispVal::Float(to_complex(&z).angle())),
+    }
+}
+
+fn eval_real_part(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [z] = fixed_args(list)?;
+    match z {
+        LispVal::Complex(z) => Ok(LispVal::Float(z.re)),
+        _ => rank(&z).map(|_| z),
+    }
+}
+
+// The imaginary part of a real number is exact zero
+fn eval_imag_part(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [z] = fixed_args(list)?;
+    match z {
+        LispVal::Complex(z) => Ok(LispVal::Float(z.im)),
+        _ => rank(&z).map(|_| LispVal::Number(0)),
+    }
+}
+
+fn eval_make_rectangular(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [re, im] = fixed_args(list)?;
+    real_rank(&re)?;
+    real_rank(&im)?;
+    if matches!(im, LispVal::Number(0)) {
+        return Ok(re);
+    }
+    Ok(complex(Complex {
+        re: to_f64(&re),
+        im: to_f64(&im),
+    }))
+}
+
+fn eval_make_polar(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [magnitude, angle] = fixed_args(list)?;
+    real_rank(&magnitude)?;
+    real_rank(&angle)?;
+    if matches!(angle, LispVal::Number(0)) {
+        return Ok(magnitude);
+    }
+    Ok(complex(Complex::polar(to_f64(&magnitude), to_f64(&angle))))
+}
+
+#[derive(Clone, Copy)]
+enum Round {
+    Floor,
+    Ceiling,
+    Truncate,
+    Nearest, // Ties go to the even neighbour
+}
+
+// Integers are already rounded, fractions round to exact integers and doubles to doubles
+fn round(list: Vec<LispVal>, mode: Round) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    let r = match x {
+        LispVal::Float(f) => {
+            return Ok(LispVal::Float(match mode {
+                Round::Floor => f.floor(),
+                Round::Ceiling => f.ceil(),
+                Round::Truncate => f.trunc(),
+                Round::Nearest => f.round_ties_even(),
+            }))
+        }
+        LispVal::Rational(r) => r,
+        _ => return real_rank(&x).map(|_| x),
+    };
+    let (quot, rem) = match r.numer.div_rem(&r.denom) {
+        Some(x) => x,
+        None => return Err(ErrorKind::DivisionByZero.into()),
+    };
+    let one = BigInt::from_i64(1);
+    let rounded = match mode {
+        Round::Floor if rem.is_negative() => quot.sub(&one),
+        Round::Ceiling if !rem.is_negative() => quot.add(&one),
+        Round::Nearest => {
+            // twice the remainder against the denominator decides, a tie only happens at halves
+            let twice = rem.abs().shl(1);
+            let away = match twice.cmp(&r.denom) {
+                Ordering::Greater => true,
+                Ordering::Equal => quot.is_odd(),
+                Ordering::Less => false,
+            };
+            match (away, rem.is_negative()) {
+                (false, _) => quot,
+                (true, false) => quot.add(&one),
+                (true, true) => quot.sub(&one),
+            }
+        }
+        _ => quot,
+    };
+    Ok(integer(rounded))
+}
+
+fn eval_floor(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    round(list, Round::Floor)
+}
+
+fn eval_ceiling(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    round(list, Round::Ceiling)
+}
+
+fn eval_round(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    round(list, Round::Nearest)
+}
+
+fn eval_truncate(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    round(list, Round::Truncate)
+}
+
+// Numerator and denominator of the number in lowest terms. A double gives those of the exact
+// number it stands for, as doubles
+fn fraction_parts(list: Vec<LispVal>) -> Result<(LispVal, LispVal), EvalError> {
+    let [x] = fixed_args(list)?;
+    real_rank(&x)?;
+    let r = match x {
+        LispVal::Float(f) => float_to_exact(f),
+        _ => Some(x.clone()),
+    };
+    match r.as_ref().and_then(to_rational) {
+        Some(r) if rank(&x)? == Rank::Real => Ok((
+            LispVal::Float(r.numer.to_f64()),
+            LispVal::Float(r.denom.to_f64()),
+        )),
+        Some(r) => Ok((integer(r.numer), integer(r.denom))),
+        None if matches!(x, LispVal::Float(_)) => Err(EvalError::wrong_type("a finite number", &x)),
+        None => Err(EvalError::wrong_type("a number", &x)),
+    }
+}
+
+fn eval_numerator(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    Ok(fraction_parts(list)?.0)
+}
+
+fn eval_denominator(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    Ok(fraction_parts(list)?.1)
+}
+
+// How the argument compares with zero. None for NaN
+fn sign(list: Vec<LispVal>) -> Result<Option<Ordering>, EvalError> {
+    let [x] = fixed_args(list)?;
+    real_rank(&x)?;
+    compare(&x, &LispVal::Number(0))
+}
+
+// Complex numbers are never zero, since they always have an imaginary part
+fn eval_is_zero(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(
+        compare(&x, &LispVal::Number(0))? == Some(Ordering::Equal),
+    ))
+}
+
+fn eval_is_positive(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    Ok(LispVal::Bool(sign(list)? == Some(Ordering::Greater)))
+}
+
+fn eval_is_negative(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    Ok(LispVal::Bool(sign(list)? == Some(Ordering::Less)))
+}
+
+fn is_odd(list: Vec<LispVal>) -> Result<bool, EvalError> {
+    let [x] = fixed_args(list)?;
+    match x {
+        LispVal::Number(n) => Ok(n % 2 != 0),
+        LispVal::BigInt(n) => Ok(n.is_odd()),
+        LispVal::Float(f) if is_integer(&x) => Ok(f % 2.0 != 0.0),
+        _ => Err(EvalError::wrong_type("an integer", &x)),
+    }
+}
+
+fn eval_is_odd(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    Ok(LispVal::Bool(is_odd(list)?))
+}
+
+fn eval_is_even(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    Ok(LispVal::Bool(!is_odd(list)?))
+}
+
+// The optional radix argument of the conversions between numbers and strings
+fn radix_arg(list: &[LispVal]) -> Result<u32, EvalError> {
+    match list {
+        [_] => Ok(10),
+        [_, LispVal::Number(radix @ (2 | 8 | 10 | 16))] => Ok(*radix as u32),
+        [_, radix] => Err(EvalError::wrong_type("a radix of 2, 8, 10 or 16", radix)),
+        _ => Err(EvalError::arity(1, Some(2), list.len())),
+    }
+}
+
+// Inexact numbers can only be written in radix 10
+fn eval_number_to_string(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let radix = radix_arg(&list)?;
+    // a digit takes at least one bit and one byte
+    env.allocate(exact_bits(&list[0]) as usize)?;
+    let digits = |x: &BigInt| x.to_string_radix_metered(radix, &mut |ops| env.work(ops));
+    let text = match &list[0] {
+        LispVal::Number(_) | LispVal::BigInt(_) => {
+            to_bigint(&list[0]).map(|x| digits(&x)).transpose()?
+        }
+        LispVal::Rational(r) => Some(format!("{}/{}", digits(&r.numer)?, digits(&r.denom)?)),
+        LispVal::Float(f) if radix == 10 => Some(show_float(*f)),
+        LispVal::Complex(z) if radix == 10 => Some(z.to_string()),
+        LispVal::Float(_) | LispVal::Complex(_) => {
+            return Err(EvalError::wrong_type("an exact number", &list[0]))
+        }
+        x => return Err(EvalError::wrong_type("a number", x)),
+    };
+    Ok(LispVal::string(text.unwrap_or_default()))
+}
+
+// #f when the string is not a number in the radix
+fn eval_string_to_number(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let radix = radix_arg(&list)?;
+    match &list[0] {
+        LispVal::String(text) => {
+            Ok(parse_number(&text.borrow(), radix).unwrap_or(LispVal::Bool(false)))
+        }
+        x => Err(EvalError::wrong_type("a string", x)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str) -> String {
+        match parse_number(text, 10) {
+            Some(x) => x.show_val().trim_end().to_owned(),
+            None => "none".to_owned(),
+        }
+    }
+
+    #[test]
+    fn parse_and_show_numbers() {
+        assert_eq!(read("42"), "42");
+        assert_eq!(read("6/4"), "3/2");
+        assert_eq!(read("-6/3"), "-2");
+        assert_eq!(read("1/-2"), "none");
+        assert_eq!(read("1/0"), "none");
+        assert_eq!(read("1.5"), "1.5");
+        assert_eq!(read("2."), "2.0");
+        assert_eq!(read("-0.0"), "-0.0");
+        assert_eq!(read("1e300"), "1e300");
+        assert_eq!(read("1.5e-10"), "1.5e-10");
+        assert_eq!(read("+inf.0"), "+inf.0");
+        assert_eq!(read("-inf.0"), "-inf.0");
+        assert_eq!(read("+nan.0"), "+nan.0");
+        assert_eq!(read("inf"), "none");
+        assert_eq!(read("1e"), "none");
+    }
+
+    #[test]
+    fn float_exact_round_trip() {
+        for x in [0.5, -0.1, 1e300, 5e-324, 123456.789, -3.0] {
+            let exact = float_to_exact(x).expect("finite");
+            assert_eq!(to_f64(&exact), x);
+        }
+        assert_eq!(
+            float_to_exact(0.1).map(|x| x.show_val()),
+            Some("3602879701896397/36028797018963968 ".to_owned())
+        );
+        assert_eq!(float_to_exact(f64::NAN), None);
+    }
+}
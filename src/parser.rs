@@ -1,5 +1,8 @@
 use crate::lexer::{Lexer, LexerError};
-use crate::token::{LispVal, Token, TokenType};
+use crate::number;
+use crate::symbol::Symbol;
+use crate::token::{Ident, LispVal, Location, Token, TokenType};
+use std::fmt;
 
 pub type Program = Vec<LispVal>;
 
@@ -8,6 +11,15 @@ pub enum ParseError {
     Lexer(LexerError),
     PError(String),
 }
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Lexer(x) => write!(f, "{}", x),
+            ParseError::PError(x) => write!(f, "{}", x),
+        }
+    }
+}
+
 pub struct Parser<'a> {
     lex: &'a mut Lexer,
     cur_token: Token,
@@ -22,249 +34,125 @@ impl<'a> Parser<'a> {
             cur_token: Token {
                 literal: "".to_owned(),
                 kind: TokenType::ILLEGAL,
+                loc: Location::default(),
             },
             peek_token: Token {
                 literal: "".to_owned(),
                 kind: TokenType::ILLEGAL,
+                loc: Location::default(),
             },
             //errors: Vec::<ParseError>::new(),
         };
-        match p.next_token() {
-            Ok(()) => (),
-            Err(err) => return Err(err),
-        }
-        match p.next_token() {
-            Ok(()) => (),
-            Err(err) => return Err(err),
-        }
+        p.next_token()?;
+        p.next_token()?;
         Ok(p)
     }
 
+    // Comments never reach the parser functions, they are skipped while advancing
     pub fn next_token(&mut self) -> Result<(), ParseError> {
         self.cur_token = self.peek_token.to_owned();
-        let tok = self.lex.next_token();
-        match tok {
-            Ok(t) => {
-                self.peek_token = t;
-                Ok(())
+        loop {
+            let tok = self.lex.next_token().map_err(ParseError::Lexer)?;
+            if tok.kind != TokenType::COMMENT {
+                self.peek_token = tok;
+                return Ok(());
             }
-            Err(err) => Err(ParseError::Lexer(err)),
         }
     }
 
-    // fn cur_token_is(&self, t: TokenType) -> bool {
-    //     self.cur_token.kind == t
-    // }
-
-    // fn peek_token_is(&self, t: TokenType) -> bool {
-    //     self.peek_token.kind == t
-    // }
-
     pub fn parse_program(&mut self) -> Result<Program, ParseError> {
         let mut program: Program = Program::new();
-        let mut cur_t = self.cur_token.kind;
-        while cur_t != TokenType::EOF && self.lex.position < self.lex.input.len() {
-            let val = self.parse_lisp_val();
-            match val {
-                Ok(x) => program.push(x),
-                Err(err) => return Err(err),
-            }
-            
-
-            cur_t = self.cur_token.kind;
+        while self.cur_token.kind != TokenType::EOF {
+            program.push(self.parse_lisp_val()?);
         }
         Ok(program)
     }
 
+    // Parses one datum starting at the current token and advances past it
     fn parse_lisp_val(&mut self) -> Result<LispVal, ParseError> {
-        match self.cur_token.kind {
+        let val = match self.cur_token.kind {
             TokenType::LPAREN => {
-                match self.next_token(){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                }
-                match self.parse_list(&mut Vec::<LispVal>::new()) {
-                Ok(x) => {
-                    match self.next_token(){
-                        Ok(()) => (),
-                        Err(err) => return Err(err)
-                    }
-                    Ok(x)
-                }
-                Err(x) => Err(x),
-            }},
-            TokenType::QUOTE => {
-                let mut v = Vec::<LispVal>::new();
-                v.push(LispVal::Atom(self.cur_token.clone()));
-                match self.next_token() {
-                    Ok(()) => (),
-                    Err(err) => return Err(err),
-                }
-                match self.parse_list(&mut v) {
-                    Ok(x) => {
-                        match self.next_token() {
-                            Ok(()) => (),
-                            Err(err) => return Err(err),
-                        }
-                        Ok(x)
-                    }
-                    Err(x) => Err(x),
-                }
-            }
-            // Should handle define, let, quote, quasiquote, if, cond... etc
-            TokenType::IDENT
-            | TokenType::PLUS
-            | TokenType::MINUS
-            | TokenType::ASTERICK
-            | TokenType::SLASH
-            | TokenType::AND
-            | TokenType::BEGIN
-            //| TokenType::DEFINE
-            | TokenType::ELSE
-            | TokenType::IF
-            | TokenType::NOT
-            | TokenType::OR
-            | TokenType::LAMBDA
-            //| TokenType::LET
-            | TokenType::FALSE
-            | TokenType::TRUE
-            | TokenType::LT
-            | TokenType::EQ
-            | TokenType::LTEQ
-            | TokenType::GT
-            | TokenType::GTEQ => {
-                let x = self.parse_atom();
-                match self.next_token(){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                }
-                Ok(x)
-            }
-            TokenType::LET =>  match self.parse_let() {
-                Ok(x) => {
-                    match self.next_token(){
-                        Ok(()) => (),
-                        Err(err) => return Err(err)
-                    }
-                    Ok(x)
-                }
-                Err(err) => return Err(err),
-            },
-            //TokenType::DEFINE => {vec.push(self.parse_define().unwrap()),
-            TokenType::INT => match self.parse_number() {
-                Ok(x) => {
-                    match self.next_token(){
-                        Ok(()) => (),
-                        Err(err) => return Err(err)
-                    }
-                     Ok(x)
-                }
-                Err(err) => return Err(err),
-            },
-            TokenType::STRING => {
-                let x = self.parse_string();
-                match self.next_token(){
-                    Ok(()) => (),
-                    Err(err) => return Err(err)
-                }
-                Ok(x)
-            }
-            _ => Err(ParseError::PError(format!(
-                "Unknown Token in parse_lisp_val: Token literal: {} Token kind: {:?}",
-                self.cur_token.literal, self.cur_token.kind
-            ))),
-        }
+                self.next_token()?;
+                self.parse_list()?
+            }
+            // 'x reads as (quote x). The word quote itself is an ordinary atom
+            TokenType::QUOTE if self.cur_token.literal == "'" => {
+                let quote = LispVal::Atom(Ident {
+                    name: Symbol::intern("quote"),
+                    kind: TokenType::QUOTE,
+                    loc: self.cur_token.loc,
+                });
+                self.next_token()?;
+                let datum = self.parse_lisp_val()?;
+                return Ok(LispVal::List(vec![quote, datum]));
+            }
+            TokenType::FALSE => LispVal::Bool(false),
+            TokenType::TRUE => LispVal::Bool(true),
+            TokenType::INT => self.parse_number()?,
+            TokenType::STRING => self.parse_string(),
+            TokenType::CHAR => self.parse_char()?,
+            TokenType::RPAREN | TokenType::EOF | TokenType::DOT | TokenType::ILLEGAL => {
+                return Err(ParseError::PError(format!(
+                    "Unexpected Token in parse_lisp_val: Token literal: {} Token kind: {:?}",
+                    self.cur_token.literal, self.cur_token.kind
+                )))
+            }
+            _ => self.parse_atom(),
+        };
+        self.next_token()?;
+        Ok(val)
     }
 
-    fn parse_list(&mut self, vec: &mut Vec<LispVal>) -> Result<LispVal, ParseError> {
-        while self.cur_token.kind != TokenType::RPAREN && self.cur_token.kind != TokenType::EOF {
+    // Parses the elements of a list up to the closing parenthesis. The current token is left on
+    // the closing parenthesis
+    fn parse_list(&mut self) -> Result<LispVal, ParseError> {
+        let mut vec = Vec::<LispVal>::new();
+        loop {
             match self.cur_token.kind {
-                TokenType::LPAREN => {
-                    let mut temp_vec = Vec::<LispVal>::new();
-                    match self.next_token() {
-                             Ok(()) => (),
-                             Err(err) => return Err(err),
-                    } 
-                    match self.parse_list(&mut temp_vec) {
-                        Ok(x) => vec.push(x),
-                        Err(err) => return Err(err), //self.errors.push(err)
-                    }
+                TokenType::RPAREN => break,
+                TokenType::EOF => {
+                    return Err(ParseError::PError(
+                        "Unexpected end of input: list is missing a closing parenthesis".to_owned(),
+                    ))
                 }
-                TokenType::QUOTE => {
-                    let mut v = Vec::<LispVal>::new();
-                    v.push(LispVal::Atom(self.cur_token.clone()));
-                    match self.next_token() {
-                        Ok(()) => (),
-                        Err(err) => return Err(err),
+                TokenType::DOT => {
+                    if vec.is_empty() {
+                        return Err(ParseError::PError(
+                            "Dotted list needs at least one element before the dot".to_owned(),
+                        ));
                     }
-                    match self.parse_list(&mut v) {
-                        Ok(x) => vec.push(x),
-                        Err(x) => return Err(x),
-                    };
-                }
-                // Should handle define, let, if, cond... etc
-                TokenType::IDENT
-                | TokenType::PLUS
-                | TokenType::MINUS
-                | TokenType::ASTERICK
-                | TokenType::SLASH
-                | TokenType::AND
-                | TokenType::BEGIN
-                //| TokenType::DEFINE
-                | TokenType::ELSE
-                | TokenType::IF
-                | TokenType::NOT
-                | TokenType::OR
-                //| TokenType::LET
-                | TokenType::LT
-                | TokenType::EQ
-                | TokenType::LTEQ
-                | TokenType::GT
-                | TokenType::GTEQ => {
-                    vec.push(self.parse_atom());
-                }
-                TokenType::LET => vec.push(self.parse_let().unwrap()),
-                TokenType::LAMBDA => vec.push(self.parse_lambda().unwrap()),
-                TokenType::DEFINE => vec.push(self.parse_define().unwrap()),
-                TokenType::FALSE => vec.push(LispVal::Bool(false)),
-                TokenType::TRUE => vec.push(LispVal::Bool(true)),
-
-                TokenType::INT => match self.parse_number() {
-                    Ok(x) => {
-                        vec.push(x);
+                    self.next_token()?;
+                    let tail = self.parse_lisp_val()?;
+                    if self.cur_token.kind != TokenType::RPAREN {
+                        return Err(ParseError::PError(
+                            "Dotted list should have exactly one element after the dot".to_owned(),
+                        ));
                     }
-                    Err(err) => return Err(err),
-                },
-                TokenType::STRING => {
-                    vec.push(self.parse_string());
-                }
-                //Token
-                _ => {
-                    return Err(ParseError::PError(format!(
-                        "Unknown Token in parse_lisp_val: Token literal: {} Token kind: {:?}",
-                        self.cur_token.literal, self.cur_token.kind
-                    )))
+                    return Ok(LispVal::DottedList(vec, Box::new(tail)));
                 }
+                _ => vec.push(self.parse_lisp_val()?),
             }
-            match self.next_token() {
-                Ok(()) => (),
-                Err(err) => return Err(err),
-            }
         }
-        Ok(LispVal::List(vec.to_vec()))
+        if vec.is_empty() {
+            return Ok(LispVal::Nil);
+        }
+        Ok(LispVal::List(vec))
     }
 
     #[inline(always)]
     fn parse_atom(&self) -> LispVal {
-        LispVal::Atom(self.cur_token.to_owned())
+        LispVal::Atom(Ident {
+            name: Symbol::intern(&self.cur_token.literal),
+            kind: self.cur_token.kind,
+            loc: self.cur_token.loc,
+        })
     }
 
     #[inline(always)]
     fn parse_number(&self) -> Result<LispVal, ParseError> {
-        let int = self.cur_token.literal.parse::<i64>();
-        match int {
-            Ok(x) => Ok(LispVal::Number(x)),
-            Err(_) => Err(ParseError::PError(format!(
+        match number::parse_number(&self.cur_token.literal, 10) {
+            Some(x) => Ok(x),
+            None => Err(ParseError::PError(format!(
                 "Could not parse {} as a number",
                 self.cur_token.literal
             ))),
@@ -273,498 +161,195 @@ impl<'a> Parser<'a> {
 
     #[inline(always)]
     fn parse_string(&self) -> LispVal {
-        LispVal::String(self.cur_token.literal.to_owned())
-    }
-
-    fn parse_lambda(&mut self) -> Result<LispVal, ParseError> {
-        match self.next_token() {
-            Ok(()) => (),
-            Err(err) => return Err(err),
-        }
-        let x = match self.parse_lisp_val() {
-            Ok(l) => l,
-            Err(err) => return Err(err),
-        };
-        let y = match self.parse_lisp_val() {
-            Ok(l) => l,
-            Err(err) => return Err(err),
-        };
-        match self.next_token() {
-            Ok(()) => (),
-            Err(err) => return Err(err),
-        }
-        Ok(LispVal::Lamda(Box::new(x), Box::new(y)))
+        LispVal::string(self.cur_token.literal.to_owned())
     }
 
-    fn parse_define(&mut self) -> Result<LispVal, ParseError> {
-        let def = self.parse_atom();
-        match self.next_token() {
-            Ok(()) => (),
-            Err(err) => return Err(err),
-        }
-        println!("{:?}", self.cur_token);
-        let y = match self.parse_lisp_val() {
-            Ok(l) => l,
-            Err(err) => return Err(err),
-        };
-        println!("{:?}", y);
-        let z = match self.parse_lisp_val() {
-            Ok(l) => l,
-            Err(err) => return Err(err),
+    // A character is written as itself, by name as in #\space, or by its code as in #\x41
+    fn parse_char(&self) -> Result<LispVal, ParseError> {
+        let name = self.cur_token.literal.as_str();
+        let mut chars = name.chars();
+        let ch = match (chars.next(), chars.next()) {
+            (Some(ch), None) => Some(ch),
+            _ => match name {
+                "alarm" => Some('\u{7}'),
+                "backspace" => Some('\u{8}'),
+                "delete" => Some('\u{7f}'),
+                "escape" => Some('\u{1b}'),
+                "newline" => Some('\n'),
+                "null" => Some('\0'),
+                "return" => Some('\r'),
+                "space" => Some(' '),
+                "tab" => Some('\t'),
+                _ => name
+                    .strip_prefix('x')
+                    .and_then(|x| u32::from_str_radix(x, 16).ok())
+                    .and_then(char::from_u32),
+            },
         };
-        println!("{:?}", z);
-        Ok(LispVal::List(vec![def, y, z]))
-    }
-    fn parse_let(&mut self) -> Result<LispVal, ParseError> {
-        let l = self.parse_atom();
-        match self.next_token() {
-            Ok(()) => (),
-            Err(err) => return Err(err),
-        }
-        //println!("{:?}", l);
-        match self.next_token() {
-            Ok(()) => (),
-            Err(err) => return Err(err),
-        }
-        let mut v = Vec::<LispVal>::new();
-        let pairs = self.parse_list(&mut v).unwrap();
-        println!("pairs {:?}", pairs);
-        let mut v2 = Vec::<LispVal>::new();
-        match self.next_token() {
-            Ok(()) => (),
-            Err(err) => return Err(err),
+        match ch {
+            Some(ch) => Ok(LispVal::Char(ch)),
+            None => Err(ParseError::PError(format!(
+                "Unknown character name #\\{}",
+                name
+            ))),
         }
-        let eval = self.parse_list(&mut v2).unwrap();
-        println!("eval {:?}",eval);
-        Ok(LispVal::List(vec![l,pairs,eval]))
-
     }
 }
 
-
 #[cfg(test)]
 mod tests {
-    use crate::eval::{eval_prog, Environment};
-
     use super::*;
+    use crate::test_util::show;
+
+    // Parses a program and writes each datum back out, with code lists and atoms shown the way
+    // they were read
+    fn parse(input: &str) -> Vec<String> {
+        let mut lex = Lexer::init_lex(input.to_owned());
+        let mut par = Parser::init_parser(&mut lex).expect("parser should start");
+        let prog = par.parse_program().expect("program should parse");
+        prog.iter().map(write).collect()
+    }
+
+    fn write(val: &LispVal) -> String {
+        let items = |x: &[LispVal]| x.iter().map(write).collect::<Vec<_>>().join(" ");
+        match val {
+            LispVal::Atom(x) => x.name.to_string(),
+            LispVal::List(x) => format!("({})", items(x)),
+            LispVal::DottedList(x, y) => format!("({} . {})", items(x), write(y)),
+            x => x.show_val().trim_end().to_owned(),
+        }
+    }
+
     #[test]
     fn parse_let_mult_with_neg() {
         let input = "(let ((x -2) (y 3)) (* x y))";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(parse(input), ["(let ((x -2) (y 3)) (* x y))"]);
+        assert_eq!(show(input), "-6");
     }
 
     #[test]
     fn parse_let_sub() {
         let input = "(let ((x 2 ) (y 3)) (- x y))";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(parse(input), ["(let ((x 2) (y 3)) (- x y))"]);
+        assert_eq!(show(input), "-1");
     }
 
     #[test]
     fn parse_let_add() {
         let input = "(let ((x 2 ) (y 3)) (+ x y))";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(parse(input), ["(let ((x 2) (y 3)) (+ x y))"]);
+        assert_eq!(show(input), "5");
     }
 
     #[test]
     fn parse_let_cond_lt() {
         let input = "(let ((x 2 ) (y 3)) (<  x y))";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(parse(input), ["(let ((x 2) (y 3)) (< x y))"]);
+        assert_eq!(show(input), "#t");
     }
 
     #[test]
     fn parse_let_cond_gt() {
         let input = "(let ((x 2 ) (y 3)) (>  x y))";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val())
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(parse(input), ["(let ((x 2) (y 3)) (> x y))"]);
+        assert_eq!(show(input), "#f");
     }
 
     #[test]
     fn parse_let_and_lambda() {
-        let input ="(let ((x ((lambda (x) (+ x x)) 3))) (* x x))";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val())
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        let input = "(let ((x ((lambda (x) (+ x x)) 3))) (* x x))";
+        assert_eq!(
+            parse(input),
+            ["(let ((x ((lambda (x) (+ x x)) 3))) (* x x))"]
+        );
+        assert_eq!(show(input), "36");
     }
 
     #[test]
     fn parse_more_calc() {
-        let input = "(+ (* 3 5) (+ 1 2))"; // should return 18
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val())
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        let input = "(+ (* 3 5) (+ 1 2))";
+        assert_eq!(parse(input), ["(+ (* 3 5) (+ 1 2))"]);
+        assert_eq!(show(input), "18");
     }
 
     #[test]
     fn parse_define() {
         let input = "(define x 3) (* x 3)";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(parse(input), ["(define x 3)", "(* x 3)"]);
+        assert_eq!(show(input), "9");
     }
 
     #[test]
     fn parse_define_proc() {
         let input = "(define (p x y) (+ x y ))";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(parse(input), ["(define (p x y) (+ x y))"]);
+        assert_eq!(show(input), "");
     }
 
     #[test]
     fn parse_apply_proc() {
         let input = "(define (p x y z) (+ x y z)) (p 10 2 3)";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(parse(input), ["(define (p x y z) (+ x y z))", "(p 10 2 3)"]);
+        assert_eq!(show(input), "15");
     }
 
     #[test]
     fn parse_apply_proc2() {
         let input = "(define (circumference radius) (* 3 radius radius)) (circumference 5)";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(
+            parse(input),
+            [
+                "(define (circumference radius) (* 3 radius radius))",
+                "(circumference 5)"
+            ]
+        );
+        assert_eq!(show(input), "75");
     }
 
-    // FIXME not working at all leads to error evaluating lambda parameters
     #[test]
     fn parse_apply_proc3() {
         let input = "(define (flip fn) (lambda (a b) (fn b a))) ((flip -) 5 8)";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(
+            parse(input),
+            [
+                "(define (flip fn) (lambda (a b) (fn b a)))",
+                "((flip -) 5 8)"
+            ]
+        );
+        assert_eq!(show(input), "3");
     }
 
     #[test]
     fn parse_lambda_eval() {
         let input = "((lambda (x) (* x x)) 3)";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(parse(input), ["((lambda (x) (* x x)) 3)"]);
+        assert_eq!(show(input), "9");
     }
 
     #[test]
     fn parse_lambda_eval2() {
         let input = "((lambda (x y) (* x y)) 3 4)";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        assert_eq!(parse(input), ["((lambda (x y) (* x y)) 3 4)"]);
+        assert_eq!(show(input), "12");
     }
 
     #[test]
     fn parse_quote() {
-        let input = "(\'(* x x))";
-        let mut lex = Lexer::init_lex(input.to_owned());
-        let par = Parser::init_parser(&mut lex);
-        let mut x = match par {
-            Ok(x) => x,
-            Err(e) => {
-                println!("{:?}", e);
-                panic!("false");
-            }
-        };
-        let prog = x.parse_program();
-        match prog {
-            Ok(x) => {
-                let mut env = Environment::init_env();
-                let x = eval_prog(x, &mut env).unwrap();
-                for i in x {
-                    println!("{}", i.show_val());
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                assert!(false);
-            }
-        }
+        let input = "('(* x x))";
+        assert_eq!(parse(input), ["((quote (* x x)))"]);
+        // the quoted list is read fine, but it is in operator position and a list is not a procedure
+        assert_eq!(
+            show(input),
+            "error: not a procedure: (* x x) at line 1, column 2"
+        );
+    }
+
+    #[test]
+    fn parse_quote_and_dotted_lists() {
+        assert_eq!(parse("'(* x x)"), ["(quote (* x x))"]);
+        assert_eq!(show("'(* x x)"), "(* x x)");
+        assert_eq!(parse("''a"), ["(quote (quote a))"]);
+        assert_eq!(parse("(a b . c) ()"), ["(a b . c)", "()"]);
+        assert_eq!(parse("(1 \"two\" #\\3 #t)"), ["(1 two 3 #t)"]);
     }
 }
@@ -0,0 +1,360 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bigint::BigInt;
+use crate::error::EvalError;
+use crate::eval::{fixed_args, Environment};
+use crate::number::{integer, to_bigint};
+use crate::symbol::Symbol;
+use crate::token::{Builtin, Ident, Lambda, LispVal, Location, TokenType};
+
+// Random numbers from SRFI 27. Sources are xoshiro256** generators, whose output depends only on
+// the seed, so the same seed gives the same sequence on every machine
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RandomSource {
+    state: Cell<[u64; 4]>,
+}
+
+impl Default for RandomSource {
+    // Every new source starts from the same fixed state
+    fn default() -> Self {
+        RandomSource::seeded(&[0])
+    }
+}
+
+impl RandomSource {
+    // Mixes the seed values through SplitMix64 to fill the state, which must never be all zeros
+    fn seeded(seed: &[u64]) -> Self {
+        let mut mix = seed
+            .iter()
+            .fold(0x853c_49e6_748f_ea9b, |acc, x| splitmix(acc ^ x).0);
+        let mut state = [0u64; 4];
+        for word in state.iter_mut() {
+            let (next, out) = splitmix(mix);
+            mix = next;
+            *word = out;
+        }
+        RandomSource {
+            state: Cell::new(state),
+        }
+    }
+
+    fn next_u64(&self) -> u64 {
+        let mut s = self.state.get();
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        self.state.set(s);
+        result
+    }
+
+    // Uniform in [0, n) without the bias of taking a remainder
+    fn below_u64(&self, n: u64) -> u64 {
+        let threshold = n.wrapping_neg() % n;
+        loop {
+            let x = self.next_u64();
+            if x >= threshold {
+                return x % n;
+            }
+        }
+    }
+
+    // Draws numbers with as many bits as n until one is below it
+    fn below(&self, n: &BigInt) -> BigInt {
+        let bits = n.bits();
+        loop {
+            let mut x = BigInt::zero();
+            let mut filled = 0;
+            while filled < bits {
+                let chunk = (bits - filled).min(32);
+                let digit = self.next_u64() >> (64 - chunk);
+                x = x.shl(chunk).add(&BigInt::from_i64(digit as i64));
+                filled += chunk;
+            }
+            if x < *n {
+                return x;
+            }
+        }
+    }
+
+    // Uniform in the open interval (0, 1)
+    fn real(&self) -> f64 {
+        loop {
+            let x = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+            if x > 0.0 {
+                return x;
+            }
+        }
+    }
+}
+
+// Returns the next mixing state and its output
+fn splitmix(state: u64) -> (u64, u64) {
+    let next = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = next;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (next, z ^ (z >> 31))
+}
+
+pub const RANDOM_BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "random-integer",
+        func: eval_random_integer,
+    },
+    Builtin {
+        name: "random-real",
+        func: eval_random_real,
+    },
+    Builtin {
+        name: "make-random-source",
+        func: eval_make_random_source,
+    },
+    Builtin {
+        name: "random-source?",
+        func: eval_is_random_source,
+    },
+    Builtin {
+        name: "random-source-pseudo-randomize!",
+        func: eval_random_source_pseudo_randomize,
+    },
+    Builtin {
+        name: "random-source-randomize!",
+        func: eval_random_source_randomize,
+    },
+    Builtin {
+        name: "random-source-make-integers",
+        func: eval_random_source_make_integers,
+    },
+    Builtin {
+        name: "random-source-make-reals",
+        func: eval_random_source_make_reals,
+    },
+];
+
+// Called by the procedures random-source-make-integers and random-source-make-reals hand out, with
+// their source as the first argument. They are not bound to any name
+const SOURCE_INTEGER: Builtin = Builtin {
+    name: "random-integer",
+    func: eval_source_integer,
+};
+
+const SOURCE_REAL: Builtin = Builtin {
+    name: "random-real",
+    func: eval_source_real,
+};
+
+fn source_of(val: &LispVal) -> Result<&RandomSource, EvalError> {
+    match val {
+        LispVal::RandomSource(x) => Ok(x),
+        _ => Err(EvalError::wrong_type("a random source", val)),
+    }
+}
+
+fn random_integer(source: &RandomSource, n: &LispVal) -> Result<LispVal, EvalError> {
+    match n {
+        LispVal::Number(x) if *x > 0 => Ok(LispVal::Number(source.below_u64(*x as u64) as i64)),
+        LispVal::BigInt(x) if !x.is_negative() => Ok(integer(source.below(x))),
+        _ => Err(EvalError::wrong_type("a positive exact integer", n)),
+    }
+}
+
+// (random-integer n) is uniform in [0, n), drawn from default-random-source
+fn eval_random_integer(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [n] = fixed_args(list)?;
+    random_integer(&env.random_source(), &n)
+}
+
+fn eval_random_real(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [] = fixed_args(list)?;
+    Ok(LispVal::Float(env.random_source().real()))
+}
+
+fn eval_source_integer(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [source, n] = fixed_args(list)?;
+    random_integer(source_of(&source)?, &n)
+}
+
+fn eval_source_real(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [source] = fixed_args(list)?;
+    Ok(LispVal::Float(source_of(&source)?.real()))
+}
+
+fn eval_make_random_source(
+    list: Vec<LispVal>,
+    _env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let [] = fixed_args(list)?;
+    Ok(LispVal::RandomSource(Rc::new(RandomSource::default())))
+}
+
+fn eval_is_random_source(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(matches!(x, LispVal::RandomSource(_))))
+}
+
+// (random-source-pseudo-randomize! s i j) puts s in a state that depends only on i and j, so each
+// pair of indices gives its own reproducible sequence
+fn eval_random_source_pseudo_randomize(
+    list: Vec<LispVal>,
+    _env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let [source, i, j] = fixed_args(list)?;
+    let source = source_of(&source)?;
+    let mut seed = Vec::new();
+    for x in [&i, &j] {
+        match to_bigint(x).and_then(|x| (!x.is_negative()).then_some(x)) {
+            // each index is mixed in 32 bits at a time, followed by its length
+            Some(mut x) => {
+                let bits = x.bits();
+                while !x.is_zero() {
+                    let low = x.and(&BigInt::from_i64(0xffff_ffff));
+                    seed.push(low.to_i64().unwrap_or(0) as u64);
+                    x = x.shr(32);
+                }
+                seed.push(bits);
+            }
+            None => return Err(EvalError::wrong_type("a non-negative exact integer", x)),
+        }
+    }
+    source.state.set(RandomSource::seeded(&seed).state.get());
+    Ok(LispVal::Void)
+}
+
+// Seeds from the clock, the one way to get a sequence that differs between runs
+fn eval_random_source_randomize(
+    list: Vec<LispVal>,
+    _env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let [source] = fixed_args(list)?;
+    let source = source_of(&source)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_nanos() as u64);
+    source.state.set(RandomSource::seeded(&[now]).state.get());
+    Ok(LispVal::Void)
+}
+
+// A procedure of the given parameters whose body calls builtin with source and the parameters
+fn source_procedure(
+    builtin: Builtin,
+    source: LispVal,
+    params: &[&str],
+    env: &Environment,
+) -> LispVal {
+    let atom = |name: &str| {
+        LispVal::Atom(Ident {
+            name: Symbol::intern(name),
+            kind: TokenType::IDENT,
+            loc: Location::default(),
+        })
+    };
+    let mut call = vec![LispVal::Fun(builtin), source];
+    call.extend(params.iter().map(|x| atom(x)));
+    LispVal::Lamda(Rc::new(Lambda {
+        name: Some(builtin.name.to_owned()),
+        params: params.iter().map(|x| Symbol::intern(x)).collect(),
+        rest: None,
+        body: vec![LispVal::List(call)],
+        env: env.scope(),
+    }))
+}
+
+fn eval_random_source_make_integers(
+    list: Vec<LispVal>,
+    env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let [source] = fixed_args(list)?;
+    source_of(&source)?;
+    Ok(source_procedure(SOURCE_INTEGER, source, &["n"], env))
+}
+
+fn eval_random_source_make_reals(
+    list: Vec<LispVal>,
+    env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    let [source] = fixed_args(list)?;
+    source_of(&source)?;
+    Ok(source_procedure(SOURCE_REAL, source, &[], env))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::eval_prog;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn run(input: &str) -> Vec<String> {
+        let mut lex = Lexer::init_lex(input.to_owned());
+        let mut par = Parser::init_parser(&mut lex).expect("parser should start");
+        let prog = par.parse_program().expect("program should parse");
+        match eval_prog(prog, &mut Environment::init_env()) {
+            Ok(x) => x
+                .iter()
+                .map(|x| x.show_val().trim_end().to_owned())
+                .collect(),
+            Err(e) => vec![format!("error: {}", e)],
+        }
+    }
+
+    #[test]
+    fn random_sequences_are_reproducible() {
+        // the first outputs of xoshiro256** from this seed, fixed so a change to the generator
+        // shows up as a failure
+        let source = RandomSource::default();
+        let first: Vec<u64> = (0..3).map(|_| source.next_u64()).collect();
+        assert_eq!(
+            first,
+            [
+                7722720979537242403,
+                11663294744589950186,
+                14150767017578310142
+            ]
+        );
+        let program = "(random-integer 1000) (random-integer 1000) (random-real)";
+        assert_eq!(run(program), run(program));
+        let seeded = "(define s (make-random-source))
+            (random-source-pseudo-randomize! s 3 4)
+            (define next (random-source-make-integers s))
+            (next 100000000000000000000)";
+        assert_eq!(run(seeded), run(seeded));
+        let other = seeded.replace("s 3 4", "s 4 3");
+        assert_ne!(run(seeded).last(), run(&other).last());
+    }
+
+    #[test]
+    fn random_values_stay_in_range() {
+        let source = RandomSource::default();
+        for n in [1u64, 2, 3, 10, 1 << 40, u64::MAX] {
+            for _ in 0..100 {
+                assert!(source.below_u64(n) < n);
+            }
+        }
+        let big = BigInt::parse("100000000000000000000000", 10).expect("valid number");
+        for _ in 0..100 {
+            let x = source.below(&big);
+            assert!(!x.is_negative() && x < big);
+        }
+        for _ in 0..100 {
+            let x = source.real();
+            assert!(x > 0.0 && x < 1.0);
+        }
+        let results = run(
+            "(define real (random-source-make-reals (make-random-source)))
+            (< 0 (real) 1)
+            (random-integer 1)
+            (random-source? default-random-source)",
+        );
+        assert_eq!(results[1..], ["#t", "0", "#t"]);
+        assert!(run("(random-integer 0)")[0].starts_with("error: wrong type"));
+        assert!(run("(random-integer 1.5)")[0].starts_with("error: wrong type"));
+    }
+}
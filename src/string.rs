@@ -0,0 +1,776 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::mem::size_of;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::character::{char_of, foldcase};
+use crate::error::EvalError;
+use crate::eval::{compare_chain, eval_proc, fixed_args, is_true, vec_to_list, Environment};
+use crate::list::{index_of, list_items};
+use crate::token::{Builtin, LispVal};
+
+// The R7RS string procedures and the common SRFI 13 helpers. Strings are stored as UTF-8 but every
+// index counts characters, never bytes
+
+pub const STRING_BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "string?",
+        func: eval_is_string,
+    },
+    Builtin {
+        name: "make-string",
+        func: eval_make_string,
+    },
+    Builtin {
+        name: "string",
+        func: eval_string,
+    },
+    Builtin {
+        name: "string-length",
+        func: eval_string_length,
+    },
+    Builtin {
+        name: "string-ref",
+        func: eval_string_ref,
+    },
+    Builtin {
+        name: "string-set!",
+        func: eval_string_set,
+    },
+    Builtin {
+        name: "substring",
+        func: eval_substring,
+    },
+    Builtin {
+        name: "string-append",
+        func: eval_string_append,
+    },
+    Builtin {
+        name: "string-copy",
+        func: eval_string_copy,
+    },
+    Builtin {
+        name: "string-copy!",
+        func: eval_string_copy_to,
+    },
+    Builtin {
+        name: "string-fill!",
+        func: eval_string_fill,
+    },
+    Builtin {
+        name: "string->list",
+        func: eval_string_to_list,
+    },
+    Builtin {
+        name: "list->string",
+        func: eval_list_to_string,
+    },
+    Builtin {
+        name: "string-upcase",
+        func: eval_string_upcase,
+    },
+    Builtin {
+        name: "string-downcase",
+        func: eval_string_downcase,
+    },
+    Builtin {
+        name: "string-foldcase",
+        func: eval_string_foldcase,
+    },
+    Builtin {
+        name: "string=?",
+        func: eval_string_eq,
+    },
+    Builtin {
+        name: "string<?",
+        func: eval_string_less,
+    },
+    Builtin {
+        name: "string>?",
+        func: eval_string_greater,
+    },
+    Builtin {
+        name: "string<=?",
+        func: eval_string_less_eq,
+    },
+    Builtin {
+        name: "string>=?",
+        func: eval_string_greater_eq,
+    },
+    Builtin {
+        name: "string-ci=?",
+        func: eval_string_ci_eq,
+    },
+    Builtin {
+        name: "string-ci<?",
+        func: eval_string_ci_less,
+    },
+    Builtin {
+        name: "string-ci>?",
+        func: eval_string_ci_greater,
+    },
+    Builtin {
+        name: "string-ci<=?",
+        func: eval_string_ci_less_eq,
+    },
+    Builtin {
+        name: "string-ci>=?",
+        func: eval_string_ci_greater_eq,
+    },
+    Builtin {
+        name: "string-map",
+        func: eval_string_map,
+    },
+    Builtin {
+        name: "string-for-each",
+        func: eval_string_for_each,
+    },
+    Builtin {
+        name: "string-index",
+        func: eval_string_index,
+    },
+    Builtin {
+        name: "string-contains",
+        func: eval_string_contains,
+    },
+    Builtin {
+        name: "string-join",
+        func: eval_string_join,
+    },
+    Builtin {
+        name: "string-split",
+        func: eval_string_split,
+    },
+    Builtin {
+        name: "string-trim",
+        func: eval_string_trim::<true, false>,
+    },
+    Builtin {
+        name: "string-trim-right",
+        func: eval_string_trim::<false, true>,
+    },
+    Builtin {
+        name: "string-trim-both",
+        func: eval_string_trim::<true, true>,
+    },
+    Builtin {
+        name: "string-pad",
+        func: eval_string_pad::<true>,
+    },
+    Builtin {
+        name: "string-pad-right",
+        func: eval_string_pad::<false>,
+    },
+];
+
+fn text_of(val: &LispVal) -> Result<&Rc<RefCell<String>>, EvalError> {
+    match val {
+        LispVal::String(x) => Ok(x),
+        _ => Err(EvalError::wrong_type("a string", val)),
+    }
+}
+
+fn arg_count(list: &[LispVal], min: usize, max: usize) -> Result<(), EvalError> {
+    if list.len() < min || list.len() > max {
+        return Err(EvalError::arity(min, Some(max), list.len()));
+    }
+    Ok(())
+}
+
+// Bytes taken by the chars from start up to end. ASCII text needs no search
+fn byte_range(text: &str, start: usize, end: usize) -> Range<usize> {
+    if text.is_ascii() {
+        return start..end;
+    }
+    let offset = |k| text.char_indices().nth(k).map_or(text.len(), |(i, _)| i);
+    offset(start)..offset(end)
+}
+
+fn char_count(text: &str) -> usize {
+    if text.is_ascii() {
+        text.len()
+    } else {
+        text.chars().count()
+    }
+}
+
+// Reads the optional start and end indices that follow the string, the whole string when they
+// are left out
+fn bounds(text: &str, args: &[LispVal]) -> Result<Range<usize>, EvalError> {
+    let len = char_count(text);
+    let start = args.first().map_or(Ok(0), index_of)?;
+    let end = args.get(1).map_or(Ok(len), index_of)?;
+    if end > len {
+        return Err(EvalError::wrong_type(
+            "an index within the string",
+            &args[1],
+        ));
+    }
+    if start > end {
+        return Err(EvalError::wrong_type(
+            "a start index before the end",
+            &args[0],
+        ));
+    }
+    Ok(start..end)
+}
+
+// The byte range of the single char at index k
+fn char_at(text: &str, k: &LispVal) -> Result<Range<usize>, EvalError> {
+    let index = index_of(k)?;
+    if index >= char_count(text) {
+        return Err(EvalError::wrong_type("an index within the string", k));
+    }
+    Ok(byte_range(text, index, index + 1))
+}
+
+fn new_string(text: String, env: &mut Environment) -> Result<LispVal, EvalError> {
+    env.allocate(text.len())?;
+    Ok(LispVal::string(text))
+}
+
+// SRFI 13 takes a character or a predicate where it looks for chars. A character matches only
+// itself
+fn char_matches(criterion: &LispVal, ch: char, env: &mut Environment) -> Result<bool, EvalError> {
+    match criterion {
+        LispVal::Char(x) => Ok(*x == ch),
+        LispVal::Fun(_) | LispVal::Lamda(_) | LispVal::CaseLamda(_) => Ok(is_true(&eval_proc(
+            criterion.clone(),
+            vec![LispVal::Char(ch)],
+            env,
+        )?)),
+        x => Err(EvalError::wrong_type("a character or predicate", x)),
+    }
+}
+
+fn eval_is_string(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(matches!(x, LispVal::String(_))))
+}
+
+// (make-string k char) with char left out fills the string with spaces
+fn eval_make_string(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    arg_count(&list, 1, 2)?;
+    let k = index_of(&list[0])?;
+    let fill = list.get(1).map_or(Ok(' '), char_of)?;
+    env.allocate(k.saturating_mul(fill.len_utf8()))?;
+    Ok(LispVal::string(std::iter::repeat_n(fill, k).collect()))
+}
+
+fn eval_string(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let text = list.iter().map(char_of).collect::<Result<String, _>>()?;
+    new_string(text, env)
+}
+
+fn eval_string_length(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    let len = char_count(&text_of(&x)?.borrow());
+    Ok(LispVal::Number(len as i64))
+}
+
+fn eval_string_ref(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x, k] = fixed_args(list)?;
+    let text = text_of(&x)?.borrow();
+    let range = char_at(&text, &k)?;
+    Ok(LispVal::Char(
+        text[range].chars().next().unwrap_or_default(),
+    ))
+}
+
+fn eval_string_set(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x, k, ch] = fixed_args(list)?;
+    let mut text = text_of(&x)?.borrow_mut();
+    let range = char_at(&text, &k)?;
+    text.replace_range(range, char_of(&ch)?.encode_utf8(&mut [0; 4]));
+    Ok(LispVal::Void)
+}
+
+fn eval_substring(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x, start, end] = fixed_args(list)?;
+    let text = text_of(&x)?.borrow();
+    let range = bounds(&text, &[start, end])?;
+    new_string(
+        text[byte_range(&text, range.start, range.end)].to_owned(),
+        env,
+    )
+}
+
+fn eval_string_append(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let mut text = String::new();
+    for x in &list {
+        text.push_str(&text_of(x)?.borrow());
+    }
+    new_string(text, env)
+}
+
+fn eval_string_copy(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    arg_count(&list, 1, 3)?;
+    let text = text_of(&list[0])?.borrow();
+    let range = bounds(&text, &list[1..])?;
+    new_string(
+        text[byte_range(&text, range.start, range.end)].to_owned(),
+        env,
+    )
+}
+
+// (string-copy! to at from start end) overwrites to from index at with chars of from. The chars
+// are read before any is written, so the two strings can be the same one
+fn eval_string_copy_to(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    arg_count(&list, 3, 5)?;
+    let source = {
+        let from = text_of(&list[2])?.borrow();
+        let range = bounds(&from, &list[3..])?;
+        from[byte_range(&from, range.start, range.end)].to_owned()
+    };
+    let mut to = text_of(&list[0])?.borrow_mut();
+    let at = index_of(&list[1])?;
+    let end = at + char_count(&source);
+    if end > char_count(&to) {
+        return Err(EvalError::wrong_type(
+            "an index leaving room for the copy",
+            &list[1],
+        ));
+    }
+    let range = byte_range(&to, at, end);
+    to.replace_range(range, &source);
+    Ok(LispVal::Void)
+}
+
+fn eval_string_fill(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    arg_count(&list, 2, 4)?;
+    let mut text = text_of(&list[0])?.borrow_mut();
+    let fill = char_of(&list[1])?;
+    let range = bounds(&text, &list[2..])?;
+    let filled: String = std::iter::repeat_n(fill, range.len()).collect();
+    let range = byte_range(&text, range.start, range.end);
+    text.replace_range(range, &filled);
+    Ok(LispVal::Void)
+}
+
+fn eval_string_to_list(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    arg_count(&list, 1, 3)?;
+    let text = text_of(&list[0])?.borrow();
+    let range = bounds(&text, &list[1..])?;
+    let chars: Vec<LispVal> = text[byte_range(&text, range.start, range.end)]
+        .chars()
+        .map(LispVal::Char)
+        .collect();
+    env.allocate(chars.len() * size_of::<LispVal>())?;
+    Ok(vec_to_list(chars))
+}
+
+fn eval_list_to_string(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    let text = list_items(&x)?
+        .iter()
+        .map(char_of)
+        .collect::<Result<String, _>>()?;
+    new_string(text, env)
+}
+
+fn map_text(
+    list: Vec<LispVal>,
+    env: &mut Environment,
+    f: fn(&str) -> String,
+) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    let text = f(&text_of(&x)?.borrow());
+    new_string(text, env)
+}
+
+// Case conversion follows Unicode and can change the length, as when ß becomes SS
+fn eval_string_upcase(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    map_text(list, env, str::to_uppercase)
+}
+
+fn eval_string_downcase(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    map_text(list, env, str::to_lowercase)
+}
+
+fn eval_string_foldcase(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    map_text(list, env, fold_case)
+}
+
+// Full case folding, which can make a string longer as when ß folds to ss. A char whose upper
+// case is more than one char folds to that upper case folded, the rest fold as chars do except
+// for İ, whose lower case is i with a combining dot
+fn fold_case(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for ch in text.chars().map(foldcase) {
+        let upper = ch.to_uppercase();
+        let lower = ch.to_lowercase();
+        if upper.len() > 1 {
+            folded.extend(upper.map(foldcase));
+        } else if lower.len() > 1 {
+            folded.extend(lower);
+        } else {
+            folded.push(ch);
+        }
+    }
+    folded
+}
+
+// Strings are ordered by their chars' code points, the -ci comparisons after folding case
+fn compare_strings(
+    list: Vec<LispVal>,
+    fold: fn(&str) -> String,
+    accepts: fn(Ordering) -> bool,
+) -> Result<LispVal, EvalError> {
+    compare_chain(
+        &list,
+        |x| Ok(fold(&text_of(x)?.borrow())),
+        |a, b| Ok(Some(a.cmp(b))),
+        accepts,
+    )
+}
+
+fn eval_string_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_strings(list, str::to_owned, Ordering::is_eq)
+}
+
+fn eval_string_less(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_strings(list, str::to_owned, Ordering::is_lt)
+}
+
+fn eval_string_greater(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_strings(list, str::to_owned, Ordering::is_gt)
+}
+
+fn eval_string_less_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_strings(list, str::to_owned, Ordering::is_le)
+}
+
+fn eval_string_greater_eq(
+    list: Vec<LispVal>,
+    _env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    compare_strings(list, str::to_owned, Ordering::is_ge)
+}
+
+fn eval_string_ci_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_strings(list, fold_case, Ordering::is_eq)
+}
+
+fn eval_string_ci_less(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    compare_strings(list, fold_case, Ordering::is_lt)
+}
+
+fn eval_string_ci_greater(
+    list: Vec<LispVal>,
+    _env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    compare_strings(list, fold_case, Ordering::is_gt)
+}
+
+fn eval_string_ci_less_eq(
+    list: Vec<LispVal>,
+    _env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    compare_strings(list, fold_case, Ordering::is_le)
+}
+
+fn eval_string_ci_greater_eq(
+    list: Vec<LispVal>,
+    _env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    compare_strings(list, fold_case, Ordering::is_ge)
+}
+
+// The chars of the string arguments that follow the procedure, by position. Stops at the end of
+// the shortest string
+fn columns(strings: &[LispVal]) -> Result<Vec<Vec<LispVal>>, EvalError> {
+    if strings.is_empty() {
+        return Err(EvalError::arity(2, None, 1));
+    }
+    let mut texts = Vec::<Vec<char>>::new();
+    for x in strings {
+        texts.push(text_of(x)?.borrow().chars().collect());
+    }
+    let len = texts.iter().map(Vec::len).min().unwrap_or(0);
+    Ok((0..len)
+        .map(|i| texts.iter().map(|x| LispVal::Char(x[i])).collect())
+        .collect())
+}
+
+fn eval_string_map(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let (proc, strings) = list
+        .split_first()
+        .ok_or_else(|| EvalError::arity(2, None, 0))?;
+    let mut text = String::new();
+    for chars in columns(strings)? {
+        text.push(char_of(&eval_proc(proc.clone(), chars, env)?)?);
+    }
+    new_string(text, env)
+}
+
+fn eval_string_for_each(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let (proc, strings) = list
+        .split_first()
+        .ok_or_else(|| EvalError::arity(2, None, 0))?;
+    for chars in columns(strings)? {
+        eval_proc(proc.clone(), chars, env)?;
+    }
+    Ok(LispVal::Void)
+}
+
+// (string-index s char-or-pred start end) gives the index of the first char that matches, #f when
+// none does
+fn eval_string_index(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    arg_count(&list, 2, 4)?;
+    let text = text_of(&list[0])?.borrow().clone();
+    let range = bounds(&text, &list[2..])?;
+    let chars = text.chars().enumerate().skip(range.start).take(range.len());
+    for (i, ch) in chars {
+        if char_matches(&list[1], ch, env)? {
+            return Ok(LispVal::Number(i as i64));
+        }
+    }
+    Ok(LispVal::Bool(false))
+}
+
+// The index where the second string first appears in the first one, #f when it does not
+fn eval_string_contains(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x, pattern] = fixed_args(list)?;
+    let (text, pattern) = (text_of(&x)?.borrow(), text_of(&pattern)?.borrow());
+    let found = text.find(pattern.as_str());
+    Ok(match found {
+        Some(byte) => LispVal::Number(char_count(&text[..byte]) as i64),
+        None => LispVal::Bool(false),
+    })
+}
+
+// (string-join list delimiter grammar) puts the delimiter, a space when left out, between the
+// strings. The grammar infix, strict-infix, prefix or suffix says where delimiters go
+fn eval_string_join(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    arg_count(&list, 1, 3)?;
+    let mut parts = Vec::<String>::new();
+    for x in list_items(&list[0])? {
+        parts.push(text_of(&x)?.borrow().clone());
+    }
+    let delimiter = match list.get(1) {
+        Some(x) => text_of(x)?.borrow().clone(),
+        None => " ".to_owned(),
+    };
+    let grammar = match list.get(2) {
+        Some(LispVal::Symbol(x)) => x.name(),
+        Some(x) => return Err(EvalError::wrong_type("a grammar symbol", x)),
+        None => "infix",
+    };
+    let joined = parts.join(&delimiter);
+    let text = match grammar {
+        "infix" => joined,
+        "strict-infix" if parts.is_empty() => {
+            return Err(EvalError::wrong_type(
+                "a non-empty list for strict-infix",
+                &list[0],
+            ))
+        }
+        "strict-infix" => joined,
+        "prefix" => parts
+            .iter()
+            .map(|x| format!("{}{}", delimiter, x))
+            .collect(),
+        "suffix" => parts
+            .iter()
+            .map(|x| format!("{}{}", x, delimiter))
+            .collect(),
+        _ => return Err(EvalError::wrong_type("a grammar symbol", &list[2])),
+    };
+    new_string(text, env)
+}
+
+// (string-split s delimiter) gives the fields between occurrences of a delimiter string or char,
+// empty ones included
+fn eval_string_split(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x, delimiter] = fixed_args(list)?;
+    let text = text_of(&x)?.borrow();
+    let delimiter = match &delimiter {
+        LispVal::Char(ch) => ch.to_string(),
+        _ => text_of(&delimiter)?.borrow().clone(),
+    };
+    let fields: Vec<String> = if delimiter.is_empty() {
+        text.chars().map(String::from).collect()
+    } else {
+        text.split(delimiter.as_str()).map(str::to_owned).collect()
+    };
+    env.allocate(text.len() + fields.len() * size_of::<LispVal>())?;
+    Ok(vec_to_list(
+        fields.into_iter().map(LispVal::string).collect(),
+    ))
+}
+
+// string-trim drops matching chars from the start, string-trim-right from the end and
+// string-trim-both from both. They drop whitespace when no char or predicate is given
+fn eval_string_trim<const LEFT: bool, const RIGHT: bool>(
+    list: Vec<LispVal>,
+    env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    arg_count(&list, 1, 2)?;
+    let chars: Vec<char> = text_of(&list[0])?.borrow().chars().collect();
+    let trimmed = |ch: char, env: &mut Environment| match list.get(1) {
+        Some(criterion) => char_matches(criterion, ch, env),
+        None => Ok(ch.is_whitespace()),
+    };
+    let (mut start, mut end) = (0, chars.len());
+    while LEFT && start < end && trimmed(chars[start], env)? {
+        start += 1;
+    }
+    while RIGHT && end > start && trimmed(chars[end - 1], env)? {
+        end -= 1;
+    }
+    new_string(chars[start..end].iter().collect(), env)
+}
+
+// (string-pad s len char) pads s on the left to len chars, or keeps only its last len chars when
+// it is longer. string-pad-right pads and truncates on the right
+fn eval_string_pad<const LEFT: bool>(
+    list: Vec<LispVal>,
+    env: &mut Environment,
+) -> Result<LispVal, EvalError> {
+    arg_count(&list, 2, 3)?;
+    let chars: Vec<char> = text_of(&list[0])?.borrow().chars().collect();
+    let len = index_of(&list[1])?;
+    let fill = list.get(2).map_or(Ok(' '), char_of)?;
+    env.allocate(len.saturating_mul(fill.len_utf8()))?;
+    let padding = std::iter::repeat_n(fill, len.saturating_sub(chars.len()));
+    let text: String = if LEFT {
+        let kept = &chars[chars.len().saturating_sub(len)..];
+        padding.chain(kept.iter().copied()).collect()
+    } else {
+        let kept = &chars[..len.min(chars.len())];
+        kept.iter().copied().chain(padding).collect()
+    };
+    Ok(LispVal::string(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::test_util::show;
+
+    #[test]
+    fn strings_index_by_character() {
+        assert_eq!(show("(string-length \"héllo\")"), "5");
+        assert_eq!(show("(string-ref \"héllo\" 1)"), "é");
+        assert_eq!(show("(string-ref \"a😀b\" 2)"), "b");
+        assert_eq!(show("(substring \"héllo\" 1 3)"), "él");
+        assert_eq!(
+            show("(define s (make-string 3 #\\a)) (string-set! s 1 #\\λ) s"),
+            "aλa"
+        );
+        assert_eq!(show("(string #\\a #\\space #\\x41)"), "a A");
+        assert_eq!(show("(string-copy \"héllo\" 2)"), "llo");
+        assert_eq!(
+            show("(define s (string-copy \"abcde\")) (string-copy! s 1 s 0 3) s"),
+            "aabce"
+        );
+        assert_eq!(
+            show("(define s (string-copy \"abcde\")) (string-fill! s #\\é 3) s"),
+            "abcéé"
+        );
+        assert_eq!(show("(string->list \"abc\" 1)"), "(b c)");
+        assert_eq!(show("(list->string (list #\\a #\\b))"), "ab");
+        assert_eq!(show("(string-append \"ab\" \"\" \"cd\")"), "abcd");
+        // a string is one object, changes to it show through every reference
+        assert_eq!(
+            show("(define a (string #\\a)) (define b a) (string-set! b 0 #\\z) a"),
+            "z"
+        );
+        assert!(show("(string-ref \"abc\" 3)").starts_with("error: wrong type"));
+        assert!(show("(substring \"abc\" 2 1)").starts_with("error: wrong type"));
+        assert!(show("(string-copy! (make-string 2) 1 \"ab\")").starts_with("error: wrong type"));
+        // the length is charged before the string is made, so a huge one is an error
+        for input in [
+            "(make-string 100000000000 #\\a)",
+            "(string-pad \"a\" 100000000000)",
+        ] {
+            assert!(show(input).starts_with("error: allocation too large"));
+        }
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(show(r#""say \"hi\"""#), r#"say "hi""#);
+        assert_eq!(show(r#""a\\b""#), r"a\b");
+        assert_eq!(show(r#"(string->list "a\tb\n")"#), "(a \t b \n)");
+        assert_eq!(show(r#"(string-length "\a\b\r\|")"#), "4");
+        assert_eq!(show(r#""\x41;\x3bb;\x1F600;""#), "Aλ😀");
+        assert_eq!(show("\"one \\  \n    two\""), "one two");
+        assert_eq!(show("\"one\\\r\n two\""), "onetwo");
+        // bad escapes are errors when the string is read
+        for input in [
+            r#""\q""#,
+            r#""\x41""#,
+            r#""\x;""#,
+            r#""\xD800;""#,
+            "\"a\\ b\"",
+        ] {
+            let mut lex = Lexer::init_lex(input.to_owned());
+            assert!(lex.next_token().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn string_case_and_comparison() {
+        assert_eq!(show("(string-upcase \"straße\")"), "STRASSE");
+        assert_eq!(show("(string-downcase \"ÀB\")"), "àb");
+        assert_eq!(show("(string-foldcase \"ABC\")"), "abc");
+        assert_eq!(show("(string-foldcase \"Straße\")"), "strasse");
+        assert_eq!(show("(string-foldcase \"ΣΑΣ ﬁ ᾈ\")"), "σασ fi ἀι");
+        assert_eq!(show("(string-length (string-foldcase \"İ\"))"), "2");
+        assert_eq!(show("(string=? \"a\" \"a\" \"a\")"), "#t");
+        assert_eq!(show("(string<? \"abc\" \"abd\" \"b\")"), "#t");
+        assert_eq!(show("(string<? \"b\" \"a\")"), "#f");
+        assert_eq!(show("(string>=? \"b\" \"b\" \"a\")"), "#t");
+        assert_eq!(show("(string-ci=? \"Hello\" \"hELLO\")"), "#t");
+        assert_eq!(show("(string-ci<? \"a\" \"B\")"), "#t");
+        assert_eq!(
+            show("(string-ci=? \"Straße\" \"STRASSE\" \"strasse\")"),
+            "#t"
+        );
+        assert_eq!(show("(string-ci=? \"ὈΔΥΣΣΕΎΣ\" \"ὀδυσσεύς\")"), "#t");
+        assert_eq!(show("(string=? \"Straße\" \"strasse\")"), "#f");
+        assert!(show("(string=? \"a\" 'a)").starts_with("error: wrong type"));
+    }
+
+    #[test]
+    fn string_higher_order_procedures() {
+        assert_eq!(show("(string-map (lambda (c) #\\x) \"abc\")"), "xxx");
+        assert_eq!(
+            show("(string-map (lambda (a b) b) \"adc\" \"bcde\")"),
+            "bcd"
+        );
+        assert_eq!(
+            show("(define n 0) (string-for-each (lambda (c) (set! n (+ n 1))) \"héllo\") n"),
+            "5"
+        );
+        assert_eq!(show("(string-index \"hello\" #\\l)"), "2");
+        assert_eq!(
+            show("(string-index \"héllo\" (lambda (c) (eqv? c #\\o)))"),
+            "4"
+        );
+        assert_eq!(show("(string-index \"hello\" #\\z)"), "#f");
+        assert_eq!(show("(string-contains \"héllo world\" \"wor\")"), "6");
+        assert_eq!(show("(string-contains \"hello\" \"z\")"), "#f");
+    }
+
+    #[test]
+    fn string_helpers() {
+        assert_eq!(show("(string-join '(\"a\" \"b\" \"c\"))"), "a b c");
+        assert_eq!(show("(string-join '(\"a\" \"b\") \", \")"), "a, b");
+        assert_eq!(show("(string-join '(\"a\" \"b\") \"/\" 'prefix)"), "/a/b");
+        assert_eq!(show("(string-join '(\"a\" \"b\") \";\" 'suffix)"), "a;b;");
+        assert!(show("(string-join '() \",\" 'strict-infix)").starts_with("error: wrong type"));
+        assert_eq!(show("(length (string-split \"a,b,,c\" #\\,))"), "4");
+        assert_eq!(show("(string-split \"a::b\" \"::\")"), "(a b)");
+        assert_eq!(show("(string-split \"ab\" \"\")"), "(a b)");
+        assert_eq!(show("(string-length (string-trim \"  a b  \"))"), "5");
+        assert_eq!(show("(string-trim-right \"  a b  \")"), "  a b");
+        assert_eq!(show("(string-trim-both \"xxaxx\" #\\x)"), "a");
+        assert_eq!(show("(string-pad \"42\" 5 #\\0)"), "00042");
+        assert_eq!(show("(string-pad \"12345\" 3)"), "345");
+        assert_eq!(show("(string-pad-right \"ab\" 4 #\\.)"), "ab..");
+        assert_eq!(show("(string-pad-right \"abcd\" 2)"), "ab");
+    }
+}
@@ -0,0 +1,231 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+
+use crate::error::EvalError;
+use crate::eval::{fixed_args, Environment};
+use crate::token::{Builtin, LispVal};
+
+// Symbols with the same name share it, so comparing two of them is comparing two pointers. The
+// name is freed with the last symbol that refers to it
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+thread_local! {
+    // Interned names by text. Values never leave the thread that made them, so neither do symbols
+    static TABLE: RefCell<HashMap<Box<str>, Weak<str>>> = RefCell::default();
+    static GENERATED: Cell<u64> = const { Cell::new(0) };
+}
+
+impl Symbol {
+    // The one symbol with this name
+    pub fn intern(name: &str) -> Symbol {
+        TABLE.with(|table| {
+            let mut table = table.borrow_mut();
+            if let Some(name) = table.get(name).and_then(Weak::upgrade) {
+                return Symbol(name);
+            }
+            let symbol: Rc<str> = Rc::from(name);
+            table.insert(name.into(), Rc::downgrade(&symbol));
+            Symbol(symbol)
+        })
+    }
+
+    // A new symbol that is not eq? to any other, even one with the same name. Its name is the
+    // prefix followed by a count of the symbols made this way. It is never put in the table
+    pub fn uninterned(prefix: &str) -> Symbol {
+        let count = GENERATED.with(|x| x.replace(x.get() + 1));
+        Symbol(Rc::from(format!("{}{}", prefix, count)))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+// The entry for an interned name goes with its last symbol. try_with because symbols held by
+// other thread locals can be dropped after the table is gone
+impl Drop for Symbol {
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.0) != 1 {
+            return;
+        }
+        let _ = TABLE.try_with(|table| {
+            let mut table = table.borrow_mut();
+            let ours = table
+                .get(&*self.0)
+                .is_some_and(|x| std::ptr::addr_eq(x.as_ptr(), Rc::as_ptr(&self.0)));
+            if ours {
+                table.remove(&*self.0);
+            }
+        });
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(Rc::as_ptr(&self.0) as *const u8, state)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Symbol({})", self.name())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+pub const SYMBOL_BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "symbol?",
+        func: eval_is_symbol,
+    },
+    Builtin {
+        name: "symbol->string",
+        func: eval_symbol_to_string,
+    },
+    Builtin {
+        name: "string->symbol",
+        func: eval_string_to_symbol,
+    },
+    Builtin {
+        name: "symbol=?",
+        func: eval_symbol_eq,
+    },
+    Builtin {
+        name: "gensym",
+        func: eval_gensym,
+    },
+    Builtin {
+        name: "generate-uninterned-symbol",
+        func: eval_gensym,
+    },
+];
+
+fn symbol_of(val: &LispVal) -> Result<&Symbol, EvalError> {
+    match val {
+        LispVal::Symbol(x) => Ok(x),
+        _ => Err(EvalError::wrong_type("a symbol", val)),
+    }
+}
+
+fn eval_is_symbol(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::Bool(matches!(x, LispVal::Symbol(_))))
+}
+
+fn eval_symbol_to_string(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    Ok(LispVal::string(symbol_of(&x)?.name().to_owned()))
+}
+
+fn eval_string_to_symbol(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let [x] = fixed_args(list)?;
+    match x {
+        LispVal::String(x) => {
+            env.allocate(x.borrow().len())?;
+            Ok(LispVal::Symbol(Symbol::intern(&x.borrow())))
+        }
+        _ => Err(EvalError::wrong_type("a string", &x)),
+    }
+}
+
+// (symbol=? a b c...) is true when every argument is the same symbol
+fn eval_symbol_eq(list: Vec<LispVal>, _env: &mut Environment) -> Result<LispVal, EvalError> {
+    if list.is_empty() {
+        return Err(EvalError::arity(1, None, 0));
+    }
+    let first = symbol_of(&list[0])?;
+    let mut same = true;
+    for x in &list[1..] {
+        same &= symbol_of(x)? == first;
+    }
+    Ok(LispVal::Bool(same))
+}
+
+// (gensym prefix) takes the prefix of the new name from a string or symbol, g when left out
+fn eval_gensym(list: Vec<LispVal>, env: &mut Environment) -> Result<LispVal, EvalError> {
+    let prefix = match list.as_slice() {
+        [] => "g".to_owned(),
+        [LispVal::String(x)] => x.borrow().clone(),
+        [LispVal::Symbol(x)] => x.name().to_owned(),
+        [x] => return Err(EvalError::wrong_type("a string or symbol", x)),
+        _ => return Err(EvalError::arity(0, Some(1), list.len())),
+    };
+    env.allocate(prefix.len())?;
+    Ok(LispVal::Symbol(Symbol::uninterned(&prefix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::show;
+
+    #[test]
+    fn symbols_are_interned() {
+        assert_eq!(Symbol::intern("abc"), Symbol::intern("abc"));
+        assert_ne!(Symbol::intern("abc"), Symbol::intern("abd"));
+        assert_eq!(Symbol::intern("abc").name(), "abc");
+        let fresh = Symbol::uninterned("abc");
+        assert_ne!(fresh, Symbol::intern(fresh.name()));
+    }
+
+    #[test]
+    fn unused_symbols_are_freed() {
+        let interned = || TABLE.with(|x| x.borrow().len());
+        // the builtin names stay interned once the first environment on this thread is made
+        show("0");
+        let before = interned();
+        let a = Symbol::intern("used-once");
+        assert_eq!(interned(), before + 1);
+        assert_eq!(a, Symbol::intern("used-once"));
+        drop(a);
+        assert_eq!(interned(), before);
+        let _fresh = Symbol::uninterned("used-once");
+        assert_eq!(interned(), before);
+        assert_eq!(
+            show("(gensym) (symbol->string (string->symbol \"made-at-run-time\"))"),
+            "made-at-run-time"
+        );
+        assert_eq!(interned(), before);
+        // names only held by a recursive procedure go with its environment
+        assert_eq!(
+            show("(define (count-down n) (if (= n 0) 'done (count-down (- n 1)))) (count-down 3)"),
+            "done"
+        );
+        assert_eq!(interned(), before);
+    }
+
+    #[test]
+    fn symbol_procedures() {
+        assert_eq!(show("(symbol? 'a)"), "#t");
+        assert_eq!(show("(symbol? \"a\")"), "#f");
+        assert_eq!(show("(symbol? 'if)"), "#t");
+        assert_eq!(show("(symbol->string 'abc)"), "abc");
+        assert_eq!(show("(symbol=? 'a (string->symbol \"a\") 'a)"), "#t");
+        assert_eq!(show("(symbol=? 'a 'b)"), "#f");
+        assert_eq!(show("(symbol? (gensym))"), "#t");
+        assert_eq!(show("(symbol=? (gensym) (gensym))"), "#f");
+        assert_eq!(
+            show("(define g (gensym \"tmp\")) (symbol=? g (string->symbol (symbol->string g)))"),
+            "#f"
+        );
+        assert!(show("(symbol->string (generate-uninterned-symbol 'loop))").starts_with("loop"));
+        assert!(show("(symbol=? 'a 1)").starts_with("error: wrong type"));
+    }
+}
@@ -0,0 +1,18 @@
+// Fixtures shared by the unit tests
+
+use crate::eval::{eval_prog, Environment};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+// Runs a program in a fresh environment and shows its last value, or the error it stopped with
+pub(crate) fn show(input: &str) -> String {
+    let mut lex = Lexer::init_lex(input.to_owned());
+    let mut par = Parser::init_parser(&mut lex).expect("parser should start");
+    let prog = par.parse_program().expect("program should parse");
+    match eval_prog(prog, &mut Environment::init_env()) {
+        Ok(mut x) => x.pop().map(|x| x.show_val()).unwrap_or_default(),
+        Err(e) => format!("error: {}", e),
+    }
+    .trim_end()
+    .to_owned()
+}
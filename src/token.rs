@@ -1,22 +1,72 @@
-#[derive(Clone, Debug, PartialEq)]
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::bigint::BigInt;
+use crate::error::EvalError;
+use crate::eval::{Environment, Scope};
+use crate::number::{show_float, Complex, Rational};
+use crate::random::RandomSource;
+use crate::symbol::Symbol;
+
+#[derive(Clone, Debug)]
 pub struct Token {
     pub literal: String,
     pub kind: TokenType,
+    pub loc: Location, // Where the token starts in the source
+}
+
+// Tokens compare by what they say, not by where they were read
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.literal == other.literal && self.kind == other.kind
+    }
 }
 
+// Identifier in code. Its name is interned once when it is read, so looking it up in an
+// environment never has to hash the text
+#[derive(Clone, Debug)]
+pub struct Ident {
+    pub name: Symbol,
+    pub kind: TokenType,
+    pub loc: Location,
+}
+
+impl PartialEq for Ident {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.kind == other.kind
+    }
+}
+
+// Line and column are counted from 1, a default location of line 0 means unknown
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TokenType {
     ILLEGAL,
     EOF,
     IDENT,
     STRING,
+    CHAR, // #\a, the literal is what follows the backslash
     INT,
     DOT,
     POUND,
     // ASSIGN,
     PLUS,
     MINUS,
-    COMMENT, // Single line comment: --comment
+    COMMENT, // Single line comment: --comment or ;comment
     COMMA,
     // BANG,
     ASTERICK,
@@ -33,41 +83,158 @@ pub enum TokenType {
     TRUE,  // #t
     FALSE, // #f
     IF,
-    ELSE, // might be implicit in Scheme (it exists in scheme)
+    ELSE,  // might be implicit in Scheme (it exists in scheme)
+    ARROW, // => used by cond and case clauses to pass the tested value to a procedure
+    COND,
+    CASE,
+    WHEN,
+    UNLESS,
     // Beginning of list
     LPAREN,
     RPAREN,
     AT,
-    // Used for multiline comments: {- Comment in here -}
-    LBRACK,
-    RBRACK,
     BEGIN, // evaluates a series of one or more S-Expressions in order
     DEFINE,
+    SET, // set! assigns to an existing variable
     LET, // Takes two arguments first is paired list of variables and values which are
     // scoped to evaluation of the second argument
-    LAMBDA, // Anonymous function
-    QUOTE,  // Delays the evaluation of its arguments
+    LETSTAR,       // let* binds sequentially
+    LETREC,        // letrec binds recursively
+    LETRECSTAR,    // letrec* binds recursively and sequentially
+    LETVALUES,     // let-values binds multiple return values
+    LETSTARVALUES, // let*-values binds multiple return values sequentially
+    LAMBDA,        // Anonymous function
+    CASELAMBDA,    // Anonymous function that dispatches on the number of arguments
+    GUARD,         // Catches objects raised while evaluating its body
+    QUOTE,         // Delays the evaluation of its arguments
+}
+
+pub type BuiltinFn = fn(Vec<LispVal>, &mut Environment) -> Result<LispVal, EvalError>;
+
+// Procedure implemented in Rust and bound in the global environment
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: BuiltinFn,
+}
+
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Builtin({})", self.name)
+    }
+}
+
+// Closure created by evaluating a lambda expression. It keeps the scope it was created in
+#[derive(Clone, Debug)]
+pub struct Lambda {
+    pub name: Option<String>, // Name it was defined with, shown in backtraces
+    pub params: Vec<Symbol>,
+    pub rest: Option<Symbol>, // Receives the extra arguments as a list
+    pub body: Vec<LispVal>,
+    pub env: Scope,
+}
+
+// Mutable cons cell. A list is a chain of pairs linked through their cdrs and ending in Nil
+#[derive(Debug)]
+pub struct Pair {
+    pub car: RefCell<LispVal>,
+    pub cdr: RefCell<LispVal>,
+}
+
+// Dropping a long list would otherwise recurse once per pair. The tail is unlinked a pair at a
+// time for as long as nothing else holds on to it
+impl Drop for Pair {
+    fn drop(&mut self) {
+        let mut next = self.cdr.replace(LispVal::Nil);
+        while let LispVal::Pair(pair) = next {
+            match Rc::try_unwrap(pair) {
+                Ok(pair) => next = pair.cdr.replace(LispVal::Nil),
+                Err(_) => break,
+            }
+        }
+    }
 }
 
-// AST for SCHEME
-#[derive(Clone, PartialEq, Debug)]
+// AST for SCHEME. The reader gives code as List and DottedList, data made at run time are pairs
+#[derive(Clone, Debug)]
 pub enum LispVal {
-    Atom(Token),
+    Atom(Ident),    // Identifier in code
+    Symbol(Symbol), // Symbol as a value, made by quoting an identifier
     List(Vec<LispVal>),
     DottedList(Vec<LispVal>, Box<LispVal>),
     Number(i64),
+    BigInt(Rc<BigInt>), // Exact integer too large for Number
+    Rational(Rc<Rational>),
+    Complex(Complex),
     Float(f64),
-    String(String),
-    Fun(Vec<LispVal>, Vec<LispVal>),
-    Lamda(Box<LispVal>, Box<LispVal>),
-    Nil,
+    String(Rc<RefCell<String>>), // Shared, so string-set! shows through every reference
+    Char(char),
+    Fun(Builtin),
+    Lamda(Rc<Lambda>),
+    CaseLamda(Rc<Vec<Rc<Lambda>>>),
+    Pair(Rc<Pair>),
+    Nil, // The empty list ()
     Bool(bool),
+    Void,                 // Unspecified value returned by forms such as a one armed if
+    Values(Vec<LispVal>), // Multiple return values from (values 1 2)
+    Undefined,            // Placeholder for letrec names whose init has not been evaluated yet
+    Error(Rc<EvalError>), // Error object handed to exception handlers
+    RandomSource(Rc<RandomSource>),
+}
+
+// Pairs, strings and procedures are compared by identity like eqv?, everything else by value.
+// Comparing the contents of pairs would never finish on a circular list
+impl PartialEq for LispVal {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LispVal::Atom(x), LispVal::Atom(y)) => x == y,
+            (LispVal::Symbol(x), LispVal::Symbol(y)) => x == y,
+            (LispVal::List(x), LispVal::List(y)) => x == y,
+            (LispVal::DottedList(x, a), LispVal::DottedList(y, b)) => x == y && a == b,
+            (LispVal::Number(x), LispVal::Number(y)) => x == y,
+            (LispVal::BigInt(x), LispVal::BigInt(y)) => x == y,
+            (LispVal::Rational(x), LispVal::Rational(y)) => x == y,
+            (LispVal::Complex(x), LispVal::Complex(y)) => x == y,
+            (LispVal::Float(x), LispVal::Float(y)) => x == y,
+            (LispVal::String(x), LispVal::String(y)) => Rc::ptr_eq(x, y),
+            (LispVal::Char(x), LispVal::Char(y)) => x == y,
+            (LispVal::Fun(x), LispVal::Fun(y)) => x == y,
+            (LispVal::Lamda(x), LispVal::Lamda(y)) => Rc::ptr_eq(x, y),
+            (LispVal::CaseLamda(x), LispVal::CaseLamda(y)) => Rc::ptr_eq(x, y),
+            (LispVal::Pair(x), LispVal::Pair(y)) => Rc::ptr_eq(x, y),
+            (LispVal::Bool(x), LispVal::Bool(y)) => x == y,
+            (LispVal::Values(x), LispVal::Values(y)) => x == y,
+            (LispVal::Error(x), LispVal::Error(y)) => x == y,
+            (LispVal::RandomSource(x), LispVal::RandomSource(y)) => x == y,
+            (LispVal::Nil, LispVal::Nil)
+            | (LispVal::Void, LispVal::Void)
+            | (LispVal::Undefined, LispVal::Undefined) => true,
+            _ => false,
+        }
+    }
 }
 
 impl LispVal {
+    pub fn cons(car: LispVal, cdr: LispVal) -> LispVal {
+        LispVal::Pair(Rc::new(Pair {
+            car: RefCell::new(car),
+            cdr: RefCell::new(cdr),
+        }))
+    }
+
+    pub fn string(text: String) -> LispVal {
+        LispVal::String(Rc::new(RefCell::new(text)))
+    }
+
     pub fn show_val(&self) -> String {
         match self {
-            LispVal::Atom(x) => format!("{} ", x.literal),
+            LispVal::Atom(x) => format!("{} ", x.name),
             LispVal::List(x) => {
                 let mut st: String = String::from("(");
                 for str in x {
@@ -84,36 +251,130 @@ impl LispVal {
                     st.push_str(&str.show_val());
                     // st.push_str(" ");
                 }
+                st.push_str(". ");
                 st.push_str(y.show_val().as_str());
-                st.push_str(")");
+                st.push(')');
                 st
             }
+            LispVal::Symbol(x) => format!("{} ", x),
+            LispVal::Pair(_) => show_labeled(self, &mut cycle_labels(self)),
             LispVal::Number(x) => {
                 let mut st = x.to_string();
-                st.push_str(" ");
-                st
-            }
-            LispVal::Float(x) => {
-                let mut st = x.to_string();
-                st.push_str(" ");
+                st.push(' ');
                 st
             }
+            LispVal::BigInt(x) => format!("{} ", x),
+            LispVal::Rational(x) => format!("{} ", x),
+            LispVal::Complex(x) => format!("{} ", x),
+            LispVal::Float(x) => format!("{} ", show_float(*x)),
             LispVal::String(x) => {
                 let mut st: String = String::from("");
-                st.push_str(x.as_str());
-                st.push_str(" ");
+                st.push_str(&x.borrow());
+                st.push(' ');
                 st
             }
-            LispVal::Fun(_, _) => "(internal function)".to_owned(),
-            LispVal::Lamda(_, _) => "(lambda function)".to_owned(),
-            LispVal::Nil => "Nil".to_owned(),
+            LispVal::Char(x) => format!("{} ", x),
+            LispVal::Fun(x) => format!("(internal function {}) ", x.name),
+            LispVal::Lamda(_) | LispVal::CaseLamda(_) => "(lambda function) ".to_owned(),
+            LispVal::Nil => "() ".to_owned(),
             LispVal::Bool(x) => {
-                if *x == false {
-                    "#f ".to_owned()
-                } else {
+                if *x {
                     "#t ".to_owned()
+                } else {
+                    "#f ".to_owned()
                 }
             }
+            LispVal::Void | LispVal::Undefined => "".to_owned(),
+            LispVal::Values(x) => x.iter().map(|val| val.show_val()).collect(),
+            LispVal::Error(x) => format!("(error object {}) ", x.kind),
+            LispVal::RandomSource(_) => "(random source) ".to_owned(),
+        }
+    }
+}
+
+// Pairs that lie on a cycle, with the datum label each gets once it has been written
+#[derive(Default)]
+struct Labels {
+    pairs: HashMap<*const Pair, Option<usize>>,
+    next: usize,
+}
+
+fn cycle_labels(val: &LispVal) -> Labels {
+    let mut labels = Labels::default();
+    find_cycles(val, &mut HashSet::new(), &mut HashSet::new(), &mut labels);
+    labels
+}
+
+// Depth first search through the pairs reachable from val. Meeting a pair that is still on the
+// path being searched closes a cycle through it
+fn find_cycles(
+    val: &LispVal,
+    path: &mut HashSet<*const Pair>,
+    done: &mut HashSet<*const Pair>,
+    labels: &mut Labels,
+) {
+    let mut chain = Vec::<*const Pair>::new();
+    let mut next = val.clone();
+    while let LispVal::Pair(pair) = next {
+        let ptr = Rc::as_ptr(&pair);
+        if path.contains(&ptr) {
+            labels.pairs.insert(ptr, None);
+            break;
+        }
+        if done.contains(&ptr) {
+            break;
         }
+        path.insert(ptr);
+        chain.push(ptr);
+        find_cycles(&pair.car.borrow(), path, done, labels);
+        next = pair.cdr.borrow().clone();
+    }
+    for ptr in chain {
+        path.remove(&ptr);
+        done.insert(ptr);
+    }
+}
+
+// Writes a pair on a cycle as #n=(...) the first time and as #n# after that, so circular lists
+// print in finite space
+fn show_labeled(val: &LispVal, labels: &mut Labels) -> String {
+    let pair = match val {
+        LispVal::Pair(x) => x,
+        _ => return val.show_val(),
+    };
+    match labels.pairs.get_mut(&Rc::as_ptr(pair)) {
+        Some(Some(label)) => format!("#{}# ", label),
+        Some(label) => {
+            let n = labels.next;
+            *label = Some(n);
+            labels.next += 1;
+            format!("#{}={}", n, show_pair(pair, labels))
+        }
+        None => show_pair(pair, labels),
+    }
+}
+
+fn show_pair(pair: &Rc<Pair>, labels: &mut Labels) -> String {
+    let mut st = String::from("(");
+    st.push_str(&show_labeled(&pair.car.borrow(), labels));
+    let mut rest = pair.cdr.borrow().clone();
+    loop {
+        let next = match &rest {
+            // a labeled pair in the tail has to be written as a dotted tail to carry its label
+            LispVal::Pair(y) if !labels.pairs.contains_key(&Rc::as_ptr(y)) => {
+                st.push_str(&show_labeled(&y.car.borrow(), labels));
+                y.cdr.borrow().clone()
+            }
+            LispVal::Nil => break,
+            y => {
+                st.push_str(". ");
+                st.push_str(&show_labeled(y, labels));
+                break;
+            }
+        };
+        rest = next;
     }
+    let _ = st.pop();
+    st.push_str(") ");
+    st
 }
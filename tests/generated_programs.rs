@@ -0,0 +1,175 @@
+// Random programs built from the interpreter's own vocabulary. Whatever they do, reading and
+// evaluating them has to end in a value or an error, never a panic
+use scheme_lang::eval::{eval_prog, Environment};
+use scheme_lang::lexer::Lexer;
+use scheme_lang::parser::Parser;
+
+// xorshift64*, so every run generates the same programs
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, words: &[&'a str]) -> &'a str {
+        words[self.below(words.len())]
+    }
+}
+
+const WORDS: &[&str] = &[
+    "define",
+    "lambda",
+    "let",
+    "let*",
+    "letrec",
+    "letrec*",
+    "let-values",
+    "let*-values",
+    "case-lambda",
+    "if",
+    "cond",
+    "case",
+    "when",
+    "unless",
+    "quote",
+    "set!",
+    "begin",
+    "else",
+    "=>",
+    "and",
+    "or",
+    "not",
+    "+",
+    "-",
+    "*",
+    "/",
+    "<",
+    "<=",
+    "=",
+    ">",
+    ">=",
+    "exact",
+    "inexact",
+    "modulo",
+    "floor/",
+    "round",
+    "sqrt",
+    "magnitude",
+    "random-integer",
+    "number->string",
+    "values",
+    "call-with-values",
+    "cons",
+    "car",
+    "cdr",
+    "list",
+    "append",
+    "reverse",
+    "length",
+    "memv",
+    "assoc",
+    "set-cdr!",
+    "eqv?",
+    "equal?",
+    "string->symbol",
+    "symbol->string",
+    "string-append",
+    "string-length",
+    "x",
+    "y",
+    "f",
+    "g",
+    "nothing",
+];
+
+const LITERALS: &[&str] = &[
+    "0",
+    "1",
+    "-1",
+    "42",
+    "1.5",
+    "-0.0",
+    "1/3",
+    "1+2i",
+    "-i",
+    "+nan.0",
+    "9223372036854775807",
+    "-9223372036854775808",
+    "123456789012345678901234567890",
+    "#t",
+    "#f",
+    "\"s\"",
+    "\"\"",
+    "()",
+];
+
+fn gen_expr(rng: &mut Rng, depth: usize, out: &mut String) {
+    match rng.below(if depth == 0 { 2 } else { 6 }) {
+        0 => out.push_str(rng.pick(WORDS)),
+        1 => out.push_str(rng.pick(LITERALS)),
+        2 => {
+            out.push('\'');
+            gen_expr(rng, depth - 1, out);
+        }
+        _ => {
+            out.push('(');
+            // most lists are calls or special forms, with a known name at the head
+            if rng.below(3) > 0 {
+                out.push_str(rng.pick(WORDS));
+            }
+            for i in 0..rng.below(5) {
+                if i > 0 && rng.below(12) == 0 {
+                    out.push_str(" .");
+                }
+                out.push(' ');
+                gen_expr(rng, depth - 1, out);
+            }
+            out.push(')');
+        }
+    }
+}
+
+fn run(input: &str) {
+    let mut lex = Lexer::init_lex(input.to_owned());
+    let prog = Parser::init_parser(&mut lex).and_then(|mut x| x.parse_program());
+    if let Ok(prog) = prog {
+        // like the REPL, an error in one expression does not stop the ones after it
+        let mut env = Environment::init_env();
+        for expr in prog {
+            let _ = eval_prog(vec![expr], &mut env);
+        }
+    }
+}
+
+#[test]
+fn generated_programs_do_not_panic() {
+    let mut rng = Rng(0x5eed_1234_abcd_0001);
+    let prelude = "(define x 1) (define y \"a\") (define (f a) a) (define g (lambda args args)) ";
+    for _ in 0..20000 {
+        let mut program = prelude.to_owned();
+        for _ in 0..3 {
+            gen_expr(&mut rng, 3, &mut program);
+            program.push(' ');
+        }
+        run(&program);
+    }
+}
+
+#[test]
+fn random_text_does_not_panic() {
+    let mut rng = Rng(0x0dd_ba11_cafe_f00d);
+    let chars: Vec<char> = "()'.#;\"{}-+*/<=> \n\tabxtf0129λ".chars().collect();
+    for _ in 0..5000 {
+        let len = rng.below(40);
+        let text: String = (0..len).map(|_| chars[rng.below(chars.len())]).collect();
+        run(&text);
+    }
+}